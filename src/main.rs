@@ -1,338 +1,11767 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs::create_dir_all;
 use std::fs::read_dir;
 use std::fs::remove_file;
 use std::fs::File;
+use std::io;
 use std::io::stdin;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::exit;
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 /// Represents all settings the user can set
+#[derive(Clone)]
 struct Config {
     /// Where everything will be stored locally
     root_dir: PathBuf,
+    /// Whether creating a project also creates and opens a starter note in it
+    project_creates_note: bool,
+    /// Actions offered in the menu, empty meaning all of them
+    enabled_actions: Vec<Action>,
+    /// Action run straight away instead of showing the menu, if any
+    default_action: Option<Action>,
+    /// How new notes are named
+    naming_scheme: NamingScheme,
+    /// How notes are shown in listings
+    display: DisplayMode,
+    /// Heading that links are added under, or None to add them at the end
+    link_section: Option<String>,
+    /// Whether to offer deleting a new, still empty note if the editor fails
+    cleanup_empty_on_editor_abort: bool,
+    /// Whether deleted notes are moved to the trash instead of removed
+    use_trash: bool,
+    /// If set, trashed notes older than this many days are purged at startup
+    trash_retention_days: Option<u64>,
+    /// The order notes are listed in
+    sort: Sort,
+    /// The commands used to open notes, the first one available being used
+    editor: Vec<String>,
+    /// Editors to use instead of `editor` for files with a given extension
+    editor_by_extension: HashMap<String, String>,
+    /// Whether the editor can be passed several files at once
+    editor_multi_file: bool,
+    /// Whether the editor only returns once the note is closed
+    editor_blocks: bool,
+    /// Directory under the root that scratch notes are created in
+    scratch_dir: PathBuf,
+    /// Longest file or directory name, in bytes, clife will try to create, at
+    /// least `MIN_NAME_LEN`
+    max_name_len: usize,
+    /// Directory under the root that notes are captured into for processing
+    inbox_dir: PathBuf,
+    /// Column template text is wrapped at when seeding a note, if any
+    wrap_column: Option<usize>,
+    /// Text placed between notes when they are merged
+    merge_separator: String,
+    /// Heading placed above each merged note, with `{{path}}` and `{{title}}`
+    merge_header_template: String,
+    /// Whether renaming a note updates the `title:` in its frontmatter
+    sync_title_on_rename: bool,
+    /// File extensions notes are expected to have
+    note_extensions: Vec<String>,
+    /// Whether moves and renames replace existing files without asking
+    force_overwrite: bool,
+    /// Shown instead of the usual welcome message when the menu starts
+    banner: Option<String>,
+    /// Put in front of every question clife asks
+    prompt_prefix: Option<String>,
+    /// Whether a deleted note's contents are copied into the audit log
+    log_deleted_content: bool,
+    /// Most bytes of a deleted note's contents copied into the audit log
+    log_deleted_content_max: usize,
+    /// Whether notes are converted to LF line endings after editing
+    normalize_newlines: bool,
+    /// What to do when the root dir doesn't exist
+    create_root: CreateRoot,
+    /// What to do when a new note's name is already taken
+    collision_strategy: CollisionStrategy,
+    /// Whether whitespace around project and note names is trimmed off, rather
+    /// than making the name invalid
+    trim_names: bool,
+    /// Whether searches tell upper and lower case apart
+    search_case_sensitive: bool,
+    /// Whether new notes start with an `# H1` title made from their file name
+    seed_h1_title: bool,
+    /// Whether moving a note to the trash asks for confirmation first
+    confirm_trash: bool,
+    /// Whether directories holding another vault are scanned for notes too
+    include_nested: bool,
+    /// More directories of notes to show alongside the root's, read-only
+    extra_roots: Vec<PathBuf>,
+    /// Whether to explain things that are normally done quietly
+    verbose: bool,
+    /// Whether the menu offers the previously chosen action as its default
+    remember_last_action: bool,
+    /// How many notes the picker lists before asking for a filter first, 0
+    /// for no limit
+    list_cap: usize,
+    /// The most words a note should have, flagged by lint when exceeded
+    word_budget: Option<usize>,
+    /// Whether new notes get a `project:` field naming their project
+    sync_project_on_create: bool,
+    /// The `project:` given to notes at the root, or empty to remove the field
+    root_project: String,
+    /// How long a prompt waits for an answer before clife gives up, if set
+    prompt_timeout_secs: Option<u64>,
+    /// Whether the menu points out projects without any notes and offers to
+    /// remove them
+    report_empty_projects: bool,
+    /// A shell command run with the path of each note created
+    post_create_hook: Option<String>,
+    /// A shell command run with the path of each note deleted or trashed
+    post_delete_hook: Option<String>,
+    /// Whether notes marked `locked: true` may be changed anyway, set by
+    /// `--force`
+    override_locks: bool,
+    /// Whether unknown keys in config files are only warned about, set by
+    /// `--lenient-config`
+    lenient_config: bool,
+    /// The config file the settings were loaded from, if there was one
+    config_file: Option<PathBuf>,
+    /// The vault's config file, if there was one
+    local_config_file: Option<PathBuf>,
+    /// Where each overridden setting got its value, latest last
+    sources: Vec<(String, ConfigSource)>,
 }
 
-/// Represents a single note files
-#[derive(Debug)]
-struct Note {
-    full_path: PathBuf,
-    trunc_path: PathBuf,
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            root_dir: default_root_dir(|name| std::env::var(name).ok(), &home_dir()),
+            project_creates_note: false,
+            enabled_actions: Vec::new(),
+            default_action: None,
+            naming_scheme: NamingScheme::Incremental,
+            display: DisplayMode::Path,
+            link_section: None,
+            cleanup_empty_on_editor_abort: true,
+            use_trash: true,
+            trash_retention_days: None,
+            sort: Sort::NameAsc,
+            editor: vec![String::from("nvim")],
+            editor_multi_file: true,
+            editor_blocks: true,
+            editor_by_extension: HashMap::new(),
+            scratch_dir: PathBuf::from("scratch"),
+            max_name_len: 200,
+            inbox_dir: PathBuf::from("inbox"),
+            wrap_column: None,
+            merge_separator: String::from("\n\n---\n\n"),
+            merge_header_template: String::from("## {{path}}"),
+            sync_title_on_rename: true,
+            note_extensions: vec![String::from("md")],
+            force_overwrite: false,
+            banner: None,
+            prompt_prefix: None,
+            log_deleted_content: false,
+            log_deleted_content_max: 64 * 1024,
+            normalize_newlines: false,
+            create_root: CreateRoot::Prompt,
+            collision_strategy: CollisionStrategy::Increment,
+            trim_names: true,
+            search_case_sensitive: false,
+            seed_h1_title: false,
+            confirm_trash: true,
+            include_nested: false,
+            extra_roots: Vec::new(),
+            verbose: false,
+            remember_last_action: false,
+            list_cap: 100,
+            word_budget: None,
+            sync_project_on_create: false,
+            root_project: String::from("(root)"),
+            prompt_timeout_secs: None,
+            report_empty_projects: true,
+            post_create_hook: None,
+            post_delete_hook: None,
+            override_locks: false,
+            lenient_config: false,
+            config_file: None,
+            local_config_file: None,
+            sources: Vec::new(),
+        }
+    }
 }
 
-#[derive(Debug)]
-enum Action {
-    CreateNote,
-    Delete,
-    CreateProject,
-}
+/// Sets one setting from its config file value
+type Setter = fn(&mut Config, ConfigValue, &str) -> Result<(), AppError>;
 
-/// Returns if the root dir exists already
+/// Every key the config file can set, besides `editor_by_extension.<ext>`,
+/// with how to set it
+const CONFIG_SETTINGS: &[(&str, Setter)] = &[
+    ("root_dir", |config, value, key| {
+        config.root_dir = PathBuf::from(expand_path(&value.into_string(key)?));
+        Ok(())
+    }),
+    ("project_creates_note", |config, value, key| {
+        config.project_creates_note = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("enabled_actions", |config, value, key| {
+        let mut actions = Vec::new();
+        for name in value.into_list(key)? {
+            let action = name
+                .parse()
+                .map_err(|e| AppError::Config(format!("{} in enabled_actions", e)))?;
+            actions.push(action);
+        }
+        config.enabled_actions = actions;
+        Ok(())
+    }),
+    ("default_action", |config, value, key| {
+        config.default_action = Some(
+            value
+                .into_string(key)?
+                .parse()
+                .map_err(|e| AppError::Config(format!("{} in default_action", e)))?,
+        );
+        Ok(())
+    }),
+    ("naming_scheme", |config, value, key| {
+        let name = value.into_string(key)?;
+        config.naming_scheme = NamingScheme::from_name(&name)
+            .ok_or_else(|| AppError::Config(format!("unknown naming_scheme '{}'", name)))?;
+        Ok(())
+    }),
+    ("display", |config, value, key| {
+        let name = value.into_string(key)?;
+        config.display = DisplayMode::from_name(&name)
+            .ok_or_else(|| AppError::Config(format!("unknown display '{}'", name)))?;
+        Ok(())
+    }),
+    ("link_section", |config, value, key| {
+        config.link_section = Some(value.into_string(key)?);
+        Ok(())
+    }),
+    ("cleanup_empty_on_editor_abort", |config, value, key| {
+        config.cleanup_empty_on_editor_abort = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("use_trash", |config, value, key| {
+        config.use_trash = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("trash_retention_days", |config, value, key| {
+        config.trash_retention_days = Some(value.into_integer(key)?);
+        Ok(())
+    }),
+    ("sort", |config, value, key| {
+        let name = value.into_string(key)?;
+        config.sort = Sort::from_name(&name)
+            .ok_or_else(|| AppError::Config(format!("unknown sort '{}'", name)))?;
+        Ok(())
+    }),
+    ("editor", |config, value, key| {
+        config.editor = value.into_string_or_list(key)?;
+        Ok(())
+    }),
+    ("editor_multi_file", |config, value, key| {
+        config.editor_multi_file = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("editor_blocks", |config, value, key| {
+        config.editor_blocks = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("scratch_dir", |config, value, key| {
+        config.scratch_dir = PathBuf::from(value.into_string(key)?);
+        Ok(())
+    }),
+    ("max_name_len", |config, value, key| {
+        config.max_name_len = usize::try_from(value.into_integer(key)?)
+            .ok()
+            .filter(|len| *len >= MIN_NAME_LEN)
+            .ok_or_else(|| {
+                AppError::Config(format!("'{}' must be at least {}", key, MIN_NAME_LEN))
+            })?;
+        Ok(())
+    }),
+    ("inbox_dir", |config, value, key| {
+        config.inbox_dir = PathBuf::from(value.into_string(key)?);
+        Ok(())
+    }),
+    ("wrap_column", |config, value, key| {
+        config.wrap_column = Some(value.into_integer(key)? as usize);
+        Ok(())
+    }),
+    ("merge_separator", |config, value, key| {
+        config.merge_separator = value.into_string(key)?;
+        Ok(())
+    }),
+    ("merge_header_template", |config, value, key| {
+        config.merge_header_template = value.into_string(key)?;
+        Ok(())
+    }),
+    ("sync_title_on_rename", |config, value, key| {
+        config.sync_title_on_rename = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("note_extensions", |config, value, key| {
+        config.note_extensions = value.into_list(key)?;
+        Ok(())
+    }),
+    ("extra_roots", |config, value, key| {
+        config.extra_roots = value
+            .into_list(key)?
+            .iter()
+            .map(|root| PathBuf::from(expand_path(root)))
+            .collect();
+        Ok(())
+    }),
+    ("banner", |config, value, key| {
+        config.banner = Some(value.into_string(key)?);
+        Ok(())
+    }),
+    ("prompt_prefix", |config, value, key| {
+        config.prompt_prefix = Some(value.into_string(key)?);
+        Ok(())
+    }),
+    ("log_deleted_content", |config, value, key| {
+        config.log_deleted_content = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("log_deleted_content_max", |config, value, key| {
+        config.log_deleted_content_max = value.into_integer(key)? as usize;
+        Ok(())
+    }),
+    ("normalize_newlines", |config, value, key| {
+        config.normalize_newlines = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("trim_names", |config, value, key| {
+        config.trim_names = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("search_case_sensitive", |config, value, key| {
+        config.search_case_sensitive = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("seed_h1_title", |config, value, key| {
+        config.seed_h1_title = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("confirm_trash", |config, value, key| {
+        config.confirm_trash = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("include_nested", |config, value, key| {
+        config.include_nested = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("verbose", |config, value, key| {
+        config.verbose = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("remember_last_action", |config, value, key| {
+        config.remember_last_action = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("list_cap", |config, value, key| {
+        config.list_cap = value.into_integer(key)? as usize;
+        Ok(())
+    }),
+    ("word_budget", |config, value, key| {
+        config.word_budget = Some(value.into_integer(key)? as usize);
+        Ok(())
+    }),
+    ("sync_project_on_create", |config, value, key| {
+        config.sync_project_on_create = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("root_project", |config, value, key| {
+        config.root_project = value.into_string(key)?;
+        Ok(())
+    }),
+    ("prompt_timeout_secs", |config, value, key| {
+        config.prompt_timeout_secs = Some(value.into_integer(key)?);
+        Ok(())
+    }),
+    ("report_empty_projects", |config, value, key| {
+        config.report_empty_projects = value.into_bool(key)?;
+        Ok(())
+    }),
+    ("post_create_hook", |config, value, key| {
+        config.post_create_hook = Some(value.into_string(key)?);
+        Ok(())
+    }),
+    ("post_delete_hook", |config, value, key| {
+        config.post_delete_hook = Some(value.into_string(key)?);
+        Ok(())
+    }),
+    ("create_root", |config, value, key| {
+        let name = value.into_string(key)?;
+        config.create_root = CreateRoot::from_name(&name)
+            .ok_or_else(|| AppError::Config(format!("unknown create_root '{}'", name)))?;
+        Ok(())
+    }),
+    ("collision_strategy", |config, value, key| {
+        let name = value.into_string(key)?;
+        config.collision_strategy = CollisionStrategy::from_name(&name)
+            .ok_or_else(|| AppError::Config(format!("unknown collision_strategy '{}'", name)))?;
+        Ok(())
+    }),
+];
+
+/// Counts the single character insertions, deletions and substitutions it
+/// takes to turn one string into another
 ///
 /// # Arguments
 ///
-/// * `config` - a reference to a config object
-fn detect_root_folder(config: &Config) -> bool {
-    let exists = config.root_dir.try_exists();
-    if exists.is_ok() {
-        return exists.unwrap();
-    } else {
-        panic!("Failed to parse root dir {}", config.root_dir.display());
+/// * `a` - the string to start from
+/// * `b` - the string to end up with
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
     }
+    previous[b.len()]
 }
 
-/// Creates a root folder to store things in
+/// Describes a config key clife doesn't know, suggesting the closest known
+/// one if there's one close enough to be a typo
 ///
 /// # Arguments
 ///
-/// * `config` - a reference to a config object
-fn create_root_folder(config: &Config) {
-    _ = create_dir_all(&config.root_dir);
-    println!("{} directory created!", config.root_dir.display());
+/// * `key` - the unknown key
+fn unknown_key_message(key: &str) -> String {
+    let (name, rest) = match key.split_once('.') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (key, None),
+    };
+    let suggestion = CONFIG_SETTINGS
+        .iter()
+        .map(|(known, _)| known)
+        .chain(rest.map(|_| &"editor_by_extension"))
+        .map(|known| (edit_distance(name, known), *known))
+        .filter(|(distance, _)| *distance <= 3)
+        .min();
+    match suggestion {
+        Some((_, "editor_by_extension")) => format!(
+            "unknown setting '{}', did you mean 'editor_by_extension.{}'?",
+            key,
+            rest.unwrap_or_default()
+        ),
+        Some((_, known)) => format!("unknown setting '{}', did you mean '{}'?", key, known),
+        None => format!("unknown setting '{}'", key),
+    }
 }
 
-/// Creates the core notes vector from the root directory
-///
-/// # Arguments
-///
-/// * `config` - a reference to a config object
-fn create_note_objects(config: &Config) -> Vec<Note> {
-    let mut notes: Vec<Note> = Vec::new();
-    _get_dir_notes(&config.root_dir, &mut notes, &config.root_dir);
-    return notes;
-}
+impl Config {
+    /// Overrides settings with those found in a config file
+    ///
+    /// Keys clife doesn't know about are an error, see `apply_partial`.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - the raw contents of the config file
+    #[cfg(test)]
+    fn apply_file(&mut self, contents: &str) -> Result<(), AppError> {
+        self.apply_partial(PartialConfig::parse(contents, ConfigSource::File)?)
+    }
 
-/// Creates notes from the base directory - recurses through directories
-///
-/// # Arguments
-///
-/// * `base` - a reference to the base directory to search
-/// * `notes` - The current state of a vector of notes to append to
-/// * `root_dir` - the overall root_dir of the run
-fn _get_dir_notes(base: &PathBuf, notes: &mut Vec<Note>, root_dir: &PathBuf) {
-    let contents = read_dir(base).unwrap();
-    for curr in contents {
-        let curr_file = curr.expect("Failed to read");
-        let curr_path = curr_file.path();
-        if curr_path.is_dir() {
-            _get_dir_notes(&curr_path, notes, root_dir);
-        } else {
-            let trunc_path = curr_path
-                .strip_prefix(root_dir.to_path_buf())
-                .unwrap()
-                .to_path_buf();
-            let curr_note = Note {
-                full_path: curr_path,
-                trunc_path,
-            };
-            notes.push(curr_note)
+    /// Overrides the settings present in a partial config, key by key
+    ///
+    /// Unknown keys are an error, or with `lenient_config` set just a warning.
+    ///
+    /// # Arguments
+    ///
+    /// * `partial` - the settings to override with
+    fn apply_partial(&mut self, partial: PartialConfig) -> Result<(), AppError> {
+        for (key, value) in partial.entries {
+            if let Some(extension) = key.strip_prefix("editor_by_extension.") {
+                let editor = value.into_string(&key)?;
+                self.editor_by_extension
+                    .insert(extension.to_string(), editor);
+            } else if let Some((_, set)) = CONFIG_SETTINGS.iter().find(|(name, _)| *name == key) {
+                set(self, value, &key)?;
+            } else {
+                let message = unknown_key_message(&key);
+                if !self.lenient_config {
+                    return Err(AppError::Config(message));
+                }
+                println!("Warning: {}, ignoring it", message);
+                continue;
+            }
+            self.sources.push((key, partial.source.clone()));
         }
+        Ok(())
     }
-}
 
-/// Prompts the user for the action they want to take
-fn prompt_for_action() -> Action {
-    let mut input = String::new();
-    while !["c", "d", "p"].contains(&input.trim()) {
-        input = String::new();
-        println!("\nWhat action would you like to take?");
-        println!("Options are ... \n\t - (c)reate note\n\t - (d)elete\n\t - create (p)roject");
-        stdin().read_line(&mut input).expect("Failed to read line");
-    }
-
-    if input.trim() == "c" {
-        return Action::CreateNote;
-    } else if input.trim() == "d" {
-        return Action::Delete;
-    } else if input.trim() == "p" {
-        return Action::CreateProject;
-    } else {
-        panic!("Unknown input");
+    /// Overrides settings with those set through environment variables
+    ///
+    /// `CLIFE_ROOT_DIR` sets the root dir and `CLIFE_EDITOR` the editor.
+    ///
+    /// # Arguments
+    ///
+    /// * `var` - looks up an environment variable by name
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) {
+        if let Some(root_dir) = var("CLIFE_ROOT_DIR") {
+            self.root_dir = PathBuf::from(expand_path(&root_dir));
+            self.set_source("root_dir", ConfigSource::Env("CLIFE_ROOT_DIR"));
+        }
+        if let Some(editor) = var("CLIFE_EDITOR") {
+            self.editor = vec![editor];
+            self.set_source("editor", ConfigSource::Env("CLIFE_EDITOR"));
+        }
+    }
+
+    /// Records where a setting's current value came from
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the setting's config key
+    /// * `source` - where its value came from
+    fn set_source(&mut self, key: &str, source: ConfigSource) {
+        self.sources.push((String::from(key), source));
+    }
+
+    /// Returns where a setting's current value came from
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the setting's config key
+    fn source(&self, key: &str) -> ConfigSource {
+        self.sources
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, source)| source.clone())
+            .unwrap_or(ConfigSource::Default)
+    }
+
+    /// Returns if the passed action should be offered to the user
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - the action to check
+    fn action_enabled(&self, action: Action) -> bool {
+        self.enabled_actions.is_empty() || self.enabled_actions.contains(&action)
     }
 }
 
-/// Creates a new note markdown file
-///
-/// # Arguments
-///
-/// * `config` - the config file that controls the run
-/// * `note_suffix` - the number of the note to start with as a suffix
-fn create_new_note(config: &Config, mut note_suffix: usize) -> PathBuf {
-    let mut note_created = false;
-    let mut note_path = PathBuf::from(&config.root_dir);
-    while !note_created {
-        note_path = PathBuf::from(&config.root_dir);
-        let mut note_name = String::from("new_note_");
-        note_name.push_str(&note_suffix.to_string());
-        note_name.push_str(".md");
-        note_path.push(&note_name);
-        if note_path.exists() {
-            println!("{} already exists, trying again ...", note_name);
-            note_suffix += 1;
-            continue;
+/// Where a setting's value came from
+#[derive(Debug, Clone, PartialEq)]
+enum ConfigSource {
+    /// Nothing overrode the built-in default
+    Default,
+    /// The config file
+    File,
+    /// The vault's own config file
+    LocalFile,
+    /// The named environment variable
+    Env(&'static str),
+    /// The named command line flag
+    Flag(&'static str),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File => write!(f, "file"),
+            ConfigSource::LocalFile => write!(f, "local file"),
+            ConfigSource::Env(name) => write!(f, "env {}", name),
+            ConfigSource::Flag(name) => write!(f, "flag {}", name),
         }
-        let _ = File::create(&note_path);
-        println!("New note created: {}", note_name);
-        note_created = true;
     }
-    return note_path;
 }
 
-/// Prompts the user for a note to take action on
-///
-/// # Arguments
-///
-/// * `notes` - a reference to the notes vector
-/// * `action` - an action to take, only used to prompt the user
-fn prompt_for_note(notes: &Vec<Note>, action: String) -> PathBuf {
-    let mut input = String::new();
-    let mut valid_input_passed: bool = false;
-    while !valid_input_passed {
-        input = String::new();
-        println!("\nWhat file would you like to {}?", action);
-        println!("Options are ... ");
-        for note in notes {
-            println!("- {:?}", note.trunc_path.as_os_str());
-        }
-        stdin().read_line(&mut input).expect("Failed to read line");
-        if notes
-            .iter()
-            .any(|e| e.trunc_path.to_str() == Some(&input.as_str().trim()))
-        {
-            valid_input_passed = true;
+/// Errors that stop clife from running
+#[derive(Debug)]
+enum AppError {
+    /// The config file is malformed or has a bad setting
+    Config(String),
+    /// The command line arguments couldn't be understood
+    Usage(String),
+    /// A name is taken and the collision strategy is to give up
+    Collision(String),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::Config(message) => write!(f, "config error: {}", message),
+            AppError::Usage(message) => write!(f, "usage error: {}", message),
+            AppError::Collision(name) => write!(f, "name collision: {} already exists", name),
         }
     }
+}
 
-    return PathBuf::from(input.trim());
+/// The settings set by one config file, not yet applied to a config
+#[derive(Debug)]
+struct PartialConfig {
+    /// Every setting in the file, in the order they appeared
+    entries: Vec<(String, ConfigValue)>,
+    /// Where the settings came from
+    source: ConfigSource,
 }
 
-/// Confirms with the user that they want a file to be deleted
-///
-/// # Arguments
-///
-/// * `path` - the potential file path to delete
-fn confirm_delete(path: &PathBuf) {
-    let mut input = String::new();
-    while !["n", "y"].contains(&input.trim()) {
-        input = String::new();
-        println!("\nAre you sure you want to delete {}?", path.display());
-        println!("Options are ... \n\t- (y)es\n\t- (n)o");
-        stdin().read_line(&mut input).expect("Failed to read line");
+impl PartialConfig {
+    /// Parses a config file's contents
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - the raw contents of the config file
+    /// * `source` - which file the contents came from
+    fn parse(contents: &str, source: ConfigSource) -> Result<PartialConfig, AppError> {
+        Ok(PartialConfig {
+            entries: parse_config_file(contents)?,
+            source,
+        })
     }
 
-    if &input.trim() == &"n" {
-        println!("Cancelling ...");
-        exit(0);
+    /// Reads and parses a config file, or returns None if there isn't one
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the config file to read
+    /// * `source` - which file it is
+    fn read(path: &Path, source: ConfigSource) -> Result<Option<PartialConfig>, AppError> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("couldn't read {}: {}", path.display(), e)))?;
+        PartialConfig::parse(&contents, source).map(Some)
     }
 }
 
-/// Deletes the passed PathBuf
-///
-/// # Arguments:
-///
-/// * `full_path` - the file path to delete
-fn delete(full_path: PathBuf) -> bool {
-    println!("Deleting note {} ...", full_path.display());
-    let result = remove_file(full_path);
-    match result {
-        Ok(()) => {
-            println!("File successfully deleted");
+/// A single value from the config file
+#[derive(Debug, PartialEq)]
+enum ConfigValue {
+    String(String),
+    Bool(bool),
+    Integer(u64),
+    List(Vec<String>),
+}
+
+impl ConfigValue {
+    /// Returns the value as a string, erroring if it is another type
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key the value belongs to, used in the error
+    fn into_string(self, key: &str) -> Result<String, AppError> {
+        match self {
+            ConfigValue::String(s) => Ok(s),
+            _ => Err(AppError::Config(format!("'{}' must be a string", key))),
         }
-        Err(e) => {
-            panic!("Failed to delete file: {:?}", e);
+    }
+
+    /// Returns the value as a bool, erroring if it is another type
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key the value belongs to, used in the error
+    fn into_bool(self, key: &str) -> Result<bool, AppError> {
+        match self {
+            ConfigValue::Bool(b) => Ok(b),
+            _ => Err(AppError::Config(format!("'{}' must be true or false", key))),
         }
     }
 
-    return true;
-}
+    /// Returns the value as a whole number, erroring if it is another type
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key the value belongs to, used in the error
+    fn into_integer(self, key: &str) -> Result<u64, AppError> {
+        match self {
+            ConfigValue::Integer(n) => Ok(n),
+            _ => Err(AppError::Config(format!(
+                "'{}' must be a whole number",
+                key
+            ))),
+        }
+    }
 
-/// Prompts the user for a valid project name
-fn prompt_for_project_name() -> String {
-    let mut input = String::new();
-    let mut valid_input = false;
-    while !valid_input {
-        input = String::new();
-        println!("\nWhat would you like to name this project?");
-        stdin().read_line(&mut input).expect("Failed to read line");
+    /// Returns the value as a list, erroring if it is another type
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key the value belongs to, used in the error
+    fn into_list(self, key: &str) -> Result<Vec<String>, AppError> {
+        match self {
+            ConfigValue::List(items) => Ok(items),
+            _ => Err(AppError::Config(format!("'{}' must be a list", key))),
+        }
+    }
 
-        // Ensure the input is a valid directory name
-        valid_input = validate_project_name(&input);
-        if !valid_input {
-            println!(
-                "Potential project name {} contains invalid characters",
-                input
-            );
-            println!("May only use alphanumerics, '_', and '.'");
+    /// Returns the value as a list, a single string being a one item list
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key the value belongs to, used in the error
+    fn into_string_or_list(self, key: &str) -> Result<Vec<String>, AppError> {
+        match self {
+            ConfigValue::String(s) => Ok(vec![s]),
+            ConfigValue::List(items) => Ok(items),
+            _ => Err(AppError::Config(format!(
+                "'{}' must be a string or a list",
+                key
+            ))),
         }
     }
-    return String::from(input.trim());
 }
 
-/// Ensures the passed project_name is a valid directory name
-///
-/// # Arguments 
-///
-/// * project_name - a reference to the project_name
-fn validate_project_name(project_name: &String) -> bool {
-    if project_name.trim().len() == 0 {
-        return false;
+/// The ways new notes can be named
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NamingScheme {
+    /// `new_note_N`, counting up
+    Incremental,
+    /// A `YYYYMMDDHHMM` Zettelkasten ID from the current (UTC) time
+    Timestamp,
+    /// A short random hex ID
+    UuidShort,
+}
+
+impl NamingScheme {
+    /// Looks up a naming scheme by the name used for it in the config file
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the scheme name, e.g. "timestamp"
+    fn from_name(name: &str) -> Option<NamingScheme> {
+        match name {
+            "incremental" => Some(NamingScheme::Incremental),
+            "timestamp" => Some(NamingScheme::Timestamp),
+            "uuid_short" => Some(NamingScheme::UuidShort),
+            _ => None,
+        }
     }
+}
 
-    // Ensure the input is a valid directory name
-    let valid_input = project_name
-        .trim()
-        .chars()
-        .all(|c| char::is_alphanumeric(c) || ['_', '.'].contains(&c));
-    return valid_input;
+/// What to do when the root dir doesn't exist yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CreateRoot {
+    /// Create it without asking
+    Always,
+    /// Stop with an error
+    Never,
+    /// Ask before creating it
+    Prompt,
 }
 
-fn main() {
-    println!("Welcome to clife!");
+impl CreateRoot {
+    /// Looks up a mode by the name used for it in the config file
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the mode name, e.g. "prompt"
+    fn from_name(name: &str) -> Option<CreateRoot> {
+        match name {
+            "always" => Some(CreateRoot::Always),
+            "never" => Some(CreateRoot::Never),
+            "prompt" => Some(CreateRoot::Prompt),
+            _ => None,
+        }
+    }
+}
 
-    let config = Config {
-        root_dir: PathBuf::from("/home/parker/.clife"),
-    };
+/// What to do when a new note's name is already taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CollisionStrategy {
+    /// Add or bump a numeric suffix
+    Increment,
+    /// Add the current date and time
+    Timestamp,
+    /// Give up with an error
+    Error,
+}
 
-    if !detect_root_folder(&config) {
-        println!("No clife folder detected at {}", config.root_dir.display());
-        create_root_folder(&config);
+impl CollisionStrategy {
+    /// Looks up a strategy by the name used for it in the config file
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the strategy name, e.g. "timestamp"
+    fn from_name(name: &str) -> Option<CollisionStrategy> {
+        match name {
+            "increment" => Some(CollisionStrategy::Increment),
+            "timestamp" => Some(CollisionStrategy::Timestamp),
+            "error" => Some(CollisionStrategy::Error),
+            _ => None,
+        }
     }
+}
 
-    let notes = create_note_objects(&config);
-    println!("Found {} notes", notes.len());
-
-    let action = prompt_for_action();
+/// How notes are shown in listings and the selection prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DisplayMode {
+    /// The note's path relative to the root
+    Path,
+    /// The note's frontmatter title
+    Title,
+    /// The title followed by the path
+    Both,
+}
 
-    match action {
-        Action::CreateNote => {
-            let note_path = create_new_note(&config, notes.len() + 1);
-            let _ = std::process::Command::new("nvim")
-                .arg(&note_path.into_os_string())
-                .status();
-        }
-        Action::Delete => {
-            let note_path = prompt_for_note(&notes, String::from("delete"));
-            confirm_delete(&note_path);
-            let mut full_path = config.root_dir.clone();
-            full_path.push(&note_path);
-            delete(full_path);
+impl DisplayMode {
+    /// Looks up a display mode by the name used for it in the config file
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the display mode name, e.g. "title"
+    fn from_name(name: &str) -> Option<DisplayMode> {
+        match name {
+            "path" => Some(DisplayMode::Path),
+            "title" => Some(DisplayMode::Title),
+            "both" => Some(DisplayMode::Both),
+            _ => None,
         }
-        Action::CreateProject => {
-            let project_name = prompt_for_project_name();
+    }
+}
+
+/// The orders notes can be listed in
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sort {
+    /// By path, A to Z
+    NameAsc,
+    /// By path, Z to A
+    NameDesc,
+    /// Most recently modified first
+    MtimeDesc,
+    /// Least recently modified first
+    MtimeAsc,
+    /// Largest first
+    SizeDesc,
+}
+
+impl Sort {
+    /// Looks up a sort order by the name used for it in the config file
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the sort name, e.g. "mtime_desc"
+    fn from_name(name: &str) -> Option<Sort> {
+        match name {
+            "name_asc" => Some(Sort::NameAsc),
+            "name_desc" => Some(Sort::NameDesc),
+            "mtime_desc" => Some(Sort::MtimeDesc),
+            "mtime_asc" => Some(Sort::MtimeAsc),
+            "size_desc" => Some(Sort::SizeDesc),
+            _ => None,
         }
-        _ => {
-            println!("Unknown action")
+    }
+}
+
+/// A calendar date and time of day in UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+}
+
+impl DateTime {
+    /// Converts a point in time to its UTC calendar date and time
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - the point in time to convert
+    fn from_system_time(time: SystemTime) -> DateTime {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let days = secs.div_euclid(86_400);
+        let secs_of_day = secs.rem_euclid(86_400) as u32;
+
+        // Howard Hinnant's days-to-civil algorithm
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: secs_of_day / 3_600,
+            minute: secs_of_day % 3_600 / 60,
+            second: secs_of_day % 60,
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// A calendar date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Date {
+    year: i64,
+    month: u32,
+    day: u32,
+}
 
-    #[test]
-    fn test_detect_root_folder_exists() {
-        let config = Config {
-            root_dir: PathBuf::from("/home"),
+impl Date {
+    /// Parses a `YYYY-MM-DD` date, also accepting an RFC 3339 timestamp
+    /// whose date part comes first, e.g. `2024-05-01T09:00:00Z`
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the date to parse
+    fn parse(text: &str) -> Option<Date> {
+        let text = text.trim();
+        let (date, rest) = text.split_at_checked(10)?;
+        if !(rest.is_empty() || rest.starts_with(['T', 't', ' '])) {
+            return None;
+        }
+        let mut parts = date.split('-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        let year: i64 = year.parse().ok()?;
+        let month: u32 = month.parse().ok()?;
+        let day: u32 = day.parse().ok()?;
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let days_in_month = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return None,
         };
-        let result: bool = detect_root_folder(&config);
-        assert_eq!(result, true)
+        (1..=days_in_month)
+            .contains(&day)
+            .then_some(Date { year, month, day })
     }
+}
 
-    #[test]
-    fn test_detect_root_folder_not_exists() {
-        let config = Config {
-            root_dir: PathBuf::from("~/nonsense_folder_ntuyfwntw/"),
-        };
-        let result: bool = detect_root_folder(&config);
-        assert_eq!(result, false)
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
     }
+}
 
-    #[test]
-    fn test_create_note_objects() {
-        let config = Config {
-            root_dir: PathBuf::from(
-                "/home/parker/Documents/projects/clife/clife/test_data/.clife/",
-            ),
-        };
-        let result: Vec<Note> = create_note_objects(&config);
-        assert_eq!(result.len(), 3);
+impl From<DateTime> for Date {
+    fn from(time: DateTime) -> Date {
+        Date {
+            year: time.year,
+            month: time.month,
+            day: time.day,
+        }
     }
+}
 
-    #[test]
-    fn test_valid_project_name() {
-        let valid_names = ["test", "test_1", "my.project", ".HELLO.P_Arker_", "   hello   "];
+/// Returns a pseudo-random number derived from the passed seed (splitmix64)
+///
+/// # Arguments
+///
+/// * `seed` - the seed to mix
+fn pseudo_random(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
 
-        for name in valid_names {
-            assert_eq!(validate_project_name(&String::from(name)), true);
+/// Represents a single note files
+#[derive(Debug)]
+struct Note {
+    full_path: PathBuf,
+    trunc_path: PathBuf,
+    /// The root the note was found under, which `trunc_path` is relative to
+    root: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    CreateNote,
+    Delete,
+    CreateProject,
+    Edit,
+    File,
+    Link,
+    PruneEmpty,
+    Attach,
+    List,
+    Duplicates,
+    BulkRename,
+    Toc,
+    Scratch,
+    Cat,
+    Search,
+    Process,
+    UndoCreate,
+    Last,
+    Retag,
+    Tree,
+    Lint,
+    Promote,
+    Demote,
+    Projects,
+    Tag,
+    Agenda,
+    SyncProject,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    /// Looks up an action by its name, e.g. "create", or by the short name
+    /// shown for it in the menu, e.g. "c"
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the action name or short name
+    fn from_str(name: &str) -> Result<Action, String> {
+        match name {
+            "c" | "create" => Ok(Action::CreateNote),
+            "d" | "delete" => Ok(Action::Delete),
+            "p" | "project" => Ok(Action::CreateProject),
+            "e" | "edit" => Ok(Action::Edit),
+            "f" | "file" => Ok(Action::File),
+            "l" | "link" => Ok(Action::Link),
+            "prune-empty" => Ok(Action::PruneEmpty),
+            "a" | "attach" => Ok(Action::Attach),
+            "n" | "list" => Ok(Action::List),
+            "dup" | "duplicates" => Ok(Action::Duplicates),
+            "bulk-rename" => Ok(Action::BulkRename),
+            "toc" => Ok(Action::Toc),
+            "s" | "scratch" => Ok(Action::Scratch),
+            "cat" => Ok(Action::Cat),
+            "search" => Ok(Action::Search),
+            "process" => Ok(Action::Process),
+            "undo-create" => Ok(Action::UndoCreate),
+            "last" => Ok(Action::Last),
+            "retag" => Ok(Action::Retag),
+            "tree" => Ok(Action::Tree),
+            "lint" => Ok(Action::Lint),
+            "promote" => Ok(Action::Promote),
+            "demote" => Ok(Action::Demote),
+            "projects" => Ok(Action::Projects),
+            "tag" => Ok(Action::Tag),
+            "agenda" => Ok(Action::Agenda),
+            "sync-project" => Ok(Action::SyncProject),
+            _ => Err(format!("unknown action '{}'", name)),
         }
     }
+}
 
-    #[test]
-    fn test_invalid_project_name() {
-        let invalid_names = ["hello parker", "&parker", "_hello_("];
+/// Everything passed on the command line
+#[derive(Debug, PartialEq)]
+struct Cli {
+    /// What to do this run
+    command: Command,
+    /// Overrides the configured sort order for this run
+    sort: Option<Sort>,
+    /// Shows the menu even if a default action is configured
+    menu: bool,
+    /// Lets moves and renames replace existing files, and change locked notes
+    force: bool,
+    /// Scans directories holding another vault as well
+    include_nested: bool,
+    /// Explains things that are normally done quietly
+    verbose: bool,
+    /// A project to treat as the root for this run
+    focus: Option<String>,
+    /// Only warns about unknown config keys instead of stopping
+    lenient_config: bool,
+    /// Leaves out the summary of changes made, printed before exiting
+    quiet: bool,
+}
 
-        for name in invalid_names {
-            assert_eq!(validate_project_name(&String::from(name)), false);
+/// What was asked for on the command line
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// No subcommand, so run the interactive menu
+    Menu,
+    /// Create a note without any prompts, optionally from a named template
+    /// and with a chosen name. With `fence` set, piped content is put in a
+    /// code fence, tagged with the language if one was given
+    New {
+        template: Option<String>,
+        name: Option<String>,
+        fence: Option<Option<String>>,
+    },
+    /// Create and open a timestamped scratch note
+    Scratch,
+    /// Open the most recently modified note
+    Last,
+    /// Open a note picked at random
+    Random,
+    /// Print the link graph in DOT format, to stdout or the passed file
+    Graph { output: Option<PathBuf> },
+    /// Permanently remove everything in the trash
+    EmptyTrash,
+    /// Print the resolved settings and where each came from
+    ShowConfig,
+    /// Open the config file in the editor, scaffolding it first if needed
+    EditConfig,
+    /// Check the whole vault for problems
+    Doctor,
+    /// Print every tag with how many notes carry it
+    Tags,
+    /// List the notes carrying all (or with `all` unset, any) of the tags
+    Tagged { tags: Vec<String>, all: bool },
+    /// Throw away the metadata cache and rebuild it from a full scan
+    Reindex,
+    /// Print how many notes each project holds, grouping nested projects
+    /// `depth` directories deep
+    Projects { depth: usize },
+    /// Check every note for markdown hygiene, optionally fixing what it can
+    Lint { fix: bool },
+    /// Write an INDEX.md at the root linking every note, grouped by project
+    Index,
+    /// Print the lines of every note containing a query, with context
+    Search {
+        query: String,
+        context: usize,
+        case_sensitive: bool,
+    },
+    /// Print a note's contents, optionally without its frontmatter
+    Cat {
+        note: String,
+        strip_frontmatter: bool,
+    },
+    /// Combine every note in a project into a single markdown file
+    ExportProject { name: String, dest: PathBuf },
+    /// Render a note to a standalone HTML file
+    ExportHtml { note: String, dest: PathBuf },
+    /// Move a note into another directory under the root
+    Move { note: String, dir: String },
+    /// Add one or more tags to a note
+    Tag { note: String, tags: Vec<String> },
+    /// Give a note a new file name in the same directory
+    Rename { note: String, name: String },
+    /// Remove a note, asking first unless `yes` is set
+    Delete { note: String, yes: bool },
+}
+
+/// Parses the command line arguments, excluding the program name
+///
+/// Options may appear anywhere, everything else is passed on to pick the
+/// command.
+///
+/// # Arguments
+///
+/// * `args` - the arguments passed to clife
+fn parse_args(args: &[String]) -> Result<Cli, AppError> {
+    let mut positional = Vec::new();
+    let mut sort = None;
+    let mut menu = false;
+    let mut force = false;
+    let mut include_nested = false;
+    let mut verbose = false;
+    let mut focus = None;
+    let mut lenient_config = false;
+    let mut quiet = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sort" => {
+                let name = option_value(&mut args, arg)?;
+                sort = Some(
+                    Sort::from_name(name)
+                        .ok_or_else(|| AppError::Usage(format!("unknown sort '{}'", name)))?,
+                );
+            }
+            "--menu" => menu = true,
+            "--force" => force = true,
+            "--include-nested" => include_nested = true,
+            "--verbose" => verbose = true,
+            "--focus" => focus = Some(option_value(&mut args, arg)?.clone()),
+            "--lenient-config" => lenient_config = true,
+            "--quiet" => quiet = true,
+            _ => positional.push(arg.clone()),
+        }
+    }
+    Ok(Cli {
+        command: parse_command(&positional)?,
+        sort,
+        menu,
+        force,
+        include_nested,
+        verbose,
+        focus,
+        lenient_config,
+        quiet,
+    })
+}
+
+/// Narrows the config down to a single project, which is then used as the
+/// root for everything this run
+///
+/// The config itself is still the vault's, since it was loaded before.
+///
+/// # Arguments
+///
+/// * `config` - the config to narrow
+/// * `project` - the project to focus on, e.g. "work/acme"
+fn apply_focus(config: &mut Config, project: &str) -> Result<(), AppError> {
+    let dir = parse_project_path(project)
+        .map(|dir| config.root_dir.join(dir))
+        .filter(|dir| dir.is_dir())
+        .ok_or_else(|| AppError::Usage(format!("no project named '{}' to focus on", project)))?;
+    config.root_dir = dir;
+    config.set_source("root_dir", ConfigSource::Flag("--focus"));
+    Ok(())
+}
+
+/// Returns the value following a command line option
+///
+/// # Arguments
+///
+/// * `args` - the remaining arguments
+/// * `option` - the option the value is for, used in the error
+fn option_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<&'a String, AppError> {
+    args.next()
+        .ok_or_else(|| AppError::Usage(format!("{} needs a value", option)))
+}
+
+/// Picks the command from the command line arguments left after global options
+///
+/// # Arguments
+///
+/// * `args` - the arguments that aren't global options
+fn parse_command(args: &[String]) -> Result<Command, AppError> {
+    let mut args = args.iter().peekable();
+    let command = match args.next().map(String::as_str) {
+        None => Command::Menu,
+        Some("new") => {
+            let mut template = None;
+            let mut name = None;
+            let mut fence = None;
+            while let Some(arg) = args.next_if(|arg| arg.starts_with("--")) {
+                match arg.as_str() {
+                    "--template" => template = Some(option_value(&mut args, arg)?.clone()),
+                    "--name" => name = Some(option_value(&mut args, arg)?.clone()),
+                    "--fence" => fence = Some(args.next_if(|arg| !arg.starts_with("--")).cloned()),
+                    _ => return Err(AppError::Usage(format!("unknown option '{}'", arg))),
+                }
+            }
+            Command::New {
+                template,
+                name,
+                fence,
+            }
+        }
+        Some("graph") => Command::Graph {
+            output: args.next().map(PathBuf::from),
+        },
+        Some("empty-trash") => Command::EmptyTrash,
+        Some("scratch") => Command::Scratch,
+        Some("last") => Command::Last,
+        Some("random") => Command::Random,
+        Some("config") => match args.next_if(|arg| *arg == "edit") {
+            Some(_) => Command::EditConfig,
+            None => Command::ShowConfig,
+        },
+        Some("doctor") => Command::Doctor,
+        Some("tags") => Command::Tags,
+        Some("tagged") => {
+            let mut tags = Vec::new();
+            let mut all = true;
+            for arg in args.by_ref() {
+                match arg.as_str() {
+                    "--all" => all = true,
+                    "--any" => all = false,
+                    _ if arg.starts_with("--") => {
+                        return Err(AppError::Usage(format!("unknown option '{}'", arg)))
+                    }
+                    _ => tags.push(arg.trim_start_matches('#').to_string()),
+                }
+            }
+            if tags.is_empty() {
+                return Err(AppError::Usage(String::from(
+                    "tagged needs at least one tag",
+                )));
+            }
+            Command::Tagged { tags, all }
         }
+        Some("reindex") => Command::Reindex,
+        Some("index") => Command::Index,
+        Some("projects") => {
+            let mut depth = 1;
+            while let Some(arg) = args.next_if(|arg| arg.starts_with("--")) {
+                match arg.as_str() {
+                    "--depth" => {
+                        depth = option_value(&mut args, arg)?.parse().map_err(|_| {
+                            AppError::Usage(String::from("--depth needs a whole number"))
+                        })?
+                    }
+                    _ => return Err(AppError::Usage(format!("unknown option '{}'", arg))),
+                }
+            }
+            Command::Projects { depth }
+        }
+        Some("lint") => {
+            let mut fix = false;
+            while let Some(arg) = args.next_if(|arg| arg.starts_with("--")) {
+                match arg.as_str() {
+                    "--fix" => fix = true,
+                    _ => return Err(AppError::Usage(format!("unknown option '{}'", arg))),
+                }
+            }
+            Command::Lint { fix }
+        }
+        Some("search") => {
+            let mut query = None;
+            let mut context = 0;
+            let mut case_sensitive = false;
+            while let Some(arg) = args.next_if(|arg| arg.starts_with('-') || query.is_none()) {
+                match arg.as_str() {
+                    "--context" => {
+                        context = option_value(&mut args, arg)?.parse().map_err(|_| {
+                            AppError::Usage(String::from("--context needs a whole number"))
+                        })?
+                    }
+                    "--case-sensitive" | "-s" => case_sensitive = true,
+                    _ if arg.starts_with('-') && query.is_some() => {
+                        return Err(AppError::Usage(format!("unknown option '{}'", arg)))
+                    }
+                    _ => query = Some(arg.clone()),
+                }
+            }
+            match query {
+                Some(query) => Command::Search {
+                    query,
+                    context,
+                    case_sensitive,
+                },
+                None => return Err(AppError::Usage(String::from("search needs a query"))),
+            }
+        }
+        Some("cat") => {
+            let mut note = None;
+            let mut strip_frontmatter = false;
+            while let Some(arg) = args.next_if(|arg| arg.starts_with("--") || note.is_none()) {
+                match arg.as_str() {
+                    "--no-frontmatter" => strip_frontmatter = true,
+                    _ if arg.starts_with("--") => {
+                        return Err(AppError::Usage(format!("unknown option '{}'", arg)))
+                    }
+                    _ => note = Some(arg.clone()),
+                }
+            }
+            match note {
+                Some(note) => Command::Cat {
+                    note,
+                    strip_frontmatter,
+                },
+                None => return Err(AppError::Usage(String::from("cat needs a note"))),
+            }
+        }
+        Some("export-project") => match (args.next(), args.next()) {
+            (Some(name), Some(dest)) => Command::ExportProject {
+                name: name.clone(),
+                dest: PathBuf::from(dest),
+            },
+            _ => {
+                return Err(AppError::Usage(String::from(
+                    "export-project needs a project name and a destination file",
+                )))
+            }
+        },
+        Some("export-html") => match (args.next(), args.next()) {
+            (Some(note), Some(dest)) => Command::ExportHtml {
+                note: note.clone(),
+                dest: PathBuf::from(dest),
+            },
+            _ => {
+                return Err(AppError::Usage(String::from(
+                    "export-html needs a note and a destination file",
+                )))
+            }
+        },
+        Some("move") => match (args.next(), args.next()) {
+            (Some(note), Some(dir)) => Command::Move {
+                note: note.clone(),
+                dir: dir.clone(),
+            },
+            _ => {
+                return Err(AppError::Usage(String::from(
+                    "move needs a note and a directory",
+                )))
+            }
+        },
+        Some("tag") => {
+            let Some(note) = args.next() else {
+                return Err(AppError::Usage(String::from("tag needs a note and a tag")));
+            };
+            let tags: Vec<String> = args.by_ref().cloned().collect();
+            if tags.is_empty() {
+                return Err(AppError::Usage(String::from("tag needs a note and a tag")));
+            }
+            Command::Tag {
+                note: note.clone(),
+                tags,
+            }
+        }
+        Some("rename") => match (args.next(), args.next()) {
+            (Some(note), Some(name)) => Command::Rename {
+                note: note.clone(),
+                name: name.clone(),
+            },
+            _ => {
+                return Err(AppError::Usage(String::from(
+                    "rename needs a note and a new name",
+                )))
+            }
+        },
+        Some("delete") => {
+            let mut note = None;
+            let mut yes = false;
+            while let Some(arg) = args.next_if(|arg| arg.starts_with('-') || note.is_none()) {
+                match arg.as_str() {
+                    "--yes" | "-y" => yes = true,
+                    _ if arg.starts_with('-') => {
+                        return Err(AppError::Usage(format!("unknown option '{}'", arg)))
+                    }
+                    _ => note = Some(arg.clone()),
+                }
+            }
+            match note {
+                Some(note) => Command::Delete { note, yes },
+                None => return Err(AppError::Usage(String::from("delete needs a note"))),
+            }
+        }
+        Some(other) => return Err(AppError::Usage(format!("unknown command '{}'", other))),
+    };
+    if let Some(extra) = args.next() {
+        if extra.starts_with("--") {
+            return Err(AppError::Usage(format!("unknown option '{}'", extra)));
+        }
+        return Err(AppError::Usage(format!("unexpected argument '{}'", extra)));
+    }
+    Ok(command)
+}
+
+/// Returns the user's home directory
+fn home_dir() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or_default())
+}
+
+/// Returns where the vault lives when `root_dir` isn't set
+///
+/// An existing `~/.clife` is kept for backward compatibility. Otherwise the
+/// vault goes in `$XDG_DATA_HOME/clife`, or `~/.local/share/clife` if that
+/// isn't set to an absolute path.
+///
+/// # Arguments
+///
+/// * `var` - looks up an environment variable by name
+/// * `home` - the user's home directory
+fn default_root_dir(var: impl Fn(&str) -> Option<String>, home: &Path) -> PathBuf {
+    let legacy = home.join(".clife");
+    if legacy.is_dir() {
+        return legacy;
+    }
+    match var("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(data_home) if data_home.is_absolute() => data_home.join("clife"),
+        _ => home.join(".local").join("share").join("clife"),
+    }
+}
+
+/// Expands a leading `~` and any `$VAR` or `${VAR}` environment variables
+///
+/// Unset variables expand to nothing, like in a shell.
+///
+/// # Arguments
+///
+/// * `s` - the path or command to expand
+fn expand_path(s: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = s;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&home_dir().to_string_lossy());
+        rest = &rest[1..];
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&std::env::var(name).unwrap_or_default());
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Returns where the config file is expected to live
+fn config_path() -> PathBuf {
+    home_dir().join(".config").join("clife").join("config.toml")
+}
+
+/// The config file written by `clife config edit` when there isn't one yet
+const DEFAULT_CONFIG: &str = "\
+# clife config file
+#
+# Every setting is optional. Uncomment a line to change it, and run
+# `clife config` to see every setting and where its value comes from.
+
+# root_dir = \"~/notes\"
+# editor = \"vim\"
+# editor = [\"nvim\", \"vim\", \"nano\"]
+# editor_blocks = true
+# naming_scheme = \"incremental\"
+# display = \"path\"
+# sort = \"name_asc\"
+# use_trash = true
+# confirm_trash = true
+# create_root = \"prompt\"
+";
+
+/// Writes a commented default config file if there isn't one at `path` yet
+///
+/// Returns whether a file was written.
+///
+/// # Arguments
+///
+/// * `path` - where the config file should live
+fn scaffold_config(path: &Path) -> io::Result<bool> {
+    if path.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    write_text(path, DEFAULT_CONFIG)?;
+    Ok(true)
+}
+
+/// Opens the config file in the editor, creating a default one first if
+/// there isn't one yet
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn edit_config(config: &Config) {
+    let path = config.config_file.clone().unwrap_or_else(config_path);
+    match scaffold_config(&path) {
+        Ok(true) => println!("Created {}", path.display()),
+        Ok(false) => {}
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            println!("Can't create {}: the location is read-only", path.display());
+            exit(1);
+        }
+        Err(e) => {
+            println!("Failed to create {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+    if std::fs::metadata(&path).is_ok_and(|meta| meta.permissions().readonly()) {
+        println!("{} is read-only, so changes can't be saved", path.display());
+        exit(1);
+    }
+    if let Err(e) = open_in_editor(config, &path) {
+        println!("Failed to open {}: {}", path.display(), e);
+        exit(1);
+    }
+}
+
+/// Parses the simple `key = value` config file format
+///
+/// Values may be quoted strings, `true`/`false`, whole numbers, or single-line
+/// lists of quoted strings. Blank lines and lines starting with `#` are ignored.
+///
+/// # Arguments
+///
+/// * `contents` - the raw contents of the config file
+fn parse_config_file(contents: &str) -> Result<Vec<(String, ConfigValue)>, AppError> {
+    let mut entries = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(AppError::Config(format!(
+                "line {} is not of the form key = value",
+                i + 1
+            )));
+        };
+        match parse_config_value(value.trim()) {
+            Some(value) => entries.push((String::from(key.trim()), value)),
+            None => {
+                return Err(AppError::Config(format!(
+                    "couldn't understand the value on line {}",
+                    i + 1
+                )))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Parses the right hand side of a config line
+///
+/// # Arguments
+///
+/// * `value` - the trimmed value text
+fn parse_config_value(value: &str) -> Option<ConfigValue> {
+    if value == "true" || value == "false" {
+        return Some(ConfigValue::Bool(value == "true"));
+    }
+    if let Ok(n) = value.parse::<u64>() {
+        return Some(ConfigValue::Integer(n));
+    }
+    if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        let mut items = Vec::new();
+        for item in inner.split(',') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            items.push(parse_quoted(item)?);
+        }
+        return Some(ConfigValue::List(items));
+    }
+    parse_quoted(value).map(ConfigValue::String)
+}
+
+/// Strips the double quotes from a quoted config string
+///
+/// The escapes `\n`, `\t`, `\"` and `\\` are turned into the characters they
+/// stand for; any other backslash is kept as is.
+///
+/// # Arguments
+///
+/// * `value` - the quoted string
+fn parse_quoted(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('"') => unescaped.push('"'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    Some(unescaped)
+}
+
+/// Returns where a vault's own config file lives
+///
+/// # Arguments
+///
+/// * `root_dir` - the root directory of the vault
+fn local_config_path(root_dir: &Path) -> PathBuf {
+    root_dir.join(".clife.toml")
+}
+
+/// Overrides a config with the settings in a partial config
+///
+/// Only the keys present in `override_` change, everything else is kept.
+///
+/// # Arguments
+///
+/// * `base` - the config to start from
+/// * `override_` - the settings that take precedence
+fn merge_configs(mut base: Config, override_: PartialConfig) -> Result<Config, AppError> {
+    base.apply_partial(override_)?;
+    Ok(base)
+}
+
+/// Builds the config for this run
+///
+/// The global config file is read first, then the vault's `.clife.toml`,
+/// which wins key by key. Environment variables override both.
+///
+/// # Arguments
+///
+/// * `lenient` - whether unknown keys are only warned about
+fn load_config(lenient: bool) -> Result<Config, AppError> {
+    let mut config = Config {
+        lenient_config: lenient,
+        ..Default::default()
+    };
+    let path = config_path();
+    if let Some(global) = PartialConfig::read(&path, ConfigSource::File)? {
+        config = merge_configs(config, global)?;
+        config.config_file = Some(path);
+    }
+    config.apply_env(|name| std::env::var(name).ok());
+    let path = local_config_path(&config.root_dir);
+    if let Some(local) = PartialConfig::read(&path, ConfigSource::LocalFile)? {
+        config = merge_configs(config, local)?;
+        config.local_config_file = Some(path);
+        config.apply_env(|name| std::env::var(name).ok());
+    }
+    Ok(config)
+}
+
+/// Describes every setting, its value and where the value came from
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn describe_config(config: &Config) -> String {
+    let mut lines = vec![match &config.config_file {
+        Some(path) => format!("config file: {}", path.display()),
+        None => String::from("config file: none, using defaults"),
+    }];
+    if let Some(path) = &config.local_config_file {
+        lines.push(format!("local config file: {}", path.display()));
+    }
+    let mut setting = |key: &str, value: String| {
+        lines.push(format!("{} = {} ({})", key, value, config.source(key)));
+    };
+    setting("root_dir", config.root_dir.display().to_string());
+    setting("editor", config.editor.join(", "));
+    setting("editor_multi_file", config.editor_multi_file.to_string());
+    setting("editor_blocks", config.editor_blocks.to_string());
+    let mut by_extension: Vec<_> = config.editor_by_extension.iter().collect();
+    by_extension.sort();
+    for (extension, editor) in by_extension {
+        setting(
+            &format!("editor_by_extension.{}", extension),
+            editor.clone(),
+        );
+    }
+    setting(
+        "project_creates_note",
+        config.project_creates_note.to_string(),
+    );
+    setting("enabled_actions", format!("{:?}", config.enabled_actions));
+    setting("default_action", format!("{:?}", config.default_action));
+    setting("naming_scheme", format!("{:?}", config.naming_scheme));
+    setting("display", format!("{:?}", config.display));
+    setting("link_section", format!("{:?}", config.link_section));
+    setting(
+        "cleanup_empty_on_editor_abort",
+        config.cleanup_empty_on_editor_abort.to_string(),
+    );
+    setting("use_trash", config.use_trash.to_string());
+    setting(
+        "trash_retention_days",
+        format!("{:?}", config.trash_retention_days),
+    );
+    setting("sort", format!("{:?}", config.sort));
+    setting("scratch_dir", config.scratch_dir.display().to_string());
+    setting("max_name_len", config.max_name_len.to_string());
+    setting("inbox_dir", config.inbox_dir.display().to_string());
+    setting("wrap_column", format!("{:?}", config.wrap_column));
+    setting("merge_separator", format!("{:?}", config.merge_separator));
+    setting(
+        "merge_header_template",
+        format!("{:?}", config.merge_header_template),
+    );
+    setting(
+        "sync_title_on_rename",
+        config.sync_title_on_rename.to_string(),
+    );
+    setting("note_extensions", format!("{:?}", config.note_extensions));
+    setting("extra_roots", format!("{:?}", config.extra_roots));
+    setting("force_overwrite", config.force_overwrite.to_string());
+    setting("banner", format!("{:?}", config.banner));
+    setting("prompt_prefix", format!("{:?}", config.prompt_prefix));
+    setting(
+        "log_deleted_content",
+        config.log_deleted_content.to_string(),
+    );
+    setting(
+        "log_deleted_content_max",
+        config.log_deleted_content_max.to_string(),
+    );
+    setting("normalize_newlines", config.normalize_newlines.to_string());
+    setting("create_root", format!("{:?}", config.create_root));
+    setting(
+        "collision_strategy",
+        format!("{:?}", config.collision_strategy),
+    );
+    setting("trim_names", config.trim_names.to_string());
+    setting(
+        "search_case_sensitive",
+        config.search_case_sensitive.to_string(),
+    );
+    setting("seed_h1_title", config.seed_h1_title.to_string());
+    setting("confirm_trash", config.confirm_trash.to_string());
+    setting("include_nested", config.include_nested.to_string());
+    setting("verbose", config.verbose.to_string());
+    setting(
+        "remember_last_action",
+        config.remember_last_action.to_string(),
+    );
+    setting("list_cap", config.list_cap.to_string());
+    setting("word_budget", format!("{:?}", config.word_budget));
+    setting(
+        "sync_project_on_create",
+        config.sync_project_on_create.to_string(),
+    );
+    setting("root_project", format!("{:?}", config.root_project));
+    setting(
+        "prompt_timeout_secs",
+        format!("{:?}", config.prompt_timeout_secs),
+    );
+    setting(
+        "report_empty_projects",
+        config.report_empty_projects.to_string(),
+    );
+    setting("post_create_hook", format!("{:?}", config.post_create_hook));
+    setting("post_delete_hook", format!("{:?}", config.post_delete_hook));
+    lines.join("\n") + "\n"
+}
+
+/// Returns if the root dir exists already
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn detect_root_folder(config: &Config) -> bool {
+    match config.root_dir.try_exists() {
+        Ok(exists) => exists,
+        Err(_) => panic!("Failed to parse root dir {}", config.root_dir.display()),
+    }
+}
+
+/// Ensures the root dir, if it exists, is a directory rather than a file
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn validate_root_dir(config: &Config) -> Result<(), AppError> {
+    match std::fs::metadata(&config.root_dir) {
+        Ok(metadata) if !metadata.is_dir() => Err(AppError::Config(format!(
+            "root_dir points to a file: {}",
+            config.root_dir.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Makes sure the root dir exists, creating it as `create_root` says
+///
+/// Errors if the root is missing and isn't created. Running out of input at
+/// the prompt counts as a no.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `input` - where to read the answer to the prompt from, usually stdin
+fn ensure_root_folder(config: &Config, input: &mut impl BufRead) -> Result<(), AppError> {
+    if detect_root_folder(config) {
+        return Ok(());
+    }
+    println!("No clife folder detected at {}", config.root_dir.display());
+    let create = match config.create_root {
+        CreateRoot::Always => true,
+        CreateRoot::Never => false,
+        CreateRoot::Prompt => loop {
+            println!("\nCreate it?");
+            println!("Options are ... \n\t- (y)es\n\t- (n)o");
+            let answer = read_input(input);
+            match answer.trim() {
+                "y" => break true,
+                "n" => break false,
+                _ if answer.is_empty() => break false,
+                _ => {}
+            }
+        },
+    };
+    if !create {
+        return Err(AppError::Config(format!(
+            "root_dir doesn't exist: {}",
+            config.root_dir.display()
+        )));
+    }
+    create_root_folder(config);
+    Ok(())
+}
+
+/// Creates a root folder to store things in
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn create_root_folder(config: &Config) {
+    _ = create_dir_all(&config.root_dir);
+    println!("{} directory created!", config.root_dir.display());
+}
+
+/// Returns the directory note templates are stored in
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn templates_dir(config: &Config) -> PathBuf {
+    config.root_dir.join("templates")
+}
+
+/// The file a project directory can hold to template the notes created in it
+const PROJECT_TEMPLATE: &str = ".template.md";
+
+/// Returns the template new notes in `target_dir` should start from
+///
+/// A `.template.md` in the directory itself wins over the global
+/// `templates/default.md`. Returns None if neither exists.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `target_dir` - the directory the note is being created in
+fn resolve_template(config: &Config, target_dir: &Path) -> Option<PathBuf> {
+    [
+        target_dir.join(PROJECT_TEMPLATE),
+        templates_dir(config).join("default.md"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// Creates the core notes vector from the root directory and any
+/// `extra_roots`
+///
+/// Extra roots that aren't directories are skipped.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn create_note_objects(config: &Config) -> Vec<Note> {
+    let mut notes: Vec<Note> = Vec::new();
+    for root in std::iter::once(&config.root_dir).chain(&config.extra_roots) {
+        if *root != config.root_dir && !root.is_dir() {
+            if config.verbose {
+                println!(
+                    "Skipping extra root {}, it isn't a directory",
+                    root.display()
+                );
+            }
+            continue;
+        }
+        let ignore = load_ignore(root);
+        _get_dir_notes(root, root, &mut notes, config, &ignore);
+    }
+    notes
+}
+
+/// Glob patterns for paths that should be skipped when scanning
+#[derive(Debug, Default)]
+struct IgnoreSet {
+    patterns: Vec<String>,
+}
+
+impl IgnoreSet {
+    /// Returns if the passed path matches any of the patterns
+    ///
+    /// # Arguments
+    ///
+    /// * `trunc_path` - the path to check, relative to the root
+    fn is_ignored(&self, trunc_path: &Path) -> bool {
+        let path = trunc_path.to_string_lossy();
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), path.as_bytes()))
+    }
+}
+
+/// Reads the `.clifeignore` file at the root, if there is one
+///
+/// Each line is a glob pattern relative to the root, where `*` and `?` match
+/// within a single path component and `**` matches across components. Blank
+/// lines and lines starting with `#` are skipped.
+///
+/// # Arguments
+///
+/// * `root` - the root directory of the vault
+fn load_ignore(root: &Path) -> IgnoreSet {
+    let contents = std::fs::read_to_string(root.join(".clifeignore")).unwrap_or_default();
+    let patterns = contents
+        .lines()
+        .map(|line| line.trim().trim_start_matches('/').trim_end_matches('/'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+    IgnoreSet { patterns }
+}
+
+/// Returns if the text matches the glob pattern
+///
+/// # Arguments
+///
+/// * `pattern` - the glob pattern
+/// * `text` - the text to match against
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            // Let `**/` also match zero directories
+            if let Some(after_slash) = rest.strip_prefix(b"/") {
+                if glob_match(after_slash, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => {
+            text.first().is_some_and(|c| *c != b'/') && glob_match(&pattern[1..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Returns if a directory looks like the root of a vault of its own
+///
+/// # Arguments
+///
+/// * `dir` - the directory to check
+fn is_vault_root(dir: &Path) -> bool {
+    local_config_path(dir).is_file() || dir.join(".clife").is_dir()
+}
+
+/// Creates notes from the base directory - recurses through directories
+///
+/// Symlinked files are notes like any other, but symlinked directories are
+/// never followed.
+///
+/// # Arguments
+///
+/// * `base` - a reference to the base directory to search
+/// * `root_dir` - the root the base directory is in
+/// * `notes` - The current state of a vector of notes to append to
+/// * `config` - a reference to a config object
+/// * `ignore` - patterns for paths to skip
+fn _get_dir_notes(
+    base: &Path,
+    root_dir: &Path,
+    notes: &mut Vec<Note>,
+    config: &Config,
+    ignore: &IgnoreSet,
+) {
+    let contents = read_dir(base).unwrap();
+    for curr in contents {
+        let curr_file = curr.expect("Failed to read");
+        let curr_path = curr_file.path();
+        // Hidden files and directories, like the trash, are never notes
+        if curr_file.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let trunc_path = curr_path.strip_prefix(root_dir).unwrap().to_path_buf();
+        if ignore.is_ignored(&trunc_path) || trunc_path == Path::new(INDEX_FILE) {
+            continue;
+        }
+        let is_link = curr_file.file_type().is_ok_and(|t| t.is_symlink());
+        if is_link && curr_path.is_dir() {
+            if config.verbose {
+                println!("Skipping symlinked directory {}", curr_path.display());
+            }
+            continue;
+        }
+        if curr_path.is_dir() {
+            // Templates and attachments live in the vault but aren't notes themselves
+            if curr_path == root_dir.join("templates")
+                || curr_path.extension() == Some("assets".as_ref())
+            {
+                continue;
+            }
+            if !config.include_nested && is_vault_root(&curr_path) {
+                if config.verbose {
+                    println!("Skipping nested vault at {}", curr_path.display());
+                }
+                continue;
+            }
+            _get_dir_notes(&curr_path, root_dir, notes, config, ignore);
+        } else {
+            let curr_note = Note {
+                full_path: curr_path,
+                trunc_path,
+                root: root_dir.to_path_buf(),
+            };
+            notes.push(curr_note)
+        }
+    }
+}
+
+/// Returns when a note was last modified, or the epoch if that can't be read
+///
+/// # Arguments
+///
+/// * `note` - the note to check
+fn note_modified(note: &Note) -> SystemTime {
+    std::fs::metadata(&note.full_path)
+        .and_then(|m| m.modified())
+        .unwrap_or(UNIX_EPOCH)
+}
+
+/// Returns the note modified most recently
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn most_recent_note(notes: &[Note]) -> Option<&Note> {
+    notes.iter().max_by_key(|note| note_modified(note))
+}
+
+/// Picks a note at random, or None if there are no notes
+///
+/// The same seed always picks the same note.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `seed` - seeds the pseudo-random pick
+fn pick_random(notes: &[Note], seed: u64) -> Option<&Note> {
+    if notes.is_empty() {
+        return None;
+    }
+    notes.get((pseudo_random(seed) % notes.len() as u64) as usize)
+}
+
+/// Opens the most recently modified note, or a fresh one if there are none
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn open_last(config: &Config, notes: &[Note]) {
+    let note_path = match most_recent_note(notes) {
+        Some(note) => note.full_path.clone(),
+        None => match create_new_note(config, &config.root_dir, 1) {
+            Ok(note_path) => note_path,
+            Err(e) => {
+                println!("Failed to create note: {}", e);
+                exit(1);
+            }
+        },
+    };
+    let _ = open_in_editor(config, &note_path);
+}
+
+/// Sorts notes into the passed order
+///
+/// # Arguments
+///
+/// * `notes` - the notes to sort
+/// * `sort` - the order to sort them in
+fn sort_notes(notes: &mut [Note], sort: Sort) {
+    let size = |note: &Note| std::fs::metadata(&note.full_path).map_or(0, |m| m.len());
+    match sort {
+        Sort::NameAsc => notes.sort_by(|a, b| a.trunc_path.cmp(&b.trunc_path)),
+        Sort::NameDesc => notes.sort_by(|a, b| b.trunc_path.cmp(&a.trunc_path)),
+        Sort::MtimeDesc => notes.sort_by_cached_key(|note| std::cmp::Reverse(note_modified(note))),
+        Sort::MtimeAsc => notes.sort_by_cached_key(note_modified),
+        Sort::SizeDesc => notes.sort_by_cached_key(|note| std::cmp::Reverse(size(note))),
+    }
+}
+
+/// Prints every note, one per line
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn list_notes(config: &Config, notes: &[Note]) {
+    for note in notes {
+        println!("- {}", display_name(note, config.display));
+    }
+}
+
+/// A directory or note in the tree of the vault
+#[derive(Debug, Default, PartialEq)]
+struct TreeNode {
+    /// The file or directory name
+    name: String,
+    /// What's inside a directory, empty for notes
+    children: Vec<TreeNode>,
+}
+
+/// Builds the directory tree holding the passed notes
+///
+/// Directories are only included if they hold at least one of the notes.
+/// Entries keep the order the notes are in.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn build_tree(notes: &[Note]) -> TreeNode {
+    let mut root = TreeNode {
+        name: String::from("."),
+        children: Vec::new(),
+    };
+    for note in notes {
+        let names: Vec<String> = note
+            .trunc_path
+            .iter()
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        let Some((file_name, dirs)) = names.split_last() else {
+            continue;
+        };
+        let mut node = &mut root;
+        for dir in dirs {
+            let i = match node
+                .children
+                .iter()
+                .position(|child| !child.children.is_empty() && child.name == *dir)
+            {
+                Some(i) => i,
+                None => {
+                    node.children.push(TreeNode {
+                        name: dir.clone(),
+                        children: Vec::new(),
+                    });
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[i];
+        }
+        node.children.push(TreeNode {
+            name: file_name.clone(),
+            children: Vec::new(),
+        });
+    }
+    root
+}
+
+/// Renders a tree with branch markers, like `tree(1)`
+///
+/// # Arguments
+///
+/// * `tree` - the tree to render
+fn render_tree(tree: &TreeNode) -> String {
+    fn render_children(node: &TreeNode, prefix: &str, out: &mut String) {
+        for (i, child) in node.children.iter().enumerate() {
+            let last = i + 1 == node.children.len();
+            out.push_str(&format!(
+                "{}{}{}\n",
+                prefix,
+                if last { "└── " } else { "├── " },
+                child.name
+            ));
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            render_children(child, &prefix, out);
+        }
+    }
+
+    let mut out = format!("{}\n", tree.name);
+    render_children(tree, "", &mut out);
+    out
+}
+
+/// The action menu, as (label, action) in display order
+///
+/// Each label shows, in brackets, a short name the action can be picked by.
+const ACTION_MENU: [(&str, Action); 27] = [
+    ("(c)reate note", Action::CreateNote),
+    ("(d)elete", Action::Delete),
+    ("create (p)roject", Action::CreateProject),
+    ("(e)dit note", Action::Edit),
+    ("(f)ile notes into projects", Action::File),
+    ("(l)ink two notes", Action::Link),
+    ("(prune-empty) delete empty notes", Action::PruneEmpty),
+    ("(a)ttach a file to a note", Action::Attach),
+    ("list (n)otes", Action::List),
+    ("find (dup)licate notes", Action::Duplicates),
+    ("(bulk-rename) rename notes by pattern", Action::BulkRename),
+    ("(toc) show a note's table of contents", Action::Toc),
+    ("open a (s)cratch note", Action::Scratch),
+    ("(cat) print a note", Action::Cat),
+    ("(search) notes", Action::Search),
+    ("(process) the inbox", Action::Process),
+    (
+        "(undo-create) remove the last created note",
+        Action::UndoCreate,
+    ),
+    ("(last) open the latest note", Action::Last),
+    ("(retag) rename a tag across notes", Action::Retag),
+    ("(tree) show the notes as a tree", Action::Tree),
+    ("(lint) check notes for markdown hygiene", Action::Lint),
+    ("(promote) move a note up a directory", Action::Promote),
+    ("(demote) move a note down a directory", Action::Demote),
+    ("(projects) list the projects", Action::Projects),
+    ("(tag) add a tag to several notes", Action::Tag),
+    ("(agenda) list notes by due date", Action::Agenda),
+    (
+        "(sync-project) set each note's project field",
+        Action::SyncProject,
+    ),
+];
+
+/// Writes the welcome message shown when the menu starts
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `out` - where to write the message, usually stdout
+fn write_banner(config: &Config, out: &mut impl io::Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{}",
+        config.banner.as_deref().unwrap_or("Welcome to clife!")
+    )
+}
+
+/// Prints a question, after the configured prompt prefix
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `question` - the question to ask
+fn ask(config: &Config, question: &str) {
+    println!(
+        "\n{}{}",
+        config.prompt_prefix.as_deref().unwrap_or_default(),
+        question
+    );
+}
+
+/// Returns the short name an action is shown with in the menu, e.g. "c"
+///
+/// # Arguments
+///
+/// * `action` - the action to name
+fn action_short_name(action: Action) -> &'static str {
+    let (label, _) = ACTION_MENU
+        .iter()
+        .find(|(_, a)| *a == action)
+        .expect("every action is in the menu");
+    let start = label.find('(').map_or(0, |i| i + 1);
+    let end = label.find(')').unwrap_or(label.len());
+    &label[start..end]
+}
+
+/// Returns the file the last chosen menu action is remembered in
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn last_action_path(config: &Config) -> PathBuf {
+    config.root_dir.join(".last_action")
+}
+
+/// Reads the action chosen last time, if one was remembered
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn load_last_action(config: &Config) -> Option<Action> {
+    std::fs::read_to_string(last_action_path(config))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Remembers the chosen action for the next run
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `action` - the action that was chosen
+fn save_last_action(config: &Config, action: Action) -> io::Result<()> {
+    write_text(&last_action_path(config), action_short_name(action))
+}
+
+/// Prompts the user for the action they want to take
+///
+/// Only actions enabled in the config are shown or accepted. If there's a
+/// default it's marked in the menu and picked by an empty answer.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `input` - where to read answers from, usually stdin
+/// * `default` - the action to take on an empty answer, if any
+fn prompt_for_action(config: &Config, input: &mut impl BufRead, default: Option<Action>) -> Action {
+    let options: Vec<&(&str, Action)> = ACTION_MENU
+        .iter()
+        .filter(|(_, action)| config.action_enabled(*action))
+        .collect();
+    let default = default.filter(|action| config.action_enabled(*action));
+    loop {
+        ask(config, "What action would you like to take?");
+        println!("Options are ... ");
+        for (label, action) in &options {
+            if Some(*action) == default {
+                println!("\t - {} [default]", label);
+            } else {
+                println!("\t - {}", label);
+            }
+        }
+        let answer = read_input(input);
+        if let (Some(action), "") = (default, answer.trim()) {
+            return action;
+        }
+        match answer.trim().parse::<Action>() {
+            Ok(action) if config.action_enabled(action) => return action,
+            Ok(_) => println!("That action isn't enabled"),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// The shortest `max_name_len` allowed, which still fits every generated
+/// note name
+const MIN_NAME_LEN: usize = 20;
+
+/// How many times the interactive create flow re-prompts before giving up
+const MAX_CREATE_ATTEMPTS: usize = 3;
+
+/// The exit code used when a prompt goes unanswered for `prompt_timeout_secs`
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Reads lines on a background thread so that waiting for one can give up
+/// after a while
+///
+/// A line is only read when one is asked for, so nothing is taken from the
+/// terminal while, say, the editor has it.
+struct TimedReader {
+    requests: std::sync::mpsc::Sender<()>,
+    lines: std::sync::mpsc::Receiver<io::Result<String>>,
+    timeout: Duration,
+    line: String,
+    pos: usize,
+}
+
+impl TimedReader {
+    /// Starts the background thread
+    ///
+    /// # Arguments
+    ///
+    /// * `read_line` - reads the next line, appending it to the passed string
+    /// * `timeout` - how long to wait for each line
+    fn new(
+        mut read_line: impl FnMut(&mut String) -> io::Result<usize> + Send + 'static,
+        timeout: Duration,
+    ) -> TimedReader {
+        let (requests, asked) = std::sync::mpsc::channel::<()>();
+        let (answers, lines) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            while asked.recv().is_ok() {
+                let mut line = String::new();
+                let result = read_line(&mut line).map(|_| line);
+                if answers.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+        TimedReader {
+            requests,
+            lines,
+            timeout,
+            line: String::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl io::Read for TimedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for TimedReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.line.len() {
+            if self.requests.send(()).is_err() {
+                return Ok(&[]);
+            }
+            match self.lines.recv_timeout(self.timeout) {
+                Ok(line) => {
+                    self.line = line?;
+                    self.pos = 0;
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no answer after {} seconds", self.timeout.as_secs()),
+                    ));
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return Ok(&[]),
+            }
+        }
+        Ok(&self.line.as_bytes()[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.line.len());
+    }
+}
+
+/// Returns where prompts read their answers from: stdin, giving up after
+/// `prompt_timeout_secs` if that's set
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn prompt_input(config: &Config) -> Box<dyn BufRead> {
+    match config.prompt_timeout_secs {
+        Some(secs) => Box::new(TimedReader::new(
+            |line| stdin().read_line(line),
+            Duration::from_secs(secs),
+        )),
+        None => Box::new(stdin().lock()),
+    }
+}
+
+/// Reads a single line from the passed input, panicking if it can't be read
+///
+/// If no answer comes in time (see `prompt_timeout_secs`) clife exits with
+/// `TIMEOUT_EXIT_CODE`.
+///
+/// # Arguments
+///
+/// * `input` - where to read the line from, usually stdin
+fn read_input(input: &mut impl BufRead) -> String {
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(_) => line,
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            println!("\nCancelling, {}", e);
+            exit(TIMEOUT_EXIT_CODE);
+        }
+        Err(e) => panic!("Failed to read line: {:?}", e),
+    }
+}
+
+/// Generates the name (without extension) for a new note
+///
+/// For timestamps, a non-zero `n` means earlier names collided, so the seconds
+/// are included and stepped `n` seconds past `now`.
+///
+/// # Arguments
+///
+/// * `scheme` - the naming scheme to use
+/// * `now` - the current time
+/// * `n` - the incremental suffix, or how many names have already collided
+fn generate_note_id(scheme: NamingScheme, now: SystemTime, n: usize) -> String {
+    match scheme {
+        NamingScheme::Incremental => format!("new_note_{}", n),
+        NamingScheme::Timestamp => {
+            let t = DateTime::from_system_time(now + Duration::from_secs(n as u64));
+            let id = format!(
+                "{:04}{:02}{:02}{:02}{:02}",
+                t.year, t.month, t.day, t.hour, t.minute
+            );
+            if n == 0 {
+                id
+            } else {
+                format!("{}{:02}", id, t.second)
+            }
+        }
+        NamingScheme::UuidShort => {
+            let nanos = now
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0);
+            format!("{:08x}", pseudo_random(nanos ^ n as u64) as u32)
+        }
+    }
+}
+
+/// Finds the lowest `new_note_N` suffix not already taken
+///
+/// Names may be given with or without their `.md` extension. Only names that
+/// are exactly `new_note_` followed by a number, written the way clife writes
+/// it (digits only, no leading zeros), count; anything else, like
+/// `new_note_plan.md` or `new_note_07.md`, is ignored.
+///
+/// # Arguments
+///
+/// * `existing` - the file names of the existing notes
+fn next_note_suffix(existing: &[String]) -> usize {
+    let taken: Vec<usize> = existing
+        .iter()
+        .filter_map(|name| {
+            let digits = name
+                .strip_suffix(".md")
+                .unwrap_or(name)
+                .strip_prefix("new_note_")?;
+            let canonical = !digits.is_empty()
+                && digits.bytes().all(|b| b.is_ascii_digit())
+                && (digits == "0" || !digits.starts_with('0'));
+            canonical.then(|| digits.parse().ok()).flatten()
+        })
+        .collect();
+    (1..).find(|n| !taken.contains(n)).unwrap_or(1)
+}
+
+/// Picks a free note name for `base` using the passed collision strategy
+///
+/// A free `base` is returned as is. Otherwise `Increment` adds `_2`, `_3` and
+/// so on, `Timestamp` adds the current UTC date and time (counting up from
+/// there if even that's taken) and `Error` fails. Names are compared with or
+/// without their `.md` extension. `base` is cut short where needed so that a
+/// suffixed name, with its `.md`, stays within `max_len` bytes.
+///
+/// # Arguments
+///
+/// * `base` - the wanted note name, without its extension
+/// * `strategy` - what to do if it's taken
+/// * `existing` - the file names already in use
+/// * `max_len` - the longest name allowed, in bytes
+fn resolve_collision(
+    base: &str,
+    strategy: CollisionStrategy,
+    existing: &[String],
+    max_len: usize,
+) -> Result<String, AppError> {
+    let taken = |stem: &str| {
+        existing
+            .iter()
+            .any(|name| name.strip_suffix(".md").unwrap_or(name) == stem)
+    };
+    let budget = max_len.saturating_sub(".md".len());
+    let with_suffix = |base: &str, suffix: &str| {
+        format!(
+            "{}{}",
+            truncate_stem(base, budget.saturating_sub(suffix.len())),
+            suffix
+        )
+    };
+    if !taken(base) {
+        return Ok(String::from(base));
+    }
+    let base = match strategy {
+        CollisionStrategy::Error => return Err(AppError::Collision(String::from(base))),
+        CollisionStrategy::Increment => String::from(base),
+        CollisionStrategy::Timestamp => {
+            let t = DateTime::from_system_time(SystemTime::now());
+            let stamp = format!(
+                "_{:04}{:02}{:02}{:02}{:02}{:02}",
+                t.year, t.month, t.day, t.hour, t.minute, t.second
+            );
+            let stamped = with_suffix(base, &stamp);
+            if !taken(&stamped) {
+                return Ok(stamped);
+            }
+            stamped
+        }
+    };
+    Ok((2..)
+        .map(|n| with_suffix(&base, &format!("_{}", n)))
+        .find(|candidate| !taken(candidate))
+        .expect("there's always a free suffix"))
+}
+
+/// Returns the names of the files in a directory
+///
+/// # Arguments
+///
+/// * `dir` - the directory to list
+fn dir_file_names(dir: &Path) -> Vec<String> {
+    read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Returns the file names of the passed notes
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn note_file_names(notes: &[Note]) -> Vec<String> {
+    notes
+        .iter()
+        .filter_map(|note| note.full_path.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Creates a new note markdown file, named by the configured naming scheme
+///
+/// Fails if `dir` isn't an existing directory or the file can't be created.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `dir` - the directory to create the note in
+/// * `note_suffix` - the number of the note to start with as a suffix
+fn create_new_note(config: &Config, dir: &Path, note_suffix: usize) -> io::Result<PathBuf> {
+    let note_path = create_named_note(config, dir, config.naming_scheme, note_suffix)?;
+    finish_creating(config, &note_path);
+    Ok(note_path)
+}
+
+/// Seeds a note file that has just been created and announces it
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the newly created, empty note
+fn seed_new_note(config: &Config, note_path: &Path) -> io::Result<()> {
+    seed_h1_title(config, note_path)?;
+    seed_project_field(config, note_path)?;
+    *JUST_CREATED
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(note_path.to_path_buf());
+    println!(
+        "New note created: {}",
+        note_path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Records a note's creation and runs `post_create_hook` on it
+///
+/// Called once the note's contents have all been written, so the hook sees
+/// the finished note.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the newly created note
+fn finish_creating(config: &Config, note_path: &Path) {
+    record_operation(Operation::Create);
+    run_hook(
+        "post_create_hook",
+        config.post_create_hook.as_deref(),
+        note_path,
+    );
+}
+
+/// Builds a note's file name from its stem, truncating it to fit `max_len`
+///
+/// The stem is cut on a character boundary so that the name, including the
+/// `.md` extension, is at most `max_len` bytes long.
+///
+/// # Arguments
+///
+/// * `stem` - the note name without its extension
+/// * `max_len` - the longest name allowed, in bytes
+fn build_note_name(stem: &str, max_len: usize) -> String {
+    format!(
+        "{}.md",
+        truncate_stem(stem, max_len.saturating_sub(".md".len()))
+    )
+}
+
+/// Cuts a stem down to at most `budget` bytes, on a character boundary
+///
+/// # Arguments
+///
+/// * `stem` - the name to cut
+/// * `budget` - the most bytes it may take up
+fn truncate_stem(stem: &str, budget: usize) -> &str {
+    let mut end = stem.len().min(budget);
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    &stem[..end]
+}
+
+/// Builds a file name the user asked for, shortening its stem to fit
+/// `max_len` and saying so if it had to
+///
+/// # Arguments
+///
+/// * `stem` - the name without its extension
+/// * `extension` - the extension to keep, without its dot
+/// * `max_len` - the longest name allowed, in bytes
+fn fit_file_name(stem: &str, extension: &str, max_len: usize) -> String {
+    let wanted = format!("{}.{}", stem, extension);
+    let budget = max_len.saturating_sub(extension.len() + 1);
+    let name = format!("{}.{}", truncate_stem(stem, budget), extension);
+    if name != wanted {
+        println!(
+            "'{}' is longer than max_name_len ({}), so it was shortened to '{}'",
+            wanted, max_len, name
+        );
+    }
+    name
+}
+
+/// Creates a new note markdown file named by the passed naming scheme
+///
+/// A name that's taken is handled by `collision_strategy`, the original name
+/// being shortened to leave room for any suffix it adds. Fails if `dir`
+/// isn't an existing directory or the file can't be created. The caller
+/// calls `finish_creating` once it has written the note's contents.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `dir` - the directory to create the note in
+/// * `scheme` - how to name the note
+/// * `note_suffix` - the number of the note to start with as a suffix
+fn create_named_note(
+    config: &Config,
+    dir: &Path,
+    scheme: NamingScheme,
+    note_suffix: usize,
+) -> io::Result<PathBuf> {
+    if !dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", dir.display()),
+        ));
+    }
+
+    let now = SystemTime::now();
+    let mut attempt = 0;
+    let mut note_created = false;
+    let mut note_path = PathBuf::from(dir);
+    while !note_created {
+        note_path = PathBuf::from(dir);
+        let n = match scheme {
+            NamingScheme::Incremental => note_suffix + attempt,
+            _ => attempt,
+        };
+        let mut note_name = build_note_name(&generate_note_id(scheme, now, n), config.max_name_len);
+        note_path.push(&note_name);
+        if note_path.exists() {
+            if config.collision_strategy == CollisionStrategy::Increment {
+                println!("{} already exists, trying again ...", note_name);
+                attempt += 1;
+                continue;
+            }
+            let stem = resolve_collision(
+                note_name.trim_end_matches(".md"),
+                config.collision_strategy,
+                &dir_file_names(dir),
+                config.max_name_len,
+            )
+            .map_err(|e| io::Error::new(io::ErrorKind::AlreadyExists, e.to_string()))?;
+            note_name = format!("{}.md", stem);
+            note_path.set_file_name(&note_name);
+        }
+        File::create_new(&note_path)?;
+        seed_new_note(config, &note_path)?;
+        note_created = true;
+    }
+    Ok(note_path)
+}
+
+/// Fills in a template's `{{title}}`, `{{date}}` and `{{time}}` placeholders
+///
+/// Dates and times are in UTC.
+///
+/// # Arguments
+///
+/// * `template` - the template contents
+/// * `title` - the title of the note being created
+/// * `now` - the current time
+fn render_template(template: &str, title: &str, now: SystemTime) -> String {
+    let t = DateTime::from_system_time(now);
+    template
+        .replace("{{title}}", title)
+        .replace(
+            "{{date}}",
+            &format!("{:04}-{:02}-{:02}", t.year, t.month, t.day),
+        )
+        .replace("{{time}}", &format!("{:02}:{:02}", t.hour, t.minute))
+}
+
+/// Creates a note at the root without prompting, seeding it from a template
+///
+/// A `.template.md` at the root is always used if present. Otherwise, with a
+/// template name, `templates/<name>.md` is rendered into the note, and it is
+/// an error for it not to exist; without one `templates/default.md` is used
+/// if it exists, else the note is left blank.
+/// With a note name the note is called `<name>.md`, and it is an error for
+/// that to exist already; otherwise the naming scheme picks the name.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `template` - the name of the template to use, if any
+/// * `name` - the name of the note, without its extension, if any
+/// * `note_suffix` - the number of the note to start with as a suffix
+fn create_note_from_template(
+    config: &Config,
+    template: Option<&str>,
+    name: Option<&str>,
+    note_suffix: usize,
+) -> io::Result<PathBuf> {
+    let note_path = start_note_from_template(config, template, name, note_suffix)?;
+    finish_creating(config, &note_path);
+    Ok(note_path)
+}
+
+/// Does the work of `create_note_from_template`, short of `finish_creating`
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `template` - the name of the template to use, if any
+/// * `name` - the name of the note, without its extension, if any
+/// * `note_suffix` - the number of the note to start with as a suffix
+fn start_note_from_template(
+    config: &Config,
+    template: Option<&str>,
+    name: Option<&str>,
+    note_suffix: usize,
+) -> io::Result<PathBuf> {
+    let project_template = config.root_dir.join(PROJECT_TEMPLATE);
+    let template_path = match template {
+        Some(_) if project_template.is_file() => Some(project_template),
+        Some(name) => {
+            let path = templates_dir(config).join(format!("{}.md", name));
+            if !path.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no template named '{}' at {}", name, path.display()),
+                ));
+            }
+            Some(path)
+        }
+        None => resolve_template(config, &config.root_dir),
+    };
+
+    let note_path = match name {
+        Some(name) => {
+            if !validate_project_name(name, config.trim_names) || name.starts_with('.') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("'{}' isn't a valid note name", name),
+                ));
+            }
+            let note_path =
+                config
+                    .root_dir
+                    .join(fit_file_name(name.trim(), "md", config.max_name_len));
+            File::create_new(&note_path)?;
+            seed_new_note(config, &note_path)?;
+            note_path
+        }
+        None => create_named_note(config, &config.root_dir, config.naming_scheme, note_suffix)?,
+    };
+    if let Some(template_path) = template_path {
+        apply_template(config, &note_path, &template_path)?;
+    }
+    Ok(note_path)
+}
+
+/// Turns a file stem into a title, e.g. `my_great_note` into `My Great Note`
+///
+/// # Arguments
+///
+/// * `stem` - the file name without its extension
+fn title_from_stem(stem: &str) -> String {
+    stem.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Puts an H1 title made from `stem` after any frontmatter in `contents`
+///
+/// # Arguments
+///
+/// * `contents` - the note contents
+/// * `stem` - the note's file name without its extension
+fn with_h1_title(contents: &str, stem: &str) -> String {
+    let body = split_frontmatter(contents).1;
+    format!(
+        "{}# {}\n\n{}",
+        &contents[..contents.len() - body.len()],
+        title_from_stem(stem),
+        body
+    )
+}
+
+/// Writes an H1 title into a freshly created note if `seed_h1_title` is set
+///
+/// The note is written by `write_text`, so it holds just `# Title\n`, without
+/// the blank line `with_h1_title` puts after the title.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the empty note
+fn seed_h1_title(config: &Config, note_path: &Path) -> io::Result<()> {
+    if !config.seed_h1_title {
+        return Ok(());
+    }
+    let stem = note_path.file_stem().unwrap_or_default().to_string_lossy();
+    write_text(note_path, &with_h1_title("", &stem))
+}
+
+/// Writes a `project:` field into a freshly created note if
+/// `sync_project_on_create` is set
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the new note
+fn seed_project_field(config: &Config, note_path: &Path) -> io::Result<()> {
+    if !config.sync_project_on_create {
+        return Ok(());
+    }
+    let contents = std::fs::read_to_string(note_path)?;
+    write_text(
+        note_path,
+        &set_project_field(&contents, &project_for(config, note_path)),
+    )
+}
+
+/// Makes text end in exactly one newline, collapsing any extra ones
+///
+/// Empty text stays empty, so new notes can still be told apart as empty.
+///
+/// # Arguments
+///
+/// * `s` - the text to fix up
+fn ensure_trailing_newline(s: &str) -> String {
+    let trimmed = s.trim_end_matches(['\n', '\r']);
+    if trimmed.is_empty() {
+        return String::new();
+    }
+    format!("{}\n", trimmed)
+}
+
+/// Writes text to a file, making sure it ends in exactly one newline
+///
+/// # Arguments
+///
+/// * `path` - the file to write
+/// * `contents` - the text to write
+fn write_text(path: &Path, contents: &str) -> io::Result<()> {
+    atomic_write(path, ensure_trailing_newline(contents).as_bytes())
+}
+
+/// Returns the temporary file `atomic_write` writes next to `path`
+///
+/// # Arguments
+///
+/// * `path` - the file being written
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Held while `atomic_write` writes a file, so on Ctrl-C clife waits for the
+/// write to finish before it exits
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Writes a file by writing a temporary file beside it and renaming it into
+/// place, so the file holds either its old or its new contents in full even
+/// if clife is killed part way through
+///
+/// A symlink is written through, so the file it points to gets the new
+/// contents and the link stays a link. The file keeps its permissions.
+///
+/// # Arguments
+///
+/// * `path` - the file to write
+/// * `contents` - the bytes to write
+fn atomic_write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let _writing = WRITE_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    atomic_write_locked(path, contents)
+}
+
+/// `atomic_write` for callers already holding `WRITE_LOCK`
+///
+/// # Arguments
+///
+/// * `path` - the file to write
+/// * `contents` - the bytes to write
+fn atomic_write_locked(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = if path.symlink_metadata().is_ok() {
+        path.canonicalize()?
+    } else {
+        path.to_path_buf()
+    };
+    let permissions = std::fs::metadata(&path).map(|m| m.permissions()).ok();
+    let temp = temp_path_for(&path);
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            io::Write::write_all(&mut file, contents)?;
+            if let Some(permissions) = permissions {
+                file.set_permissions(permissions)?;
+            }
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temp, &path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+        return result;
+    }
+    sync_parent_dir(&path)
+}
+
+/// Flushes a directory entry change, such as a rename, to disk
+///
+/// # Arguments
+///
+/// * `path` - the file whose directory to sync
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Renders a template into a note, titling it after the note's file name
+///
+/// The rendered text is wrapped at `wrap_column` when that is set, and given
+/// LF line endings when `normalize_newlines` is. With `seed_h1_title` an H1
+/// title goes after the template's frontmatter, and with
+/// `sync_project_on_create` a `project:` field goes in it.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the note to write
+/// * `template_path` - the template to render
+fn apply_template(config: &Config, note_path: &Path, template_path: &Path) -> io::Result<()> {
+    let template = std::fs::read_to_string(template_path)?;
+    let title = note_path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut contents = render_template(&template, &title, SystemTime::now());
+    if let Some(width) = config.wrap_column {
+        contents = wrap_text(&contents, width);
+    }
+    if config.normalize_newlines {
+        contents = normalize_newlines(&contents);
+    }
+    if config.seed_h1_title {
+        contents = with_h1_title(&contents, &title);
+    }
+    if config.sync_project_on_create {
+        contents = set_project_field(&contents, &project_for(config, note_path));
+    }
+    write_text(note_path, &contents)
+}
+
+/// Wraps lines longer than `width` at word boundaries
+///
+/// Shorter lines are left alone, as are frontmatter, headings, tables and
+/// anything inside code fences. Wrapped list items keep their continuation
+/// lines indented under the item's text.
+///
+/// # Arguments
+///
+/// * `text` - the text to wrap
+/// * `width` - the column to wrap at
+fn wrap_text(text: &str, width: usize) -> String {
+    let body = split_frontmatter(text).1;
+    let mut wrapped = String::from(&text[..text.len() - body.len()]);
+
+    let mut in_fence = false;
+    for line in body.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[content.len()..];
+        let trimmed = content.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let keep = in_fence
+            || content.chars().count() <= width
+            || trimmed.starts_with('#')
+            || trimmed.starts_with('|')
+            || trimmed.starts_with("```")
+            || trimmed.starts_with("~~~");
+        if keep {
+            wrapped.push_str(line);
+            continue;
+        }
+
+        let marker_len = ["- ", "* ", "+ ", "> "]
+            .iter()
+            .find(|marker| trimmed.starts_with(*marker))
+            .map(|marker| marker.len())
+            .or_else(|| {
+                let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+                (digits > 0 && trimmed[digits..].starts_with(". ")).then_some(digits + 2)
+            })
+            .unwrap_or(0);
+        let indent = content.len() - trimmed.len() + marker_len;
+        let continuation = " ".repeat(indent);
+
+        let mut current = String::from(&content[..indent]);
+        let mut current_len = indent;
+        let mut line_has_word = false;
+        for word in content[indent..].split_whitespace() {
+            let word_len = word.chars().count();
+            if line_has_word && current_len + 1 + word_len > width {
+                wrapped.push_str(&current);
+                wrapped.push('\n');
+                current = continuation.clone();
+                current_len = indent;
+                line_has_word = false;
+            }
+            if line_has_word {
+                current.push(' ');
+                current_len += 1;
+            }
+            current.push_str(word);
+            current_len += word_len;
+            line_has_word = true;
+        }
+        wrapped.push_str(&current);
+        wrapped.push_str(ending);
+    }
+    wrapped
+}
+
+/// Wraps text in a markdown code fence, optionally tagged with a language
+///
+/// The fence is made longer than any run of backticks in the text, so fences
+/// inside it can't close it early.
+///
+/// # Arguments
+///
+/// * `body` - the text to wrap
+/// * `lang` - the language the fence is tagged with, if any
+fn fence_content(body: &str, lang: Option<&str>) -> String {
+    let longest_run = body.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat((longest_run + 1).max(3));
+    let mut fenced = format!("{}{}\n{}", fence, lang.unwrap_or(""), body);
+    if !body.is_empty() && !body.ends_with('\n') {
+        fenced.push('\n');
+    }
+    fenced.push_str(&fence);
+    fenced.push('\n');
+    fenced
+}
+
+/// Creates a note like `create_note_from_template`, then appends everything
+/// read from `input` to its body
+///
+/// The input is added as is, unless `fence` is set, in which case it's
+/// wrapped in a code fence by `fence_content`.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `template` - the name of the template to use, if any
+/// * `name` - the name of the note, without its extension, if any
+/// * `note_suffix` - the number of the note to start with as a suffix
+/// * `fence` - whether to fence the input, and with which language
+/// * `input` - where the note's body is read from, e.g. piped stdin
+fn create_note_from_input(
+    config: &Config,
+    template: Option<&str>,
+    name: Option<&str>,
+    note_suffix: usize,
+    fence: Option<Option<&str>>,
+    input: &mut impl io::Read,
+) -> io::Result<PathBuf> {
+    let mut body = String::new();
+    input.read_to_string(&mut body)?;
+    let note_path = start_note_from_template(config, template, name, note_suffix)?;
+    let mut contents = std::fs::read_to_string(&note_path)?;
+    if let Some(lang) = fence {
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        body = fence_content(&body, lang);
+    }
+    contents.push_str(&body);
+    write_text(&note_path, &contents)?;
+    finish_creating(config, &note_path);
+    Ok(note_path)
+}
+
+/// Creates a timestamped note in the scratch directory, creating it if needed
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn create_scratch_note(config: &Config) -> io::Result<PathBuf> {
+    let dir = config.root_dir.join(&config.scratch_dir);
+    create_dir_all(&dir)?;
+    let note_path = create_named_note(config, &dir, NamingScheme::Timestamp, 1)?;
+    finish_creating(config, &note_path);
+    Ok(note_path)
+}
+
+/// Creates a scratch note and opens it, removing it again if left empty
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn run_scratch(config: &Config) {
+    let note_path = match create_scratch_note(config) {
+        Ok(note_path) => note_path,
+        Err(e) => {
+            println!("Failed to create scratch note: {}", e);
+            exit(1);
+        }
+    };
+    let _ = open_in_editor(config, &note_path);
+    // A non-blocking editor hasn't had the chance to write anything yet
+    if config.editor_blocks && is_empty_note(&note_path) {
+        let note = Note {
+            trunc_path: note_path
+                .strip_prefix(&config.root_dir)
+                .unwrap_or(&note_path)
+                .to_path_buf(),
+            full_path: note_path,
+            root: config.root_dir.clone(),
+        };
+        remove_note(config, &note);
+    }
+}
+
+/// Interactively creates a new note, asking which project it belongs in
+///
+/// The note starts from the template `resolve_template` picks for the
+/// project, if any. If creation fails the error is shown and the user is asked again, up to
+/// `MAX_CREATE_ATTEMPTS` times before the last error is returned.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `input` - where to read answers from, usually stdin
+/// * `note_suffix` - the number of the note to start with as a suffix
+fn prompt_and_create_note(
+    config: &Config,
+    input: &mut impl BufRead,
+    note_suffix: usize,
+) -> io::Result<PathBuf> {
+    let mut attempts = 0;
+    loop {
+        ask(
+            config,
+            "Which project should the note go in? (leave blank for the root)",
+        );
+        let location = read_input(input);
+        let dir = config.root_dir.join(location.trim());
+        match create_named_note(config, &dir, config.naming_scheme, note_suffix) {
+            Ok(note_path) => {
+                if let Some(template_path) = resolve_template(config, &dir) {
+                    apply_template(config, &note_path, &template_path)?;
+                }
+                finish_creating(config, &note_path);
+                return Ok(note_path);
+            }
+            Err(e) => {
+                attempts += 1;
+                println!("Failed to create note: {}", e);
+                if attempts >= MAX_CREATE_ATTEMPTS {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// Splits an editor command into words on whitespace
+///
+/// Single or double quotes keep whitespace inside a word, e.g.
+/// `"/opt/My Editor/bin/edit" --wait`.
+///
+/// # Arguments
+///
+/// * `command` - the editor command, optionally with arguments
+fn split_command(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in command.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Builds the command that opens every passed file in the editor
+///
+/// The editor is split into a program and its arguments by `split_command`,
+/// and each has `~` and environment variables expanded. Each path is then
+/// passed as its own argument exactly as is, never split or expanded, so
+/// paths with spaces or shell characters are safe.
+///
+/// # Arguments
+///
+/// * `editor` - the editor program to run, optionally with arguments
+/// * `paths` - the files to open
+fn build_editor_command(editor: &str, paths: &[&Path]) -> std::process::Command {
+    let mut words = split_command(editor)
+        .into_iter()
+        .map(|word| expand_path(&word));
+    let mut command = std::process::Command::new(words.next().unwrap_or_default());
+    command.args(words);
+    command.args(paths);
+    command
+}
+
+/// Picks the editor commands to try for a file
+///
+/// Uses the `editor_by_extension` entry for the file's extension if there is
+/// one, and the `editor` fallback chain otherwise.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `path` - the file about to be opened
+fn editor_for<'a>(config: &'a Config, path: &Path) -> &'a [String] {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| config.editor_by_extension.get(extension))
+        .map(std::slice::from_ref)
+        .unwrap_or(&config.editor)
+}
+
+/// Returns the first editor whose program can be found
+///
+/// Each candidate is split into a program and its arguments like in
+/// `build_editor_command`. Programs given as a path must exist, and bare
+/// program names must be found in one of the `PATH` directories.
+///
+/// # Arguments
+///
+/// * `candidates` - the editor commands to try, in order
+fn first_available_editor(candidates: &[String]) -> Option<String> {
+    let path_dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default();
+    candidates
+        .iter()
+        .find(|candidate| {
+            let Some(program) = split_command(candidate).first().map(|p| expand_path(p)) else {
+                return false;
+            };
+            if program.contains(std::path::MAIN_SEPARATOR) {
+                Path::new(&program).is_file()
+            } else {
+                path_dirs.iter().any(|dir| dir.join(&program).is_file())
+            }
+        })
+        .cloned()
+}
+
+/// Picks the editor for a file from its fallback chain
+///
+/// Prints which editor was picked if `verbose` is set, and every editor that
+/// was tried if none of them are available.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `path` - the file about to be opened
+fn pick_editor(config: &Config, path: &Path) -> io::Result<String> {
+    let candidates = editor_for(config, path);
+    match first_available_editor(candidates) {
+        Some(editor) => {
+            if config.verbose {
+                println!("Using editor {}", editor);
+            }
+            Ok(editor)
+        }
+        None => {
+            println!("No editor found, tried: {}", candidates.join(", "));
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "none of the configured editors are available",
+            ))
+        }
+    }
+}
+
+/// Converts CRLF and lone CR line endings to LF
+///
+/// # Arguments
+///
+/// * `s` - the text to convert
+fn normalize_newlines(s: &str) -> String {
+    s.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Rewrites a note with LF line endings if `normalize_newlines` is set
+///
+/// Binary files and notes that already use LF are left untouched.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the note to rewrite
+fn normalize_note_newlines(config: &Config, note_path: &Path) -> io::Result<()> {
+    if !config.normalize_newlines {
+        return Ok(());
+    }
+    match read_text(note_path)? {
+        Some(contents) if contents.contains('\r') => {
+            write_text(note_path, &normalize_newlines(&contents))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Opens the passed note in the editor, waiting for it to close
+///
+/// The first editor of the fallback chain that's installed is used. Returns
+/// the editor's exit status, or an error if none could be started.
+/// If `editor_blocks` isn't set the editor is only spawned, and a successful
+/// status is returned straight away. Otherwise the note's line endings are
+/// normalized once the editor exits, if `normalize_newlines` is set.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the note file to open
+fn open_in_editor(config: &Config, note_path: &Path) -> io::Result<ExitStatus> {
+    let mut command = build_editor_command(&pick_editor(config, note_path)?, &[note_path]);
+    if !config.editor_blocks {
+        return command.spawn().map(|_| ExitStatus::default());
+    }
+    let status = wait_in_foreground(&mut command)?;
+    let _ = normalize_note_newlines(config, note_path);
+    warn_word_budget(config, note_path);
+    Ok(status)
+}
+
+/// Opens several notes in the editor
+///
+/// They're opened in a single editor invocation if `editor_multi_file` is set
+/// and they all use the same editor, and one after another otherwise.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_paths` - the note files to open
+fn open_all_in_editor(config: &Config, note_paths: &[&Path]) -> io::Result<()> {
+    let editor = note_paths.first().map(|path| editor_for(config, path));
+    let one_editor = note_paths
+        .iter()
+        .all(|path| Some(editor_for(config, path)) == editor);
+    if !config.editor_blocks && config.editor_multi_file && one_editor {
+        let editor = pick_editor(config, note_paths[0])?;
+        build_editor_command(&editor, note_paths).spawn()?;
+    } else if config.editor_multi_file && one_editor {
+        let editor = pick_editor(config, note_paths[0])?;
+        wait_in_foreground(&mut build_editor_command(&editor, note_paths))?;
+        for note_path in note_paths {
+            let _ = normalize_note_newlines(config, note_path);
+        }
+    } else {
+        for note_path in note_paths {
+            open_in_editor(config, note_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// How much of a file `is_probably_text` looks at
+const TEXT_SNIFF_LEN: usize = 8192;
+
+/// Guesses whether bytes are text rather than a binary file
+///
+/// Only the first few KB are checked: they must contain no NUL bytes and be
+/// valid UTF-8, allowing for a character cut off at the end.
+///
+/// # Arguments
+///
+/// * `bytes` - the file contents
+fn is_probably_text(bytes: &[u8]) -> bool {
+    let start = &bytes[..bytes.len().min(TEXT_SNIFF_LEN)];
+    if start.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(start) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && start.len() == TEXT_SNIFF_LEN,
+    }
+}
+
+/// Reads a file as text, returning None if it looks like a binary file
+///
+/// # Arguments
+///
+/// * `path` - the file to read
+fn read_text(path: &Path) -> io::Result<Option<String>> {
+    let bytes = std::fs::read(path)?;
+    if !is_probably_text(&bytes) {
+        return Ok(None);
+    }
+    String::from_utf8(bytes).map(Some).or(Ok(None))
+}
+
+/// Returns if the file at the passed path has no contents
+///
+/// Files that can't be read are not considered empty.
+///
+/// # Arguments
+///
+/// * `path` - the file to check
+fn is_empty_note(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0)
+}
+
+/// A note as it was recorded in the metadata cache
+#[derive(Debug, PartialEq)]
+struct CachedNote {
+    /// The note's path relative to the root
+    trunc_path: PathBuf,
+    /// When the note was last modified, in seconds since the epoch
+    modified: u64,
+}
+
+/// How the notes on disk differ from those in the metadata cache
+#[derive(Debug, Default, PartialEq)]
+struct ScanDiff {
+    /// Notes that weren't in the cache
+    added: Vec<PathBuf>,
+    /// Cached notes that no longer exist
+    removed: Vec<PathBuf>,
+    /// Notes modified more recently than the cache says
+    changed: Vec<PathBuf>,
+}
+
+/// Returns the file the metadata cache is kept in
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn cache_path(config: &Config) -> PathBuf {
+    config.root_dir.join(".cache")
+}
+
+/// Returns when a file was last modified, in seconds since the epoch
+///
+/// # Arguments
+///
+/// * `path` - the file to check
+fn modified_secs(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Reads the metadata cache, or None if there isn't one yet
+///
+/// Each line is a modified time and a relative path separated by a tab;
+/// lines that don't parse are skipped.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn load_cache(config: &Config) -> Option<Vec<CachedNote>> {
+    let contents = std::fs::read_to_string(cache_path(config)).ok()?;
+    Some(
+        contents
+            .lines()
+            .filter_map(|line| {
+                let (modified, trunc_path) = line.split_once('\t')?;
+                Some(CachedNote {
+                    trunc_path: PathBuf::from(trunc_path),
+                    modified: modified.parse().ok()?,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Records every note's modified time in the metadata cache
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn save_cache(config: &Config, notes: &[Note]) -> io::Result<()> {
+    atomic_write(&cache_path(config), render_cache(notes).as_bytes())
+}
+
+/// Renders the metadata cache's contents, one `modified<TAB>path` line per
+/// note
+///
+/// # Arguments
+///
+/// * `notes` - the notes to cache
+fn render_cache(notes: &[Note]) -> String {
+    notes
+        .iter()
+        .map(|note| {
+            format!(
+                "{}\t{}\n",
+                modified_secs(&note.full_path),
+                note.trunc_path.display()
+            )
+        })
+        .collect()
+}
+
+/// Rebuilds the metadata cache from scratch
+///
+/// Returns how many notes were indexed.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn reindex(config: &Config) -> io::Result<usize> {
+    match std::fs::remove_file(cache_path(config)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let notes = create_note_objects(config);
+    save_cache(config, &notes)?;
+    Ok(notes.len())
+}
+
+/// Compares a cached scan with the current one
+///
+/// # Arguments
+///
+/// * `old` - the notes recorded in the cache
+/// * `new` - the notes found now
+fn diff_scans(old: &[CachedNote], new: &[Note]) -> ScanDiff {
+    let mut diff = ScanDiff::default();
+    for note in new {
+        match old
+            .iter()
+            .find(|cached| cached.trunc_path == note.trunc_path)
+        {
+            None => diff.added.push(note.trunc_path.clone()),
+            Some(cached) if modified_secs(&note.full_path) > cached.modified => {
+                diff.changed.push(note.trunc_path.clone())
+            }
+            Some(_) => {}
+        }
+    }
+    for cached in old {
+        if !new.iter().any(|note| note.trunc_path == cached.trunc_path) {
+            diff.removed.push(cached.trunc_path.clone());
+        }
+    }
+    diff
+}
+
+/// Prints the notes that changed, appeared or disappeared since the last run
+///
+/// # Arguments
+///
+/// * `diff` - the differences to print
+fn print_scan_diff(diff: &ScanDiff) {
+    for path in &diff.changed {
+        println!("changed since last run: {}", path.display());
+    }
+    for path in &diff.added {
+        println!("new since last run: {}", path.display());
+    }
+    for path in &diff.removed {
+        println!("deleted since last run: {}", path.display());
+    }
+}
+
+/// Returns the directory deleted notes are moved to
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn trash_dir(config: &Config) -> PathBuf {
+    config.root_dir.join(".trash")
+}
+
+/// C's `struct timeval`
+#[cfg(unix)]
+#[repr(C)]
+struct Timeval {
+    tv_sec: std::os::raw::c_long,
+    #[cfg(target_os = "macos")]
+    tv_usec: i32,
+    #[cfg(not(target_os = "macos"))]
+    tv_usec: std::os::raw::c_long,
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn lutimes(path: *const std::os::raw::c_char, times: *const [Timeval; 2]) -> i32;
+}
+
+/// Sets a file's modified time to now, without following symlinks
+///
+/// A symlink gets its own time set rather than its target's, since the
+/// target may be shared with notes outside the trash. Where that isn't
+/// possible the symlink is left alone. A file is only opened for reading,
+/// which is enough to set its time when clife owns it, so read-only notes get
+/// their time set too.
+///
+/// # Arguments
+///
+/// * `path` - the file to touch
+fn touch_without_following(path: &Path) -> io::Result<()> {
+    if !path.symlink_metadata()?.file_type().is_symlink() {
+        return File::open(path)?.set_modified(SystemTime::now());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if unsafe { lutimes(c_path.as_ptr(), std::ptr::null()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Moves a note into the trash, keeping its path relative to the root
+///
+/// The trashed file's modified time is set to now, so its age in the trash
+/// can be told later. Failing to set it only warns, since the note is in the
+/// trash by then. A note with the same path already in the trash gets a
+/// numbered suffix rather than being overwritten.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to trash
+fn trash_note(config: &Config, note: &Note) -> io::Result<PathBuf> {
+    let mut dest = trash_dir(config).join(&note.trunc_path);
+    let stem = dest
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let extension = dest.extension().map(|e| e.to_string_lossy().into_owned());
+    let mut n = 1;
+    while dest.exists() {
+        let name = match &extension {
+            Some(ext) => format!("{}_{}.{}", stem, n, ext),
+            None => format!("{}_{}", stem, n),
+        };
+        dest.set_file_name(name);
+        n += 1;
+    }
+
+    create_dir_all(dest.parent().unwrap_or(&trash_dir(config)))?;
+    std::fs::rename(&note.full_path, &dest)?;
+    if let Err(e) = touch_without_following(&dest) {
+        println!(
+            "Warning: couldn't update the trash time of {}: {}",
+            dest.display(),
+            e
+        );
+    }
+    Ok(dest)
+}
+
+/// Returns the file deletes are recorded in
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn audit_log_path(config: &Config) -> PathBuf {
+    config.root_dir.join(".audit.log")
+}
+
+/// Records a note's deletion in the audit log
+///
+/// If `log_deleted_content` is set the note's contents, cut off after
+/// `log_deleted_content_max` bytes, are added in a fenced block below the
+/// entry so they can be recovered by hand. The fence is longer than any run
+/// of backticks in the contents, so code blocks in the note can't end it.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note about to be deleted
+fn log_delete(config: &Config, note: &Note) -> io::Result<()> {
+    let t = DateTime::from_system_time(SystemTime::now());
+    let mut entry = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z delete {}\n",
+        t.year,
+        t.month,
+        t.day,
+        t.hour,
+        t.minute,
+        t.second,
+        note.trunc_path.display()
+    );
+    if config.log_deleted_content {
+        let bytes = std::fs::read(&note.full_path)?;
+        let contents = String::from_utf8_lossy(&bytes);
+        let mut end = contents.len().min(config.log_deleted_content_max);
+        while !contents.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut kept = String::from(&contents[..end]);
+        if !kept.is_empty() && !kept.ends_with('\n') {
+            kept.push('\n');
+        }
+        if end < contents.len() {
+            kept.push_str(&format!("[cut off, {} bytes in total]\n", bytes.len()));
+        }
+        entry.push_str(&fence_content(&kept, None));
+    }
+    let mut log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(audit_log_path(config))?;
+    io::Write::write_all(&mut log, entry.as_bytes())
+}
+
+/// Returns if the file at `path` has `locked: true` in its frontmatter
+///
+/// # Arguments
+///
+/// * `path` - the file to check
+fn path_is_locked(path: &Path) -> bool {
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    split_frontmatter(&contents)
+        .0
+        .and_then(|frontmatter| frontmatter_value(frontmatter, "locked"))
+        .is_some_and(|value| value == "true")
+}
+
+/// Returns if a note is locked against being deleted, moved or overwritten
+///
+/// # Arguments
+///
+/// * `note` - the note to check
+fn is_locked(note: &Note) -> bool {
+    path_is_locked(&note.full_path)
+}
+
+/// Fails if the file at `path` is locked, unless `--force` was given
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `path` - the file about to be changed
+fn check_unlocked(config: &Config, path: &Path) -> io::Result<()> {
+    if config.override_locks || !path_is_locked(path) {
+        return Ok(());
+    }
+    let shown = path.strip_prefix(&config.root_dir).unwrap_or(path);
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "{} is locked, pass --force to change it anyway",
+            shown.display()
+        ),
+    ))
+}
+
+/// Returns if a note comes from one of the `extra_roots`, which clife never
+/// changes
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to check
+fn is_read_only(config: &Config, note: &Note) -> bool {
+    note.root != config.root_dir
+}
+
+/// Returns if a note is a symlink to a file kept elsewhere
+///
+/// # Arguments
+///
+/// * `note` - the note to check
+fn is_symlink(note: &Note) -> bool {
+    std::fs::symlink_metadata(&note.full_path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Fails if a note is read-only or locked, unless `--force` was given for a
+/// locked or symlinked note
+///
+/// Every change to a note's file goes through this first. A symlinked note
+/// changed with `--force` is written through the link, see `atomic_write`.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note about to be changed
+fn check_writable(config: &Config, note: &Note) -> io::Result<()> {
+    if is_read_only(config, note) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is in {}, which is read-only",
+                note.trunc_path.display(),
+                note.root.display()
+            ),
+        ));
+    }
+    if is_symlink(note) && !config.override_locks {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is a symlink, pass --force to change it anyway",
+                note.trunc_path.display()
+            ),
+        ));
+    }
+    check_unlocked(config, &note.full_path)
+}
+
+/// Runs a hook command for a note, if one is set
+///
+/// The command is run by `sh` with the note's path as its argument, also
+/// available as `CLIFE_NOTE_PATH`. A hook that fails only gets a warning, as
+/// whatever it was run for has already happened.
+///
+/// # Arguments
+///
+/// * `name` - the hook's setting, used in warnings
+/// * `hook` - the command to run, if any
+/// * `note_path` - the note the hook is run for
+fn run_hook(name: &str, hook: Option<&str>, note_path: &Path) {
+    let Some(hook) = hook else {
+        return;
+    };
+    let status = wait_in_foreground(
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", hook))
+            .arg("clife")
+            .arg(note_path)
+            .env("CLIFE_NOTE_PATH", note_path),
+    );
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => println!("Warning: {} exited with {}", name, status),
+        Err(e) => println!("Warning: couldn't run {}: {}", name, e),
+    }
+}
+
+/// Removes a note, moving it to the trash if `use_trash` is set
+///
+/// Returns whether the note was removed.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to remove
+fn remove_note(config: &Config, note: &Note) -> bool {
+    if let Err(e) = check_writable(config, note) {
+        println!("{}", e);
+        return false;
+    }
+    if let Err(e) = log_delete(config, note) {
+        println!("Failed to update the audit log: {}", e);
+    }
+    let removed = if !config.use_trash {
+        delete(note.full_path.clone())
+    } else {
+        match trash_note(config, note) {
+            Ok(_) => {
+                println!("Moved {} to the trash", note.trunc_path.display());
+                record_operation(Operation::Delete);
+                true
+            }
+            Err(e) => {
+                println!("Failed to trash {}: {}", note.trunc_path.display(), e);
+                false
+            }
+        }
+    };
+    if removed {
+        run_hook(
+            "post_delete_hook",
+            config.post_delete_hook.as_deref(),
+            &note.full_path,
+        );
+    }
+    removed
+}
+
+/// Returns every file in the trash along with when it was trashed
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn trash_entries(config: &Config) -> Vec<(PathBuf, SystemTime)> {
+    let mut entries = Vec::new();
+    let mut dirs = vec![trash_dir(config)];
+    while let Some(dir) = dirs.pop() {
+        let Ok(contents) = read_dir(&dir) else {
+            continue;
+        };
+        for entry in contents.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                entries.push((path, modified));
+            }
+        }
+    }
+    entries
+}
+
+/// Returns the trash entries that have been in the trash too long
+///
+/// # Arguments
+///
+/// * `entries` - trashed files and when they were trashed
+/// * `now` - the current time
+/// * `retention_days` - how many days entries are kept for
+fn expired_trash(
+    entries: &[(PathBuf, SystemTime)],
+    now: SystemTime,
+    retention_days: u64,
+) -> Vec<PathBuf> {
+    let retention = Duration::from_secs(retention_days.saturating_mul(86_400));
+    entries
+        .iter()
+        .filter(|(_, trashed)| {
+            now.duration_since(*trashed)
+                .is_ok_and(|age| age > retention)
+        })
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Purges old notes from the trash if `trash_retention_days` is set
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn purge_expired_trash(config: &Config) {
+    let Some(retention_days) = config.trash_retention_days else {
+        return;
+    };
+    let expired = expired_trash(&trash_entries(config), SystemTime::now(), retention_days);
+    let removed = expired
+        .iter()
+        .filter(|path| remove_file(path).is_ok())
+        .count();
+    if removed > 0 {
+        println!(
+            "Purged {} notes older than {} days from the trash",
+            removed, retention_days
+        );
+    }
+}
+
+/// Permanently removes everything in the trash after confirming
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `input` - where to read answers from, usually stdin
+fn empty_trash(config: &Config, input: &mut impl BufRead) -> io::Result<()> {
+    let count = trash_entries(config).len();
+    if count == 0 {
+        println!("The trash is already empty");
+        return Ok(());
+    }
+    let question = format!("Permanently delete the {} notes in the trash?", count);
+    if !confirm(input, &question) {
+        println!("Cancelling ...");
+        return Ok(());
+    }
+    std::fs::remove_dir_all(trash_dir(config))?;
+    println!("Emptied the trash");
+    Ok(())
+}
+
+/// Returns every note that has no contents
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn empty_notes(notes: &[Note]) -> Vec<&Note> {
+    notes
+        .iter()
+        .filter(|note| is_empty_note(&note.full_path))
+        .collect()
+}
+
+/// Returns the most recently modified `new_note_*` note, as the best guess at
+/// the note created last
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn last_created_note(notes: &[Note]) -> Option<&Note> {
+    notes
+        .iter()
+        .filter(|note| {
+            note.full_path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("new_note_"))
+        })
+        .filter_map(|note| {
+            let modified = std::fs::metadata(&note.full_path).ok()?.modified().ok()?;
+            Some((modified, note))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, note)| note)
+}
+
+/// Removes the note created last, after confirming once
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn undo_create(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    let Some(note) = last_created_note(notes) else {
+        println!("Nothing to undo");
+        return;
+    };
+    if confirm(input, &format!("Remove {}?", note.trunc_path.display())) {
+        remove_note(config, note);
+    } else {
+        println!("Cancelling ...");
+    }
+}
+
+/// Groups notes whose contents are byte-for-byte identical
+///
+/// Notes are bucketed by a hash of their contents, and then compared in full
+/// so that hash collisions never group different notes. Only groups with more
+/// than one note are returned, in the order their first note appears.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn group_duplicates(notes: &[Note]) -> Vec<Vec<&Note>> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let mut buckets: Vec<(u64, Vec<u8>, Vec<&Note>)> = Vec::new();
+    for note in notes {
+        let Ok(contents) = std::fs::read(&note.full_path) else {
+            continue;
+        };
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&contents);
+        let hash = hasher.finish();
+        match buckets
+            .iter_mut()
+            .find(|(h, c, _)| *h == hash && *c == contents)
+        {
+            Some((_, _, group)) => group.push(note),
+            None => buckets.push((hash, contents, vec![note])),
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|(_, _, group)| group)
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+/// Lists groups of identical notes and removes all but the first of each
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn remove_duplicates(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    let groups = group_duplicates(notes);
+    if groups.is_empty() {
+        println!("No duplicate notes found");
+        return;
+    }
+
+    for (i, group) in groups.iter().enumerate() {
+        println!("\nDuplicate group {}:", i + 1);
+        println!("- {} (kept)", group[0].trunc_path.display());
+        for note in &group[1..] {
+            println!("- {}", note.trunc_path.display());
+        }
+    }
+    let extra: usize = groups.iter().map(|group| group.len() - 1).sum();
+    if !confirm(input, &format!("Remove the {} extra copies?", extra)) {
+        println!("Cancelling ...");
+        return;
+    }
+    for group in groups {
+        for note in &group[1..] {
+            remove_note(config, note);
+        }
+    }
+}
+
+/// Lists every empty note and removes them all after confirming
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn prune_empty_notes(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    let empty = empty_notes(notes);
+    if empty.is_empty() {
+        println!("No empty notes found");
+        return;
+    }
+
+    println!("\nEmpty notes:");
+    for note in &empty {
+        println!("- {}", note.trunc_path.display());
+    }
+    if !confirm(input, &format!("Delete these {} notes?", empty.len())) {
+        println!("Cancelling ...");
+        return;
+    }
+    for note in empty {
+        remove_note(config, note);
+    }
+}
+
+/// Offers to delete a just created note the editor failed on
+///
+/// Only applies when the editor didn't exit cleanly, the note is still empty,
+/// and `cleanup_empty_on_editor_abort` is set. Editors that don't block give
+/// no useful status and may still be open, so they never trigger it.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the note that was just created
+/// * `status` - how the editor exited
+/// * `input` - where to read answers from, usually stdin
+fn cleanup_after_editor_abort(
+    config: &Config,
+    note_path: &Path,
+    status: &io::Result<ExitStatus>,
+    input: &mut impl BufRead,
+) {
+    let aborted = !status.as_ref().is_ok_and(|status| status.success());
+    if !config.editor_blocks
+        || !aborted
+        || !config.cleanup_empty_on_editor_abort
+        || !is_empty_note(note_path)
+    {
+        return;
+    }
+
+    let question = format!(
+        "The editor exited with an error. Delete the empty note {}?",
+        note_path.display()
+    );
+    if confirm(input, &question) {
+        delete(note_path.to_path_buf());
+    }
+}
+
+/// Returns a note's title, falling back to its file stem
+///
+/// # Arguments
+///
+/// * `note` - the note to get the title of
+fn note_title(note: &Note) -> String {
+    std::fs::read_to_string(&note.full_path)
+        .ok()
+        .and_then(|contents| frontmatter_value(split_frontmatter(&contents).0?, "title"))
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| {
+            note.trunc_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+}
+
+/// Returns how a note should be shown in listings
+///
+/// # Arguments
+///
+/// * `note` - the note to show
+/// * `display` - the configured display mode
+fn display_name(note: &Note, display: DisplayMode) -> String {
+    match display {
+        DisplayMode::Path => note.trunc_path.display().to_string(),
+        DisplayMode::Title => note_title(note),
+        DisplayMode::Both => format!("{} ({})", note_title(note), note.trunc_path.display()),
+    }
+}
+
+/// Parses a selection of list numbers like `1-3,5`
+///
+/// Numbers are 1-based and must be at most `max`. Returns the selected 0-based
+/// indexes in the order given, without repeats.
+///
+/// # Arguments
+///
+/// * `selection` - the selection typed by the user
+/// * `max` - how many items there are to choose from
+fn parse_selection(selection: &str, max: usize) -> Result<Vec<usize>, String> {
+    let parse_number = |s: &str| -> Result<usize, String> {
+        match s.trim().parse::<usize>() {
+            Ok(n) if (1..=max).contains(&n) => Ok(n),
+            _ => Err(format!("'{}' is not a number from 1 to {}", s.trim(), max)),
+        }
+    };
+
+    let mut indexes = Vec::new();
+    for part in selection.split(',') {
+        if part.trim().is_empty() {
+            continue;
+        }
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (parse_number(start)?, parse_number(end)?),
+            None => {
+                let n = parse_number(part)?;
+                (n, n)
+            }
+        };
+        if start > end {
+            return Err(format!("'{}' is a backwards range", part.trim()));
+        }
+        for n in start..=end {
+            if !indexes.contains(&(n - 1)) {
+                indexes.push(n - 1);
+            }
+        }
+    }
+    if indexes.is_empty() {
+        return Err(String::from("nothing was selected"));
+    }
+    Ok(indexes)
+}
+
+/// Prints the numbered list of notes to choose from
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn print_note_options(config: &Config, notes: &[Note]) {
+    println!("Options are ... ");
+    for (i, note) in notes.iter().enumerate() {
+        println!("{}. {}", i + 1, display_name(note, config.display));
+    }
+}
+
+/// Prompts the user for one or more notes to take action on
+///
+/// Notes can be chosen by a selection of numbers like `1-3,5`, or by path.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `action` - an action to take, only used to prompt the user
+/// * `input` - where to read answers from, usually stdin
+fn prompt_for_notes<'a>(
+    config: &Config,
+    notes: &'a [Note],
+    action: &str,
+    input: &mut impl BufRead,
+) -> Vec<&'a Note> {
+    loop {
+        ask(
+            config,
+            &format!("What files would you like to {}? (e.g. 1-3,5)", action),
+        );
+        print_note_options(config, notes);
+        let answer = read_input(input);
+        let answer = answer.trim();
+        if let Some(note) = notes.iter().find(|e| e.trunc_path.to_str() == Some(answer)) {
+            return vec![note];
+        }
+        match parse_selection(answer, notes.len()) {
+            Ok(indexes) => return indexes.into_iter().map(|i| &notes[i]).collect(),
+            Err(e) => println!("Invalid selection: {}", e),
+        }
+    }
+}
+
+/// Returns if every character of `query` appears in `text`, in order
+///
+/// Case is ignored, so `mtg` matches `work/Meetings.md`.
+///
+/// # Arguments
+///
+/// * `query` - what the user typed
+/// * `text` - the text to match against
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| text.any(|c| c == q))
+}
+
+/// Returns the first lines of a note's body, for previewing it
+///
+/// Binary and unreadable notes get a placeholder instead, and locked notes
+/// are marked as such above their lines.
+///
+/// # Arguments
+///
+/// * `note` - the note to preview
+/// * `lines` - how many lines of the body to show at most
+fn preview_note(note: &Note, lines: usize) -> Vec<String> {
+    let contents = match read_text(&note.full_path) {
+        Ok(Some(contents)) => contents,
+        Ok(None) => return vec![String::from("(binary)")],
+        Err(e) => return vec![format!("(unreadable: {})", e)],
+    };
+    let mut preview = Vec::new();
+    if is_locked(note) {
+        preview.push(String::from("(locked)"));
+    }
+    preview.extend(
+        split_frontmatter(&contents)
+            .1
+            .lines()
+            .take(lines)
+            .map(String::from),
+    );
+    preview
+}
+
+/// Lays a list out next to a preview, with the selected item marked
+///
+/// The list is padded to its widest item and the preview is cut off so each
+/// row fits in `width` characters.
+///
+/// # Arguments
+///
+/// * `items` - the entries in the list on the left
+/// * `selected` - the index of the entry the preview is for
+/// * `preview` - the lines shown on the right
+/// * `width` - how many characters wide each row may be
+fn render_two_pane(items: &[String], selected: usize, preview: &[String], width: usize) -> String {
+    let list_width = items
+        .iter()
+        .map(|item| item.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(width / 2);
+    let preview_width = width.saturating_sub(list_width + 5);
+    let mut out = String::new();
+    for row in 0..items.len().max(preview.len()) {
+        let marker = if row == selected { ">" } else { " " };
+        let item: String = items
+            .get(row)
+            .map(|item| item.chars().take(list_width).collect())
+            .unwrap_or_default();
+        let line: String = preview
+            .get(row)
+            .map(|line| line.chars().take(preview_width).collect())
+            .unwrap_or_default();
+        let row = format!("{} {:<list_width$} │ {}", marker, item, line);
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Returns the message shown instead of the note list when it has more
+/// than `list_cap` notes
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `count` - how many notes would be listed
+fn over_list_cap(config: &Config, count: usize) -> Option<String> {
+    (config.list_cap > 0 && count > config.list_cap)
+        .then(|| format!("too many notes ({}); type a filter", count))
+}
+
+/// Prompts the user for a note to take action on
+///
+/// Notes can be chosen by their number in the list or by their path. `?N`
+/// shows the list next to a preview of note N. Anything else filters the
+/// list down to the notes it fuzzily matches, and an empty answer clears the
+/// filter again. Lists longer than `list_cap` aren't shown until filtered,
+/// and until then notes can't be chosen or previewed by number either.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `action` - an action to take, only used to prompt the user
+/// * `input` - where to read answers from, usually stdin
+fn prompt_for_note<'a>(
+    config: &Config,
+    notes: &'a [Note],
+    action: &str,
+    input: &mut impl BufRead,
+) -> &'a Note {
+    let mut shown: Vec<&Note> = notes.iter().collect();
+    loop {
+        ask(config, &format!("What file would you like to {}?", action));
+        let names: Vec<String> = shown
+            .iter()
+            .enumerate()
+            .map(|(i, note)| format!("{}. {}", i + 1, display_name(note, config.display)))
+            .collect();
+        let withheld = over_list_cap(config, shown.len());
+        match &withheld {
+            Some(message) => println!("{}", message),
+            None => {
+                println!("Options are ... (?N previews note N)");
+                for name in &names {
+                    println!("{}", name);
+                }
+            }
+        }
+        let answer = read_input(input);
+        let answer = answer.trim();
+        if answer.is_empty() {
+            shown = notes.iter().collect();
+            continue;
+        }
+        let is_number = answer
+            .trim_start_matches('?')
+            .trim()
+            .parse::<usize>()
+            .is_ok();
+        if withheld.is_some() && is_number {
+            println!("type a filter before choosing a note by number");
+            continue;
+        }
+        if let Some(number) = answer.strip_prefix('?') {
+            match number.trim().parse::<usize>() {
+                Ok(number) if (1..=shown.len()).contains(&number) => {
+                    let width = std::env::var("COLUMNS")
+                        .ok()
+                        .and_then(|columns| columns.parse().ok())
+                        .unwrap_or(80);
+                    let preview = preview_note(shown[number - 1], 20);
+                    print!("{}", render_two_pane(&names, number - 1, &preview, width));
+                }
+                _ => println!("'{}' isn't a note number", number),
+            }
+            continue;
+        }
+        if let Ok(number) = answer.parse::<usize>() {
+            if (1..=shown.len()).contains(&number) {
+                return shown[number - 1];
+            }
+            continue;
+        }
+        if let Some(note) = notes.iter().find(|e| e.trunc_path.to_str() == Some(answer)) {
+            return note;
+        }
+        let matching: Vec<&Note> = shown
+            .iter()
+            .copied()
+            .filter(|note| fuzzy_match(answer, &display_name(note, config.display)))
+            .collect();
+        if matching.is_empty() {
+            println!("no notes match '{}', showing every note again", answer);
+            shown = notes.iter().collect();
+            continue;
+        }
+        shown = matching;
+    }
+}
+
+/// Formats a size in bytes for people, e.g. `4.2 KiB`
+///
+/// # Arguments
+///
+/// * `bytes` - the size to format
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Counts the lines in a file's contents, including a last unterminated one
+///
+/// # Arguments
+///
+/// * `bytes` - the contents to count
+fn count_lines(bytes: &[u8]) -> usize {
+    let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+    match bytes.last() {
+        Some(b'\n') | None => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+/// Builds the question asked before deleting a note
+///
+/// Includes the note's size and line count when the file can be read.
+///
+/// # Arguments
+///
+/// * `note` - the note that may be deleted
+fn delete_question(note: &Note) -> String {
+    match std::fs::read(&note.full_path) {
+        Ok(bytes) => format!(
+            "Are you sure you want to delete {} ({}, {} lines)?",
+            note.trunc_path.display(),
+            format_size(bytes.len() as u64),
+            count_lines(&bytes)
+        ),
+        Err(_) => format!(
+            "Are you sure you want to delete {}?",
+            note.trunc_path.display()
+        ),
+    }
+}
+
+/// Confirms with the user that they want a note to be deleted
+///
+/// # Arguments
+///
+/// * `note` - the note that may be deleted
+/// * `input` - where to read the answer from, usually stdin
+fn confirm_delete(note: &Note, input: &mut impl BufRead) -> bool {
+    confirm(input, &delete_question(note))
+}
+
+/// Prompts for a note and removes it
+///
+/// Permanent deletes are always confirmed; moving a note to the trash is only
+/// confirmed if `confirm_trash` is set.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn delete_chosen_note(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    let note = prompt_for_note(config, notes, "delete", input);
+    if let Err(e) = check_writable(config, note) {
+        println!("{}", e);
+        return;
+    }
+    let ask = !config.use_trash || config.confirm_trash;
+    if ask && !confirm_delete(note, input) {
+        println!("Cancelling ...");
+        return;
+    }
+    remove_note(config, note);
+}
+
+/// Deletes the passed PathBuf
+///
+/// # Arguments:
+///
+/// * `full_path` - the file path to delete
+fn delete(full_path: PathBuf) -> bool {
+    println!("Deleting note {} ...", full_path.display());
+    let result = remove_file(full_path);
+    match result {
+        Ok(()) => {
+            println!("File successfully deleted");
+            record_operation(Operation::Delete);
+        }
+        Err(e) => {
+            panic!("Failed to delete file: {:?}", e);
+        }
+    }
+
+    true
+}
+
+/// Asks the user a yes/no question
+///
+/// # Arguments
+///
+/// * `input` - where to read answers from, usually stdin
+/// * `question` - the question to ask
+fn confirm(input: &mut impl BufRead, question: &str) -> bool {
+    loop {
+        println!("\n{}", question);
+        println!("Options are ... \n\t- (y)es\n\t- (n)o");
+        let answer = read_input(input);
+        match answer.trim() {
+            "y" => return true,
+            "n" => return false,
+            _ => {}
+        }
+    }
+}
+
+/// Splits a note into its frontmatter and body
+///
+/// Frontmatter is a block at the very top of the note fenced by `---` lines.
+/// The returned frontmatter excludes the fences.
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+fn split_frontmatter(contents: &str) -> (Option<&str>, &str) {
+    let Some(rest) = contents
+        .strip_prefix("---\n")
+        .or_else(|| contents.strip_prefix("---\r\n"))
+    else {
+        return (None, contents);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, contents)
+}
+
+/// Reads a note's contents, optionally leaving out its frontmatter
+///
+/// Fails with `InvalidData` if the note isn't valid UTF-8.
+///
+/// # Arguments
+///
+/// * `note` - the note to read
+/// * `strip_frontmatter` - whether to leave out the leading frontmatter block
+fn read_note_body(note: &Note, strip_frontmatter: bool) -> io::Result<String> {
+    let contents = std::fs::read_to_string(&note.full_path)?;
+    if !strip_frontmatter {
+        return Ok(contents);
+    }
+    Ok(String::from(split_frontmatter(&contents).1))
+}
+
+/// Writes a note's contents out, as raw bytes if it isn't valid UTF-8
+///
+/// # Arguments
+///
+/// * `note` - the note to print
+/// * `strip_frontmatter` - whether to leave out the leading frontmatter block
+/// * `out` - where to write the note, usually stdout
+fn cat_note(note: &Note, strip_frontmatter: bool, out: &mut impl io::Write) -> io::Result<()> {
+    match read_note_body(note, strip_frontmatter) {
+        Ok(body) => out.write_all(body.as_bytes()),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            io::copy(&mut File::open(&note.full_path)?, out).map(|_| ())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Finds a note by its path relative to the root, with or without `.md`
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `query` - the note's relative path
+fn find_note<'a>(notes: &'a [Note], query: &str) -> Option<&'a Note> {
+    let with_extension = format!("{}.md", query);
+    notes.iter().find(|note| {
+        let path = note.trunc_path.to_string_lossy();
+        path == query || path == with_extension
+    })
+}
+
+/// Returns the value of a `key: value` line in frontmatter
+///
+/// Surrounding quotes are removed from the value.
+///
+/// # Arguments
+///
+/// * `frontmatter` - the frontmatter text, without fences
+/// * `key` - the key to look up
+fn frontmatter_value(frontmatter: &str, key: &str) -> Option<String> {
+    for line in frontmatter.lines() {
+        if let Some((k, v)) = line.split_once(':') {
+            if k.trim() == key {
+                let v = v.trim();
+                let v = v
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(v);
+                return Some(String::from(v));
+            }
+        }
+    }
+    None
+}
+
+/// Finds the `tags:` field in frontmatter
+///
+/// Returns the byte range the field covers in the frontmatter along with its
+/// tags.
+///
+/// # Arguments
+///
+/// * `frontmatter` - the frontmatter text, without fences
+fn tags_field(frontmatter: &str) -> Option<(usize, usize, Vec<String>)> {
+    list_field(frontmatter, "tags")
+}
+
+/// Finds a list field like `tags:` or `aliases:` in frontmatter
+///
+/// The list can be inline (`key: [a, b]`), comma separated (`key: a, b`) or a
+/// block of `- a` lines. Quotes and a leading `#` are removed from each item.
+/// Returns the byte range the field covers in the frontmatter along with its
+/// items.
+///
+/// # Arguments
+///
+/// * `frontmatter` - the frontmatter text, without fences
+/// * `key` - the field to look up
+fn list_field(frontmatter: &str, key: &str) -> Option<(usize, usize, Vec<String>)> {
+    let clean = |tag: &str| {
+        let tag = tag.trim().trim_matches(['"', '\'']);
+        String::from(tag.strip_prefix('#').unwrap_or(tag))
+    };
+    let mut offset = 0;
+    let mut lines = frontmatter.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let start = offset;
+        offset += line.len();
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if name.trim() != key {
+            continue;
+        }
+
+        let value = value.trim();
+        let mut tags: Vec<String> = value
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(clean)
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        if value.is_empty() {
+            while let Some(item) = lines.next_if(|line| line.trim_start().starts_with("- ")) {
+                offset += item.len();
+                tags.push(clean(&item.trim_start()[2..]));
+            }
+        }
+        return Some((start, offset, tags));
+    }
+    None
+}
+
+/// Returns the tags in a note's frontmatter
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+fn read_tags(contents: &str) -> Vec<String> {
+    split_frontmatter(contents)
+        .0
+        .and_then(tags_field)
+        .map(|(_, _, tags)| tags)
+        .unwrap_or_default()
+}
+
+/// Where a due date falls relative to today
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DueBucket {
+    Overdue,
+    Today,
+    Upcoming,
+}
+
+impl fmt::Display for DueBucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DueBucket::Overdue => "Overdue",
+            DueBucket::Today => "Today",
+            DueBucket::Upcoming => "Upcoming",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns the date in the `due:` field of frontmatter
+///
+/// # Arguments
+///
+/// * `frontmatter` - the frontmatter text, without fences
+fn parse_due(frontmatter: &str) -> Option<Date> {
+    Date::parse(&frontmatter_value(frontmatter, "due")?)
+}
+
+/// Works out whether a due date has passed, is today, or is still to come
+///
+/// # Arguments
+///
+/// * `due` - the date the note is due
+/// * `now` - today's date
+fn classify_due(due: Date, now: Date) -> DueBucket {
+    match due.cmp(&now) {
+        std::cmp::Ordering::Less => DueBucket::Overdue,
+        std::cmp::Ordering::Equal => DueBucket::Today,
+        std::cmp::Ordering::Greater => DueBucket::Upcoming,
+    }
+}
+
+/// Returns the notes with a due date, soonest first
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn agenda(notes: &[Note]) -> Vec<(Date, &Note)> {
+    let mut due: Vec<(Date, &Note)> = notes
+        .iter()
+        .filter_map(|note| {
+            let contents = std::fs::read_to_string(&note.full_path).ok()?;
+            Some((parse_due(split_frontmatter(&contents).0?)?, note))
+        })
+        .collect();
+    due.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.trunc_path.cmp(&b.1.trunc_path)));
+    due
+}
+
+/// Renders the agenda grouped into overdue, today and upcoming notes
+///
+/// # Arguments
+///
+/// * `due` - the notes with a due date, soonest first
+/// * `today` - today's date
+fn render_agenda(due: &[(Date, &Note)], today: Date) -> String {
+    if due.is_empty() {
+        return String::from("No notes have a due date\n");
+    }
+    let mut out = String::new();
+    let mut current = None;
+    for (date, note) in due {
+        let bucket = classify_due(*date, today);
+        if current != Some(bucket) {
+            if current.is_some() {
+                out.push('\n');
+            }
+            out.push_str(&format!("{}:\n", bucket));
+            current = Some(bucket);
+        }
+        out.push_str(&format!("  {}  {}\n", date, note.trunc_path.display()));
+    }
+    out
+}
+
+/// Returns the notes carrying the passed tags
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `tags` - the tags to look for
+/// * `all` - whether a note needs every tag, rather than any one of them
+fn notes_matching_tags<'a>(notes: &'a [Note], tags: &[String], all: bool) -> Vec<&'a Note> {
+    notes
+        .iter()
+        .filter(|note| {
+            let contents = std::fs::read_to_string(&note.full_path).unwrap_or_default();
+            let note_tags = read_tags(&contents);
+            let has = |tag: &String| note_tags.contains(tag);
+            if all {
+                tags.iter().all(has)
+            } else {
+                tags.iter().any(has)
+            }
+        })
+        .collect()
+}
+
+/// Counts how many notes carry each tag
+///
+/// A tag listed twice in one note still counts that note once.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn tag_counts(notes: &[Note]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for note in notes {
+        let contents = std::fs::read_to_string(&note.full_path).unwrap_or_default();
+        let mut tags = read_tags(&contents);
+        tags.sort();
+        tags.dedup();
+        for tag in tags {
+            *counts.entry(tag).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Prints every tag with its note count, most used first
+///
+/// # Arguments
+///
+/// * `counts` - how many notes carry each tag
+fn print_tag_counts(counts: &BTreeMap<String, usize>) {
+    if counts.is_empty() {
+        println!("No tags found");
+        return;
+    }
+    let mut sorted: Vec<(&String, &usize)> = counts.iter().collect();
+    // Stable, so equally used tags stay in alphabetical order
+    sorted.sort_by(|a, b| b.1.cmp(a.1));
+    for (tag, count) in sorted {
+        println!("{:>5}  {}", count, tag);
+    }
+}
+
+/// Counts how many notes are in each project
+///
+/// A note is grouped under the first `depth` directories of its path, so at
+/// depth 1 `work/clients/acme/plan.md` counts under `work` and at depth 2
+/// under `work/clients`. Notes at the root are grouped under an empty path.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `depth` - how many directories deep to group at
+fn count_by_project(notes: &[Note], depth: usize) -> BTreeMap<PathBuf, usize> {
+    let mut counts = BTreeMap::new();
+    for note in notes {
+        let project: PathBuf = note
+            .trunc_path
+            .parent()
+            .map(|parent| parent.components().take(depth).collect())
+            .unwrap_or_default();
+        *counts.entry(project).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Counts the notes directly in the inbox directory
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn inbox_count(config: &Config, notes: &[Note]) -> usize {
+    count_by_project(notes, usize::MAX)
+        .get(&config.inbox_dir)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// The generated index of the vault, kept at the root
+const INDEX_FILE: &str = "INDEX.md";
+
+/// Builds a markdown index linking every note, grouped by project
+///
+/// Notes at the root come first, then a `## project` section for each
+/// directory holding notes. Links are relative to the root.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn build_index(notes: &[Note]) -> String {
+    let mut projects: BTreeMap<PathBuf, Vec<&Note>> = BTreeMap::new();
+    for note in notes {
+        let project = note.trunc_path.parent().unwrap_or(Path::new(""));
+        projects
+            .entry(project.to_path_buf())
+            .or_default()
+            .push(note);
+    }
+
+    let mut index = String::from("# Index\n");
+    for (project, notes) in projects {
+        if project.as_os_str().is_empty() {
+            index.push('\n');
+        } else {
+            index.push_str(&format!("\n## {}\n\n", project.display()));
+        }
+        for note in notes {
+            let link = note
+                .trunc_path
+                .to_string_lossy()
+                .replace('\\', "/")
+                .replace(' ', "%20");
+            index.push_str(&format!("- [{}]({})\n", note_title(note), link));
+        }
+    }
+    index
+}
+
+/// Lists the top level projects under the root with how many notes each holds
+///
+/// Hidden directories like the trash and archive, and the templates
+/// directory, aren't projects. Notes in nested directories count towards
+/// their top level project.
+///
+/// # Arguments
+///
+/// * `root` - the root directory of the vault
+fn list_projects(root: &Path) -> io::Result<Vec<(String, usize)>> {
+    fn count_files(dir: &Path) -> usize {
+        read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| {
+                let path = entry.path();
+                if !path.is_dir() {
+                    1
+                } else if path.extension() == Some("assets".as_ref()) {
+                    0
+                } else {
+                    count_files(&path)
+                }
+            })
+            .sum()
+    }
+
+    let mut projects = Vec::new();
+    for entry in read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if !path.is_dir() || name.starts_with('.') || name == "templates" {
+            continue;
+        }
+        projects.push((name, count_files(&path)));
+    }
+    projects.sort();
+    Ok(projects)
+}
+
+/// Returns the top level projects that don't hold any notes
+///
+/// The inbox and scratch directories aren't projects, so are never returned.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn find_empty_projects(config: &Config) -> Vec<PathBuf> {
+    let root = &config.root_dir;
+    list_projects(root)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, count)| *count == 0)
+        .map(|(name, _)| root.join(name))
+        .filter(|dir| {
+            *dir != root.join(&config.inbox_dir) && *dir != root.join(&config.scratch_dir)
+        })
+        .collect()
+}
+
+/// Removes a directory along with the empty directories inside it
+///
+/// Fails, leaving the directory in place, if there's a file anywhere in it.
+///
+/// # Arguments
+///
+/// * `dir` - the directory to remove
+fn remove_empty_tree(dir: &Path) -> io::Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_empty_tree(&path)?;
+        }
+    }
+    std::fs::remove_dir(dir)
+}
+
+/// Says how many projects are empty and offers to remove them
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `input` - where to read answers from, usually stdin
+fn offer_empty_project_cleanup(config: &Config, input: &mut impl BufRead) {
+    let empty = find_empty_projects(config);
+    if empty.is_empty() {
+        return;
+    }
+    let plural = if empty.len() == 1 { "" } else { "s" };
+    println!("{} empty project{}", empty.len(), plural);
+    for dir in &empty {
+        println!(
+            "- {}",
+            dir.strip_prefix(&config.root_dir).unwrap_or(dir).display()
+        );
+    }
+    if !confirm(input, &format!("Remove the empty project{}?", plural)) {
+        return;
+    }
+    for dir in &empty {
+        if let Err(e) = remove_empty_tree(dir) {
+            println!("Failed to remove {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Prints every project with its note count
+///
+/// # Arguments
+///
+/// * `counts` - how many notes each project holds
+fn print_project_counts(counts: &BTreeMap<PathBuf, usize>) {
+    for (project, count) in counts {
+        if project.as_os_str().is_empty() {
+            println!("{:>5}  (root)", count);
+        } else {
+            println!("{:>5}  {}", count, project.display());
+        }
+    }
+}
+
+/// Renames a tag in a note's frontmatter, merging it into `new` if that tag
+/// is already there
+///
+/// The tags field is rewritten as an inline list; everything else is kept as
+/// is. Contents without the old tag are returned unchanged.
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+/// * `old` - the tag to rename, with or without a leading `#`
+/// * `new` - the tag to rename it to, with or without a leading `#`
+fn rename_tag_in_frontmatter(contents: &str, old: &str, new: &str) -> String {
+    let old = old.strip_prefix('#').unwrap_or(old);
+    let new = new.strip_prefix('#').unwrap_or(new);
+    let Some(frontmatter) = split_frontmatter(contents).0 else {
+        return String::from(contents);
+    };
+    let Some((start, end, tags)) = tags_field(frontmatter) else {
+        return String::from(contents);
+    };
+    if !tags.iter().any(|tag| tag == old) {
+        return String::from(contents);
+    }
+
+    let mut renamed: Vec<&str> = Vec::new();
+    for tag in &tags {
+        let tag = if tag == old { new } else { tag.as_str() };
+        if !renamed.contains(&tag) {
+            renamed.push(tag);
+        }
+    }
+    let offset = frontmatter.as_ptr() as usize - contents.as_ptr() as usize;
+    format!(
+        "{}tags: [{}]\n{}",
+        &contents[..offset + start],
+        renamed.join(", "),
+        &contents[offset + end..]
+    )
+}
+
+/// Adds a tag to a note's frontmatter
+///
+/// Frontmatter is created if the note has none, and a tags field if the
+/// frontmatter has none. A note already carrying the tag is returned
+/// unchanged; otherwise the tags field is rewritten as an inline list.
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+/// * `tag` - the tag to add, with or without a leading `#`
+fn add_tag_to_frontmatter(contents: &str, tag: &str) -> String {
+    let tag = tag.strip_prefix('#').unwrap_or(tag);
+    let Some(frontmatter) = split_frontmatter(contents).0 else {
+        return format!("---\ntags: [{}]\n---\n{}", tag, contents);
+    };
+    let offset = frontmatter.as_ptr() as usize - contents.as_ptr() as usize;
+    match tags_field(frontmatter) {
+        Some((_, _, tags)) if tags.iter().any(|t| t == tag) => String::from(contents),
+        Some((start, end, mut tags)) => {
+            tags.push(String::from(tag));
+            format!(
+                "{}tags: [{}]\n{}",
+                &contents[..offset + start],
+                tags.join(", "),
+                &contents[offset + end..]
+            )
+        }
+        None => {
+            let end = offset + frontmatter.len();
+            format!("{}tags: [{}]\n{}", &contents[..end], tag, &contents[end..])
+        }
+    }
+}
+
+/// Returns if a note may be changed, saying why it's skipped if not
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note about to be changed
+fn writable_or_skip(config: &Config, note: &Note) -> bool {
+    match check_writable(config, note) {
+        Ok(()) => true,
+        Err(e) => {
+            println!("Skipping {}: {}", note.trunc_path.display(), e);
+            false
+        }
+    }
+}
+
+/// Prompts for notes and a tag, then adds the tag to each of the notes
+///
+/// How many notes will change is shown and confirmed before any are. Notes
+/// `check_writable` refuses are reported and left out.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn tag_notes(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    if notes.is_empty() {
+        println!("There are no notes to tag");
+        return;
+    }
+    let selected = prompt_for_notes(config, notes, "tag", input);
+    ask(config, "Which tag should be added?");
+    let tag = read_input(input);
+    let tag = tag.trim();
+    if tag.is_empty() {
+        println!("No tag given");
+        return;
+    }
+
+    let changes: Vec<(&Note, String)> = selected
+        .into_iter()
+        .filter_map(|note| {
+            let contents = std::fs::read_to_string(&note.full_path).ok()?;
+            let tagged = add_tag_to_frontmatter(&contents, tag);
+            (tagged != contents && writable_or_skip(config, note)).then_some((note, tagged))
+        })
+        .collect();
+    if changes.is_empty() {
+        println!("Every selected note is already tagged {}", tag);
+        return;
+    }
+    if !confirm(input, &format!("Tag {} notes with {}?", changes.len(), tag)) {
+        println!("Cancelling ...");
+        return;
+    }
+    for (note, tagged) in &changes {
+        if let Err(e) = write_text(&note.full_path, tagged) {
+            println!("Failed to tag {}: {}", note.trunc_path.display(), e);
+        }
+    }
+    println!("Tagged {} notes", changes.len());
+}
+
+/// Adds a tag to a single note's frontmatter
+///
+/// Returns whether the note changed, which it doesn't if it already had the
+/// tag. Fails if `check_writable` refuses the note.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to tag
+/// * `tag` - the tag to add
+fn tag_note(config: &Config, note: &Note, tag: &str) -> io::Result<bool> {
+    check_writable(config, note)?;
+    let contents = std::fs::read_to_string(&note.full_path)?;
+    let tagged = add_tag_to_frontmatter(&contents, tag);
+    if tagged == contents {
+        return Ok(false);
+    }
+    write_text(&note.full_path, &tagged)?;
+    Ok(true)
+}
+
+/// Prompts for an old and a new tag, then renames the tag across the vault
+///
+/// Affected notes are listed and confirmed once before any are changed.
+/// Notes `check_writable` refuses are reported and left out.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn retag_notes(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    ask(config, "Which tag should be renamed?");
+    let old = read_input(input);
+    let old = old.trim();
+    ask(config, "What should it be renamed to?");
+    let new = read_input(input);
+    let new = new.trim();
+    if old.is_empty() || new.is_empty() {
+        println!("Both tags are needed");
+        return;
+    }
+
+    let changes: Vec<(&Note, String)> = notes
+        .iter()
+        .filter_map(|note| {
+            let contents = std::fs::read_to_string(&note.full_path).ok()?;
+            let renamed = rename_tag_in_frontmatter(&contents, old, new);
+            (renamed != contents && writable_or_skip(config, note)).then_some((note, renamed))
+        })
+        .collect();
+    if changes.is_empty() {
+        println!("No notes are tagged {}", old);
+        return;
+    }
+
+    println!("\nNotes to retag:");
+    for (note, _) in &changes {
+        println!("- {}", note.trunc_path.display());
+    }
+    if !confirm(input, &format!("Retag these {} notes?", changes.len())) {
+        println!("Cancelling ...");
+        return;
+    }
+    for (note, renamed) in &changes {
+        if let Err(e) = write_text(&note.full_path, renamed) {
+            println!("Failed to retag {}: {}", note.trunc_path.display(), e);
+        }
+    }
+    println!("Retagged {} notes", changes.len());
+}
+
+/// Returns the project directory a note asks to be filed into
+///
+/// This is the `project:` field of the note's frontmatter, relative to the
+/// root. Notes without the field, or with a value that isn't made up of valid
+/// project names, return None.
+///
+/// # Arguments
+///
+/// * `note` - the note to check
+fn desired_dir(note: &Note) -> Option<PathBuf> {
+    let contents = std::fs::read_to_string(&note.full_path).ok()?;
+    let project = frontmatter_value(split_frontmatter(&contents).0?, "project")?;
+    parse_project_path(&project)
+}
+
+/// Turns a `/` separated project path into a directory relative to the root
+///
+/// Returns None unless every part is a valid project name.
+///
+/// # Arguments
+///
+/// * `project` - the project path, e.g. "work/meetings"
+fn parse_project_path(project: &str) -> Option<PathBuf> {
+    let valid = project
+        .trim_matches('/')
+        .split('/')
+        .all(|part| validate_project_name(part, false) && part != "." && part != "..");
+    if !valid {
+        return None;
+    }
+    Some(PathBuf::from(project.trim_matches('/')))
+}
+
+/// Decides where a file should go when its destination may already exist
+///
+/// A free destination, or any destination when `force` is set, is used as
+/// is. Otherwise the user is asked whether to overwrite it and, if not, for a
+/// different file name, with a blank answer skipping the file (None). Without
+/// an input to ask on, an existing destination is an error.
+///
+/// # Arguments
+///
+/// * `dest` - where the file would go
+/// * `force` - whether to overwrite without asking
+/// * `input` - where to read answers from, or None when not interactive
+fn resolve_overwrite(
+    dest: &Path,
+    force: bool,
+    input: Option<&mut dyn BufRead>,
+) -> io::Result<Option<PathBuf>> {
+    if force || !dest.exists() {
+        return Ok(Some(dest.to_path_buf()));
+    }
+    let Some(mut input) = input else {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, pass --force to replace it",
+                dest.display()
+            ),
+        ));
+    };
+
+    let mut dest = dest.to_path_buf();
+    loop {
+        if confirm(
+            &mut input,
+            &format!("{} already exists, overwrite?", dest.display()),
+        ) {
+            return Ok(Some(dest));
+        }
+        println!("\nWhat should it be called instead? (leave blank to skip it)");
+        let name = read_input(&mut input);
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(None);
+        }
+        if name.contains('/') || name.starts_with('.') {
+            println!("'{}' isn't a valid file name", name);
+            continue;
+        }
+        dest.set_file_name(name);
+        if !dest.exists() {
+            return Ok(Some(dest));
+        }
+    }
+}
+
+/// Moves a note into another directory under the root
+///
+/// The directory is created if needed. An existing file at the destination is
+/// handled by `resolve_overwrite`, and None is returned if the note was
+/// skipped. Locked notes, and locked files in the way, aren't touched.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to move
+/// * `dir` - the destination directory, relative to the root
+/// * `input` - where to read answers from, or None when not interactive
+fn move_note(
+    config: &Config,
+    note: &Note,
+    dir: &Path,
+    input: Option<&mut dyn BufRead>,
+) -> io::Result<Option<PathBuf>> {
+    check_writable(config, note)?;
+    let dest_dir = config.root_dir.join(dir);
+    let dest = move_dest(config, note, dir);
+    let Some(dest) = resolve_overwrite(&dest, config.force_overwrite, input)? else {
+        return Ok(None);
+    };
+    check_unlocked(config, &dest)?;
+    create_dir_all(&dest_dir)?;
+    std::fs::rename(&note.full_path, &dest)?;
+    record_operation(Operation::Move);
+    Ok(Some(dest))
+}
+
+/// Gives a note a new file name in the same directory, without asking
+/// anything
+///
+/// `.md` is added to names without an extension. An existing file with the
+/// new name is an error unless `force_overwrite` is set, and locked notes
+/// aren't touched. With `sync_title_on_rename` set the frontmatter title is
+/// updated too.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to rename
+/// * `name` - the new file name
+fn rename_note(config: &Config, note: &Note, name: &str) -> io::Result<PathBuf> {
+    check_writable(config, note)?;
+    let name = name.trim();
+    if !is_valid_file_name(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' isn't a valid file name", name),
+        ));
+    }
+    let name = match name.rsplit_once('.') {
+        Some((stem, extension)) => fit_file_name(stem, extension, config.max_name_len),
+        None => fit_file_name(name, "md", config.max_name_len),
+    };
+    let dest = note.full_path.with_file_name(name);
+    let Some(dest) = resolve_overwrite(&dest, config.force_overwrite, None)? else {
+        unreachable!("files are only skipped when there is an input to ask on");
+    };
+    check_unlocked(config, &dest)?;
+    std::fs::rename(&note.full_path, &dest)?;
+    record_operation(Operation::Rename);
+    if config.sync_title_on_rename {
+        let contents = std::fs::read_to_string(&dest)?;
+        let updated = update_title(
+            &contents,
+            &dest.file_stem().unwrap_or_default().to_string_lossy(),
+        );
+        if updated != contents {
+            write_text(&dest, &updated)?;
+        }
+    }
+    Ok(dest)
+}
+
+/// Returns if a note may be renamed to the name, which must stay in the
+/// note's directory and not be hidden
+///
+/// # Arguments
+///
+/// * `name` - the new file name
+fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('/') && !name.starts_with('.')
+}
+
+/// Returns the directory above the note's own, relative to the root
+///
+/// A note directly in the root has nowhere to go up to, so gives None.
+///
+/// # Arguments
+///
+/// * `note` - the note to check
+/// * `root` - the root directory of the vault
+fn parent_dir_within_root(note: &Note, root: &Path) -> Option<PathBuf> {
+    let trunc_path = note.full_path.strip_prefix(root).ok()?;
+    let dir = trunc_path.parent()?;
+    if dir.as_os_str().is_empty() {
+        return None;
+    }
+    Some(dir.parent().unwrap_or(Path::new("")).to_path_buf())
+}
+
+/// Returns the names of the directories notes can be moved down into
+///
+/// Hidden directories, templates and attachment folders are left out.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `dir` - the directory to look in
+fn child_dirs(config: &Config, dir: &Path) -> Vec<String> {
+    let mut dirs: Vec<String> = read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_dir()
+                && *path != templates_dir(config)
+                && path.extension() != Some("assets".as_ref())
+        })
+        .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Prints where a moved note ended up, or why it didn't move
+///
+/// # Arguments
+///
+/// * `note` - the note that was moved
+/// * `result` - what `move_note` returned
+fn report_move(note: &Note, result: io::Result<Option<PathBuf>>) {
+    match result {
+        Ok(Some(dest)) => println!("Moved {} to {}", note.trunc_path.display(), dest.display()),
+        Ok(None) => println!("Left {} where it was", note.trunc_path.display()),
+        Err(e) => println!("Failed to move {}: {}", note.trunc_path.display(), e),
+    }
+}
+
+/// Moves a chosen note up into its parent directory
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn promote_note(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    if notes.is_empty() {
+        println!("There are no notes to promote");
+        return;
+    }
+    let note = prompt_for_note(config, notes, "promote", input);
+    match parent_dir_within_root(note, &config.root_dir) {
+        Some(dir) => report_move(note, move_note(config, note, &dir, Some(&mut *input))),
+        None => println!("{} is already at the root", note.trunc_path.display()),
+    }
+}
+
+/// Moves a chosen note down into one of the directories next to it
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn demote_note(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    if notes.is_empty() {
+        println!("There are no notes to demote");
+        return;
+    }
+    let note = prompt_for_note(config, notes, "demote", input);
+    let dir = note.trunc_path.parent().unwrap_or(Path::new(""));
+    let children = child_dirs(config, &config.root_dir.join(dir));
+    if children.is_empty() {
+        println!(
+            "There are no directories below {}",
+            note.trunc_path.display()
+        );
+        return;
+    }
+    let child = loop {
+        ask(config, "Which directory should it move into?");
+        println!("Options are ... ");
+        for (i, child) in children.iter().enumerate() {
+            println!("{}. {}", i + 1, child);
+        }
+        let answer = read_input(input);
+        if answer.is_empty() {
+            return;
+        }
+        let answer = answer.trim();
+        if let Ok(number) = answer.parse::<usize>() {
+            if (1..=children.len()).contains(&number) {
+                break &children[number - 1];
+            }
+        }
+        if let Some(child) = children.iter().find(|child| *child == answer) {
+            break child;
+        }
+    };
+    report_move(
+        note,
+        move_note(config, note, &dir.join(child), Some(&mut *input)),
+    );
+}
+
+/// Moves a note into the archive, keeping its path relative to the root
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to archive
+fn archive_note(config: &Config, note: &Note) -> io::Result<PathBuf> {
+    let parent = note.trunc_path.parent().unwrap_or(Path::new(""));
+    move_note(config, note, &Path::new(".archive").join(parent), None)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "the archived note was skipped",
+        )
+    })
+}
+
+/// Walks through the notes in the inbox one at a time, asking what to do
+/// with each
+///
+/// Each note is previewed, then moved into a project, archived, deleted or
+/// skipped. Processing ends once every note has been seen, or on quit.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn process_inbox(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    let mut inbox: Vec<&Note> = notes
+        .iter()
+        .filter(|note| note.trunc_path.starts_with(&config.inbox_dir))
+        .collect();
+    inbox.sort_by(|a, b| a.trunc_path.cmp(&b.trunc_path));
+    if inbox.is_empty() {
+        println!("The inbox is empty");
+        return;
+    }
+
+    for (i, note) in inbox.iter().enumerate() {
+        println!(
+            "\n({}/{}) {}",
+            i + 1,
+            inbox.len(),
+            note.trunc_path.display()
+        );
+        for line in preview_note(note, 5) {
+            println!("  {}", line);
+        }
+        loop {
+            println!("Options are ... \n\t- (m)ove to a project\n\t- (a)rchive\n\t- (d)elete\n\t- (s)kip\n\t- (q)uit");
+            let answer = read_input(input);
+            let result = match answer.trim() {
+                "m" => {
+                    ask(config, "Which project should it move to?");
+                    match parse_project_path(read_input(input).trim()) {
+                        Some(dir) => match move_note(config, note, &dir, Some(&mut *input)) {
+                            Ok(Some(dest)) => {
+                                println!("Moved to {}", dest.display());
+                                Ok(())
+                            }
+                            Ok(None) => continue,
+                            Err(e) => Err(e),
+                        },
+                        None => {
+                            println!("May only use alphanumerics, '_', '.' and '/'");
+                            continue;
+                        }
+                    }
+                }
+                "a" => archive_note(config, note).map(|_| println!("Archived")),
+                "d" => {
+                    remove_note(config, note);
+                    Ok(())
+                }
+                "s" => Ok(()),
+                "q" => return,
+                _ if answer.is_empty() => return,
+                _ => continue,
+            };
+            if let Err(e) = result {
+                println!("Failed: {}", e);
+                continue;
+            }
+            break;
+        }
+    }
+}
+
+/// Undoes one step of a `Transaction`
+type Undo<'a> = Box<dyn FnOnce() -> io::Result<()> + 'a>;
+
+/// The steps of a multi-file operation done so far, so they can be undone
+/// together if a later one fails
+struct Transaction<'a> {
+    /// What undoing each step does, with how to do it, oldest first
+    undos: Vec<(String, Undo<'a>)>,
+    /// The run's summary from before the first step, put back on rollback
+    summary: Summary,
+}
+
+impl<'a> Transaction<'a> {
+    fn new() -> Transaction<'a> {
+        Transaction {
+            undos: Vec::new(),
+            summary: run_summary(),
+        }
+    }
+
+    /// Records how to undo a step that was just done
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - what undoing the step does, e.g. "move b.md back to a.md"
+    /// * `undo` - undoes the step
+    fn push(&mut self, description: String, undo: impl FnOnce() -> io::Result<()> + 'a) {
+        self.undos.push((description, Box::new(undo)));
+    }
+
+    /// Undoes every recorded step, latest first
+    ///
+    /// Every step is tried even if undoing an earlier one failed, and the
+    /// steps are forgotten by the run's summary. Returns the steps that
+    /// couldn't be undone, with why.
+    fn rollback(self) -> Vec<(String, io::Error)> {
+        *SUMMARY
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = self.summary;
+        self.undos
+            .into_iter()
+            .rev()
+            .filter_map(|(description, undo)| undo().err().map(|e| (description, e)))
+            .collect()
+    }
+}
+
+/// Moves the file a step is about to overwrite into the trash, recording how
+/// to put it back
+///
+/// Returns where the file was staged, or None if nothing is in the way. The
+/// caller removes staged files once the transaction is through if
+/// `use_trash` isn't set.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `transaction` - the transaction the step belongs to
+/// * `dest` - where the step will write
+fn stage_overwritten(
+    config: &Config,
+    transaction: &mut Transaction,
+    dest: &Path,
+) -> io::Result<Option<PathBuf>> {
+    if dest.symlink_metadata().is_err() {
+        return Ok(None);
+    }
+    let note = Note {
+        trunc_path: dest
+            .strip_prefix(&config.root_dir)
+            .unwrap_or(dest)
+            .to_path_buf(),
+        full_path: dest.to_path_buf(),
+        root: config.root_dir.clone(),
+    };
+    let staged = trash_note(config, &note)?;
+    let restore = staged.clone();
+    transaction.push(
+        format!("restore {} from the trash", note.trunc_path.display()),
+        move || std::fs::rename(&restore, &note.full_path),
+    );
+    Ok(Some(staged))
+}
+
+/// Creates a directory and any missing parents, recording how to remove the
+/// ones that were created
+///
+/// # Arguments
+///
+/// * `transaction` - the transaction the step belongs to
+/// * `dir` - the directory to create
+fn create_dirs(transaction: &mut Transaction, dir: &Path) -> io::Result<()> {
+    let created: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    create_dir_all(dir)?;
+    if let Some(top) = created.last() {
+        let description = format!("remove the new directory {}", top.display());
+        transaction.push(description, move || {
+            created.iter().try_for_each(std::fs::remove_dir)
+        });
+    }
+    Ok(())
+}
+
+/// Rolls a transaction back after one of its steps failed, reporting how it
+/// went
+///
+/// # Arguments
+///
+/// * `transaction` - the steps done before the failure
+fn roll_back(transaction: Transaction) {
+    let steps = transaction.undos.len();
+    let failures = transaction.rollback();
+    if failures.is_empty() {
+        println!(
+            "Undid the {} changes already made, nothing was changed",
+            steps
+        );
+        return;
+    }
+    println!(
+        "Undid {} of the {} changes already made. These couldn't be undone:",
+        steps - failures.len(),
+        steps
+    );
+    for (description, e) in failures {
+        println!("- couldn't {}: {}", description, e);
+    }
+}
+
+/// Returns where a note ends up when moved into a directory under the root
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to move
+/// * `dir` - the destination directory, relative to the root
+fn move_dest(config: &Config, note: &Note, dir: &Path) -> PathBuf {
+    config
+        .root_dir
+        .join(dir)
+        .join(note.full_path.file_name().unwrap_or_default())
+}
+
+/// Works out the (source, destination) pair of every move in a bulk move
+///
+/// Nothing is moved, see `plan_collisions` for what stops a plan.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `moves` - each note to move, with the directory it goes to
+fn plan_moves(config: &Config, moves: &[(&Note, PathBuf)]) -> Vec<(PathBuf, PathBuf)> {
+    moves
+        .iter()
+        .map(|(note, dir)| (note.full_path.clone(), move_dest(config, note, dir)))
+        .collect()
+}
+
+/// Returns the destinations of a move plan that collide
+///
+/// A destination collides if a file is already there, unless `force` is set,
+/// or if several moves of the plan go there, as one would overwrite another.
+///
+/// # Arguments
+///
+/// * `plan` - the (source, destination) pairs from `plan_moves`
+/// * `force` - whether existing files may be overwritten
+fn plan_collisions(plan: &[(PathBuf, PathBuf)], force: bool) -> Vec<&Path> {
+    let mut collisions: Vec<&Path> = Vec::new();
+    for (i, (_, dest)) in plan.iter().enumerate() {
+        let repeated = plan[..i].iter().any(|(_, other)| other == dest);
+        if (repeated || (!force && dest.exists())) && !collisions.contains(&dest.as_path()) {
+            collisions.push(dest);
+        }
+    }
+    collisions
+}
+
+/// Lists a move plan as `source -> destination` lines, relative to the root
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `plan` - the (source, destination) pairs from `plan_moves`
+fn render_move_plan(config: &Config, plan: &[(PathBuf, PathBuf)]) -> String {
+    let relative = |path: &Path| {
+        path.strip_prefix(&config.root_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    plan.iter()
+        .map(|(src, dest)| format!("- {} -> {}\n", relative(src), relative(dest)))
+        .collect()
+}
+
+/// Files notes into the project named in their frontmatter
+///
+/// All proposed moves are listed and confirmed once before any are made. If
+/// any destination collides, see `plan_collisions`, nothing is moved, and if
+/// a move fails the ones already made are undone, including the directories
+/// they created. Files overwritten with `force_overwrite` are staged in the
+/// trash first so they can be put back. Notes that can't be changed, or
+/// whose destination is locked, are skipped before the plan is shown.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn file_notes(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    let moves: Vec<(&Note, PathBuf)> = notes
+        .iter()
+        .filter_map(|note| desired_dir(note).map(|dir| (note, dir)))
+        .filter(|(note, dir)| note.trunc_path.parent() != Some(dir.as_path()))
+        .filter(|(note, _)| writable_or_skip(config, note))
+        .filter(
+            |(note, dir)| match check_unlocked(config, &move_dest(config, note, dir)) {
+                Ok(()) => true,
+                Err(e) => {
+                    println!("Skipping {}: {}", note.trunc_path.display(), e);
+                    false
+                }
+            },
+        )
+        .collect();
+
+    if moves.is_empty() {
+        println!("Every note is already in its project");
+        return;
+    }
+
+    let plan = plan_moves(config, &moves);
+    println!("\nProposed moves:");
+    print!("{}", render_move_plan(config, &plan));
+    let collisions = plan_collisions(&plan, config.force_overwrite);
+    if !collisions.is_empty() {
+        for dest in &collisions {
+            println!(
+                "{} is already taken",
+                dest.strip_prefix(&config.root_dir)
+                    .unwrap_or(dest)
+                    .display()
+            );
+        }
+        println!("Nothing was moved, pass --force to overwrite existing files");
+        return;
+    }
+    if !confirm(input, &format!("Move these {} notes?", moves.len())) {
+        println!("Cancelling ...");
+        return;
+    }
+
+    let mut transaction = Transaction::new();
+    let mut staged = Vec::new();
+    for (note, dir) in &moves {
+        let dest = move_dest(config, note, dir);
+        let prepared = check_unlocked(config, &dest)
+            .and_then(|_| create_dirs(&mut transaction, &config.root_dir.join(dir)))
+            .and_then(|_| stage_overwritten(config, &mut transaction, &dest));
+        match prepared {
+            Ok(path) => staged.extend(path),
+            Err(e) => {
+                println!("Failed to move {}: {}", note.trunc_path.display(), e);
+                roll_back(transaction);
+                return;
+            }
+        }
+        match move_note(config, note, dir, None) {
+            Ok(Some(dest)) => {
+                println!("Moved {} to {}", note.trunc_path.display(), dest.display());
+                transaction.push(
+                    format!(
+                        "move {} back to {}",
+                        dest.display(),
+                        note.trunc_path.display()
+                    ),
+                    move || std::fs::rename(&dest, &note.full_path),
+                );
+            }
+            Ok(None) => println!("Skipped {}", note.trunc_path.display()),
+            Err(e) => {
+                println!("Failed to move {}: {}", note.trunc_path.display(), e);
+                roll_back(transaction);
+                return;
+            }
+        }
+    }
+    if !config.use_trash {
+        for path in staged {
+            let _ = remove_file(path);
+        }
+    }
+}
+
+/// Returns if the contents already contain a wikilink to the target
+///
+/// # Arguments
+///
+/// * `contents` - the note contents to search
+/// * `target_stem` - the file stem being linked to
+fn has_link(contents: &str, target_stem: &str) -> bool {
+    contents.contains(&format!("[[{}]]", target_stem))
+        || contents.contains(&format!("[[{}|", target_stem))
+}
+
+/// Appends a `[[target_stem]]` wikilink to the end of a note's contents
+///
+/// Contents that already link to the target are returned unchanged.
+///
+/// # Arguments
+///
+/// * `contents` - the note contents to add the link to
+/// * `target_stem` - the file stem being linked to
+fn insert_link(contents: &str, target_stem: &str) -> String {
+    if has_link(contents, target_stem) {
+        return String::from(contents);
+    }
+
+    let mut linked = String::from(contents);
+    if !linked.is_empty() && !linked.ends_with('\n') {
+        linked.push('\n');
+    }
+    linked.push_str(&format!("[[{}]]\n", target_stem));
+    linked
+}
+
+/// Adds a `[[target_stem]]` wikilink under a `## <section>` heading
+///
+/// The link is added as a list item at the end of the section, which is
+/// created at the end of the note if missing. Contents that already link to
+/// the target are returned unchanged.
+///
+/// # Arguments
+///
+/// * `contents` - the note contents to add the link to
+/// * `target_stem` - the file stem being linked to
+/// * `section` - the heading text to add the link under
+fn insert_link_in_section(contents: &str, target_stem: &str, section: &str) -> String {
+    if has_link(contents, target_stem) {
+        return String::from(contents);
+    }
+
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    let item = format!("- [[{}]]", target_stem);
+    let heading = format!("## {}", section);
+    match lines.iter().position(|line| line.trim_end() == heading) {
+        Some(start) => {
+            // The section runs until the next heading of the same or higher level
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| line.starts_with("# ") || line.starts_with("## "))
+                .map(|i| start + 1 + i)
+                .unwrap_or(lines.len());
+            let mut insert_at = end;
+            while insert_at > start + 1 && lines[insert_at - 1].trim().is_empty() {
+                insert_at -= 1;
+            }
+            lines.insert(insert_at, item);
+        }
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(heading);
+            lines.push(item);
+        }
+    }
+
+    let mut linked = lines.join("\n");
+    linked.push('\n');
+    linked
+}
+
+/// Returns the targets of every `[[wikilink]]` in the contents
+///
+/// Aliased links like `[[target|shown text]]` return just the target.
+///
+/// # Arguments
+///
+/// * `contents` - the note contents to search
+fn find_links(contents: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let target = rest[..end].split('|').next().unwrap_or_default().trim();
+        if !target.is_empty() && !target.contains('\n') {
+            links.push(String::from(target));
+        }
+        rest = &rest[end + 2..];
+    }
+    links
+}
+
+/// Returns the names a wikilink can use for a note: its file stem followed
+/// by the `aliases:` in its frontmatter
+///
+/// # Arguments
+///
+/// * `note` - the note to name
+fn note_names(note: &Note) -> Vec<String> {
+    let mut names = vec![note
+        .trunc_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()];
+    let contents = std::fs::read_to_string(&note.full_path).unwrap_or_default();
+    if let Some((_, _, aliases)) = split_frontmatter(&contents)
+        .0
+        .and_then(|frontmatter| list_field(frontmatter, "aliases"))
+    {
+        names.extend(aliases);
+    }
+    names
+}
+
+/// Returns the note a wikilink points at, if any
+///
+/// A link resolves if it names one of a note's names (see `note_names`) or
+/// its path relative to the root without the extension.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `names` - each note's names, in the same order as `notes`
+/// * `target` - the link target
+fn resolve_link<'a>(notes: &'a [Note], names: &[Vec<String>], target: &str) -> Option<&'a Note> {
+    notes.iter().zip(names).find_map(|(note, names)| {
+        (names.iter().any(|name| name == target)
+            || note.trunc_path.with_extension("") == Path::new(target))
+        .then_some(note)
+    })
+}
+
+/// Returns every wikilink whose target isn't a note, with the note it is in
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn broken_links(notes: &[Note]) -> Vec<(&Note, String)> {
+    let names: Vec<Vec<String>> = notes.iter().map(note_names).collect();
+    let resolves = |target: &str| resolve_link(notes, &names, target).is_some();
+    let mut broken = Vec::new();
+    for note in notes {
+        let contents = std::fs::read_to_string(&note.full_path).unwrap_or_default();
+        for target in find_links(&contents) {
+            if !resolves(&target) {
+                broken.push((note, target));
+            }
+        }
+    }
+    broken
+}
+
+/// Builds a Graphviz DOT graph of the wikilinks between notes
+///
+/// Every note is a node labeled by its file stem, so notes without any links
+/// still show up on their own.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+fn build_dot_graph(notes: &[Note]) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    for note in notes {
+        let stem = note
+            .trunc_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        nodes.push(format!("    {};", quote(&stem)));
+        let contents = std::fs::read_to_string(&note.full_path).unwrap_or_default();
+        for target in find_links(&contents) {
+            edges.push(format!("    {} -> {};", quote(&stem), quote(&target)));
+        }
+    }
+    nodes.sort();
+    nodes.dedup();
+    edges.sort();
+    edges.dedup();
+
+    let mut dot = String::from("digraph notes {\n");
+    for line in nodes.iter().chain(edges.iter()) {
+        dot.push_str(line);
+        dot.push('\n');
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Concatenates notes into a single markdown document
+///
+/// Each note's body (without frontmatter) is placed under a heading built from
+/// `merge_header_template`, in the order passed, with `merge_separator`
+/// between notes.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - the notes to combine
+fn merge_notes(config: &Config, notes: &[&Note]) -> io::Result<String> {
+    let mut sections = Vec::new();
+    for note in notes {
+        let contents = read_text(&note.full_path)?;
+        let body = match &contents {
+            Some(contents) => split_frontmatter(contents).1.trim(),
+            None => "(binary)",
+        };
+        let header = config
+            .merge_header_template
+            .replace("{{path}}", &note.trunc_path.to_string_lossy())
+            .replace("{{title}}", &note_title(note));
+        sections.push(format!("{}\n\n{}", header, body));
+    }
+    let mut merged = sections.join(&config.merge_separator);
+    merged.push('\n');
+    Ok(merged)
+}
+
+/// Writes every note in a project, sorted by path, to a single file
+///
+/// Trashed notes are never included since they aren't scanned.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `project` - the name of the project to export
+/// * `dest` - the file to write the combined note to
+fn export_project(
+    config: &Config,
+    notes: &[Note],
+    project: &str,
+    dest: &Path,
+) -> io::Result<usize> {
+    if !config.root_dir.join(project).is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no project named {}", project),
+        ));
+    }
+    let mut project_notes: Vec<&Note> = notes
+        .iter()
+        .filter(|note| note.trunc_path.starts_with(project))
+        .collect();
+    project_notes.sort_by(|a, b| a.trunc_path.cmp(&b.trunc_path));
+    write_text(dest, &merge_notes(config, &project_notes)?)?;
+    Ok(project_notes.len())
+}
+
+/// The stylesheet embedded in notes exported to HTML
+const HTML_STYLE: &str = "\
+body { max-width: 42em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; line-height: 1.5; }
+pre { background: #f4f4f4; padding: 0.75em; overflow-x: auto; }
+code { font-family: monospace; }
+blockquote { margin-left: 0; padding-left: 1em; border-left: 3px solid #ccc; color: #555; }
+.wikilink { text-decoration: underline dotted; }
+";
+
+/// Escapes the characters HTML gives a meaning to
+///
+/// # Arguments
+///
+/// * `text` - the text to escape
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns if a link's target is safe to put in an exported page
+///
+/// Relative links and anchors are, as are `http:`, `https:` and `mailto:`
+/// links. Any other scheme, such as `javascript:`, isn't.
+///
+/// # Arguments
+///
+/// * `url` - the link target
+fn is_safe_href(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => ["http", "https", "mailto"]
+            .iter()
+            .any(|safe| scheme.eq_ignore_ascii_case(safe)),
+        _ => true,
+    }
+}
+
+/// Renders markdown to HTML with pulldown-cmark
+///
+/// Tables, strikethrough and task lists are supported on top of CommonMark.
+/// Any HTML in the markdown is escaped rather than passed through, links
+/// whose target `is_safe_href` rejects are rendered as just their label, and
+/// [[wikilinks]] become plain text in a `wikilink` span.
+///
+/// # Arguments
+///
+/// * `md` - the markdown to render
+fn render_markdown_to_html(md: &str) -> String {
+    use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_WIKILINKS;
+    // What to emit in place of the end of each open link, None to drop it
+    let mut link_ends: Vec<Option<Event>> = Vec::new();
+    let events = Parser::new_ext(md, options).filter_map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Some(Event::Text(html)),
+        Event::Start(Tag::Link {
+            link_type: LinkType::WikiLink { .. },
+            ..
+        }) => {
+            link_ends.push(Some(Event::InlineHtml("</span>".into())));
+            Some(Event::InlineHtml("<span class=\"wikilink\">".into()))
+        }
+        Event::Start(Tag::Link { ref dest_url, .. })
+        | Event::Start(Tag::Image { ref dest_url, .. })
+            if !is_safe_href(dest_url) =>
+        {
+            link_ends.push(None);
+            None
+        }
+        Event::Start(Tag::Link { .. }) => {
+            link_ends.push(Some(Event::End(TagEnd::Link)));
+            Some(event)
+        }
+        Event::Start(Tag::Image { .. }) => {
+            link_ends.push(Some(Event::End(TagEnd::Image)));
+            Some(event)
+        }
+        Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => link_ends.pop().flatten(),
+        _ => Some(event),
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+/// Writes a note to a standalone HTML file, leaving out its frontmatter
+///
+/// An existing file at `dest` is an error unless `force_overwrite` is set,
+/// and a locked one is never overwritten.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to export
+/// * `dest` - the HTML file to write
+fn export_note_html(config: &Config, note: &Note, dest: &Path) -> io::Result<()> {
+    if resolve_overwrite(dest, config.force_overwrite, None)?.is_none() {
+        unreachable!("files are only skipped when there is an input to ask on");
+    }
+    check_unlocked(config, dest)?;
+    let body = read_note_body(note, true)?;
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        html_escape(&note_title(note)),
+        HTML_STYLE,
+        render_markdown_to_html(&body)
+    );
+    write_text(dest, &page)
+}
+
+/// Returns every markdown heading in the contents as (level, line, text)
+///
+/// Lines are numbered from 1. Headings inside fenced code blocks and the
+/// frontmatter are skipped.
+///
+/// # Arguments
+///
+/// * `contents` - the note contents to search
+fn extract_headings(contents: &str) -> Vec<(usize, usize, String)> {
+    let frontmatter_lines = match split_frontmatter(contents) {
+        (Some(_), body) => contents[..contents.len() - body.len()].lines().count(),
+        (None, _) => 0,
+    };
+
+    let mut headings = Vec::new();
+    let mut fence: Option<&str> = None;
+    for (i, line) in contents.lines().enumerate().skip(frontmatter_lines) {
+        let trimmed = line.trim_start();
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            match fence {
+                Some(open) if open == marker => fence = None,
+                None => fence = Some(marker),
+                _ => {}
+            }
+            continue;
+        }
+        if fence.is_some() {
+            continue;
+        }
+
+        let level = line.chars().take_while(|c| *c == '#').count();
+        let rest = &line[level..];
+        if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with([' ', '\t'])) {
+            let text = rest.trim().trim_end_matches('#').trim_end();
+            headings.push((level, i + 1, String::from(text)));
+        }
+    }
+    headings
+}
+
+/// A group of lines around one or more lines matching a search
+#[derive(Debug, PartialEq)]
+struct Hit {
+    /// The 1-based number of the first line in the group
+    first: usize,
+    /// The lines of the group, context and matches, in order
+    lines: Vec<String>,
+    /// The 1-based numbers of the matching lines
+    matches: Vec<usize>,
+}
+
+/// Returns the byte ranges in `line` where `query` occurs, without overlaps
+///
+/// # Arguments
+///
+/// * `line` - the line to search
+/// * `query` - the text to look for
+/// * `case_sensitive` - whether upper and lower case must match exactly
+fn find_matches(line: &str, query: &str, case_sensitive: bool) -> Vec<(usize, usize)> {
+    let same =
+        |a: char, b: char| a == b || (!case_sensitive && a.to_lowercase().eq(b.to_lowercase()));
+    let mut matches = Vec::new();
+    if query.is_empty() {
+        return matches;
+    }
+    let mut start = 0;
+    while start < line.len() {
+        let mut rest = line[start..].chars();
+        let mut len = 0;
+        let found = query.chars().all(|q| match rest.next() {
+            Some(c) if same(c, q) => {
+                len += c.len_utf8();
+                true
+            }
+            _ => false,
+        });
+        if found {
+            matches.push((start, start + len));
+            start += len;
+        } else {
+            start += line[start..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    matches
+}
+
+/// Finds the lines containing `query`, each with `ctx` lines either side
+///
+/// Matches whose context would overlap or touch are merged into one hit, the
+/// same way ripgrep groups them.
+///
+/// # Arguments
+///
+/// * `contents` - the note contents to search
+/// * `query` - the text to look for
+/// * `ctx` - how many lines of context to keep before and after a match
+/// * `case_sensitive` - whether upper and lower case must match exactly
+fn search_with_context(contents: &str, query: &str, ctx: usize, case_sensitive: bool) -> Vec<Hit> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut hits: Vec<Hit> = Vec::new();
+    let mut end = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if find_matches(line, query, case_sensitive).is_empty() {
+            continue;
+        }
+        let start = i.saturating_sub(ctx);
+        let stop = (i + ctx + 1).min(lines.len());
+        match hits.last_mut() {
+            Some(hit) if start <= end => {
+                hit.lines
+                    .extend(lines[end..stop].iter().map(|line| line.to_string()));
+                hit.matches.push(i + 1);
+            }
+            _ => hits.push(Hit {
+                first: start + 1,
+                lines: lines[start..stop]
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect(),
+                matches: vec![i + 1],
+            }),
+        }
+        end = stop;
+    }
+    hits
+}
+
+/// Prints every note's search hits, ripgrep style
+///
+/// Matching lines are numbered with `:` and context lines with `-`, and
+/// groups are separated by `--`. With `color` the query is highlighted.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `query` - the text to look for
+/// * `ctx` - how many lines of context to show before and after a match
+/// * `case_sensitive` - whether upper and lower case must match exactly
+/// * `color` - whether to highlight matches with terminal colors
+fn search_notes(notes: &[Note], query: &str, ctx: usize, case_sensitive: bool, color: bool) {
+    let highlight = |line: &str| {
+        let mut highlighted = String::new();
+        let mut last = 0;
+        for (start, end) in find_matches(line, query, case_sensitive) {
+            highlighted.push_str(&line[last..start]);
+            highlighted.push_str(&format!("\x1b[1;31m{}\x1b[0m", &line[start..end]));
+            last = end;
+        }
+        highlighted + &line[last..]
+    };
+    let mut found = false;
+    for note in notes {
+        let Ok(Some(contents)) = read_text(&note.full_path) else {
+            continue;
+        };
+        let hits = search_with_context(&contents, query, ctx, case_sensitive);
+        if hits.is_empty() {
+            continue;
+        }
+        if found {
+            println!();
+        }
+        found = true;
+        println!("{}", note.trunc_path.display());
+        for (i, hit) in hits.iter().enumerate() {
+            if i > 0 {
+                println!("--");
+            }
+            for (offset, line) in hit.lines.iter().enumerate() {
+                let number = hit.first + offset;
+                let separator = if hit.matches.contains(&number) {
+                    ':'
+                } else {
+                    '-'
+                };
+                if color {
+                    println!("{}{}{}", number, separator, highlight(line));
+                } else {
+                    println!("{}{}{}", number, separator, line);
+                }
+            }
+        }
+    }
+    if !found {
+        println!("No matches for '{}'", query);
+    }
+}
+
+/// A markdown hygiene problem found by `lint_markdown`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LintRule {
+    TrailingWhitespace,
+    MissingFinalNewline,
+    MultipleBlankLines,
+    TabIndentation,
+}
+
+impl LintRule {
+    /// Returns if `fix_markdown` resolves this problem
+    fn fixable(self) -> bool {
+        self != LintRule::TabIndentation
+    }
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LintRule::TrailingWhitespace => write!(f, "trailing whitespace"),
+            LintRule::MissingFinalNewline => write!(f, "missing final newline"),
+            LintRule::MultipleBlankLines => write!(f, "multiple consecutive blank lines"),
+            LintRule::TabIndentation => write!(f, "indented with tabs"),
+        }
+    }
+}
+
+/// A problem and the 1-based line it's on
+#[derive(Debug, PartialEq)]
+struct LintIssue {
+    line: usize,
+    rule: LintRule,
+}
+
+/// Checks a note's contents for markdown hygiene problems
+///
+/// A run of blank lines is reported once, at its second line.
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+fn lint_markdown(contents: &str) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut blank_run = 0;
+    for (i, line) in contents.lines().enumerate() {
+        let line_number = i + 1;
+        if line.ends_with([' ', '\t']) {
+            issues.push(LintIssue {
+                line: line_number,
+                rule: LintRule::TrailingWhitespace,
+            });
+        }
+        if line.starts_with('\t') {
+            issues.push(LintIssue {
+                line: line_number,
+                rule: LintRule::TabIndentation,
+            });
+        }
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run == 2 {
+                issues.push(LintIssue {
+                    line: line_number,
+                    rule: LintRule::MultipleBlankLines,
+                });
+            }
+        } else {
+            blank_run = 0;
+        }
+    }
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        issues.push(LintIssue {
+            line: contents.lines().count(),
+            rule: LintRule::MissingFinalNewline,
+        });
+    }
+    issues
+}
+
+/// Resolves the fixable problems `lint_markdown` reports
+///
+/// Trailing whitespace is trimmed, runs of blank lines are collapsed to one
+/// and a final newline is added. Tab indentation is left alone, and line
+/// endings become LF.
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+fn fix_markdown(contents: &str) -> String {
+    let mut fixed = String::new();
+    let mut previous_blank = false;
+    for line in contents.lines() {
+        let line = line.trim_end_matches([' ', '\t']);
+        if line.is_empty() && previous_blank {
+            continue;
+        }
+        previous_blank = line.is_empty();
+        fixed.push_str(line);
+        fixed.push('\n');
+    }
+    fixed
+}
+
+/// Counts the words in a note's body, leaving out its frontmatter
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+fn count_words(contents: &str) -> usize {
+    split_frontmatter(contents).1.split_whitespace().count()
+}
+
+/// Returns how many words a note has, and the budget, if that's more than
+/// `word_budget`
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `contents` - the full contents of a note
+fn over_word_budget(config: &Config, contents: &str) -> Option<(usize, usize)> {
+    let budget = config.word_budget?;
+    let words = count_words(contents);
+    (words > budget).then_some((words, budget))
+}
+
+/// Warns if a note that was just edited has gone over `word_budget`
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the note that was edited
+fn warn_word_budget(config: &Config, note_path: &Path) {
+    let Ok(Some(contents)) = read_text(note_path) else {
+        return;
+    };
+    if let Some((words, budget)) = over_word_budget(config, &contents) {
+        println!(
+            "{} has {} words, over the budget of {}",
+            note_path.display(),
+            words,
+            budget
+        );
+    }
+}
+
+/// Prints the hygiene problems in every note, fixing them if `fix` is set
+///
+/// Notes over `word_budget` are reported as well. Binary files are skipped,
+/// and notes `check_writable` refuses are reported but never fixed. Returns
+/// how many problems were found.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `fix` - whether to rewrite notes to resolve the fixable problems
+fn lint_notes(config: &Config, notes: &[Note], fix: bool) -> usize {
+    let mut found = 0;
+    for note in notes {
+        let Ok(Some(contents)) = read_text(&note.full_path) else {
+            continue;
+        };
+        if let Some((words, budget)) = over_word_budget(config, &contents) {
+            println!(
+                "{}: {} words, over the budget of {}",
+                note.trunc_path.display(),
+                words,
+                budget
+            );
+            found += 1;
+        }
+        let issues = lint_markdown(&contents);
+        for issue in &issues {
+            let fixed = if fix && issue.rule.fixable() {
+                " (fixed)"
+            } else {
+                ""
+            };
+            println!(
+                "{}:{}: {}{}",
+                note.trunc_path.display(),
+                issue.line,
+                issue.rule,
+                fixed
+            );
+        }
+        if fix && issues.iter().any(|issue| issue.rule.fixable()) {
+            if let Err(e) = check_writable(config, note)
+                .and_then(|_| write_text(&note.full_path, &fix_markdown(&contents)))
+            {
+                println!("Failed to fix {}: {}", note.trunc_path.display(), e);
+            }
+        }
+        found += issues.len();
+    }
+    if found == 0 {
+        println!("No problems found");
+    }
+    found
+}
+
+/// Prints headings as an indented table of contents with line numbers
+///
+/// # Arguments
+///
+/// * `headings` - the (level, line, text) headings to print
+fn print_toc(headings: &[(usize, usize, String)]) {
+    if headings.is_empty() {
+        println!("No headings found");
+        return;
+    }
+    for (level, line, text) in headings {
+        println!("{:>5}  {}{}", line, "  ".repeat(level - 1), text);
+    }
+}
+
+/// Prompts for a source and target note and links the source to the target
+///
+/// Fails without asking for the target if `check_writable` refuses the
+/// source.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn link_notes(config: &Config, notes: &[Note], input: &mut impl BufRead) -> io::Result<()> {
+    let source = prompt_for_note(config, notes, "link from", input);
+    check_writable(config, source)?;
+    let target = prompt_for_note(config, notes, "link to", input);
+    if source.full_path == target.full_path {
+        println!("A note can't link to itself");
+        return Ok(());
+    }
+
+    let target_stem = target
+        .trunc_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let contents = std::fs::read_to_string(&source.full_path)?;
+    let linked = match &config.link_section {
+        Some(section) => insert_link_in_section(&contents, &target_stem, section),
+        None => insert_link(&contents, &target_stem),
+    };
+    if linked == contents {
+        println!(
+            "{} already links to {}",
+            source.trunc_path.display(),
+            target_stem
+        );
+        return Ok(());
+    }
+    write_text(&source.full_path, &linked)?;
+    println!("Linked {} to {}", source.trunc_path.display(), target_stem);
+    Ok(())
+}
+
+/// Copies an external file into a note's assets directory and links to it
+///
+/// The file is copied to `<note-stem>.assets/` beside the note, and a markdown
+/// link (or image, for image files) to the copy is appended to the note.
+/// Returns where the file was copied to. Fails if `check_writable` refuses
+/// the note.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note` - the note to attach the file to
+/// * `src` - the external file to attach
+fn attach_file(config: &Config, note: &Note, src: &Path) -> io::Result<PathBuf> {
+    check_writable(config, note)?;
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    if src.to_str().is_none() {
+        return Err(invalid(format!("{} is not valid UTF-8", src.display())));
+    }
+    if !src.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a file", src.display()),
+        ));
+    }
+    let file_name = src
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| invalid(format!("{} has no file name", src.display())))?;
+
+    let stem = note
+        .full_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let assets_name = format!("{}.assets", stem);
+    let assets_dir = note.full_path.with_file_name(&assets_name);
+    let dest = assets_dir.join(file_name);
+    if dest.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dest.display()),
+        ));
+    }
+    create_dir_all(&assets_dir)?;
+    std::fs::copy(src, &dest)?;
+
+    let mut target = format!("{}/{}", assets_name, file_name);
+    if target.contains(' ') {
+        target = format!("<{}>", target);
+    }
+    let is_image = ["png", "jpg", "jpeg", "gif", "svg", "webp"].contains(
+        &Path::new(file_name)
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase()
+            .as_str(),
+    );
+    let link = if is_image {
+        format!("![{}]({})", file_name, target)
+    } else {
+        format!("[{}]({})", file_name, target)
+    };
+
+    let mut contents = std::fs::read_to_string(&note.full_path)?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(&link);
+    write_text(&note.full_path, &contents)?;
+    Ok(dest)
+}
+
+/// Plans renaming every note whose file name contains `from`
+///
+/// Every occurrence of `from` in the file name is replaced with `to`; the
+/// directories a note is in are left alone. Renames that would collide with
+/// an existing file, or with another planned rename, are left out of the plan,
+/// as are ones to names `rename_note` wouldn't accept.
+/// Returns (current full path, new full path) pairs.
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `from` - the text to replace
+/// * `to` - the text to replace it with
+fn plan_bulk_rename(notes: &[Note], from: &str, to: &str) -> Vec<(PathBuf, PathBuf)> {
+    if from.is_empty() {
+        return Vec::new();
+    }
+
+    let mut candidates = Vec::new();
+    for note in notes {
+        let name = note
+            .full_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        if !name.contains(from) {
+            continue;
+        }
+        let new_name = name.replace(from, to);
+        if new_name == name || !is_valid_file_name(&new_name) {
+            continue;
+        }
+        candidates.push((
+            note.full_path.clone(),
+            note.full_path.with_file_name(new_name),
+        ));
+    }
+
+    candidates
+        .iter()
+        .filter(|(_, dest)| {
+            let claimed = candidates.iter().filter(|(_, other)| other == dest).count();
+            claimed == 1 && !dest.exists()
+        })
+        .cloned()
+        .collect()
+}
+
+/// Replaces the `title:` field in a note's frontmatter
+///
+/// Everything else, including other frontmatter keys and the body, is kept
+/// as is. Notes without frontmatter, or without a title in it, are returned
+/// unchanged.
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+/// * `new_title` - the title to set
+fn update_title(contents: &str, new_title: &str) -> String {
+    let Some(frontmatter) = split_frontmatter(contents).0 else {
+        return String::from(contents);
+    };
+    let start = frontmatter.as_ptr() as usize - contents.as_ptr() as usize;
+    let end = start + frontmatter.len();
+
+    let mut updated = String::from(&contents[..start]);
+    for line in frontmatter.split_inclusive('\n') {
+        match line.split_once(':') {
+            Some((key, _)) if key.trim() == "title" => {
+                let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+                updated.push_str(&format!("title: {}{}", new_title, ending));
+            }
+            _ => updated.push_str(line),
+        }
+    }
+    updated.push_str(&contents[end..]);
+    updated
+}
+
+/// Sets the `project:` field in a note's frontmatter
+///
+/// An existing field is replaced in place, otherwise one is added, creating
+/// the frontmatter if the note has none. An empty `project` removes the field
+/// instead.
+///
+/// # Arguments
+///
+/// * `contents` - the full contents of a note
+/// * `project` - the project to set
+fn set_project_field(contents: &str, project: &str) -> String {
+    let field = (!project.is_empty()).then(|| format!("project: {}\n", project));
+    let Some(frontmatter) = split_frontmatter(contents).0 else {
+        return match field {
+            Some(field) => format!("---\n{}---\n{}", field, contents),
+            None => String::from(contents),
+        };
+    };
+    let start = frontmatter.as_ptr() as usize - contents.as_ptr() as usize;
+    let end = start + frontmatter.len();
+
+    let mut updated = String::from(&contents[..start]);
+    let mut field = field.as_deref();
+    let mut replaced = false;
+    for line in frontmatter.split_inclusive('\n') {
+        match line.split_once(':') {
+            Some((key, _)) if key.trim() == "project" => {
+                // Only the first project field is kept
+                if !replaced {
+                    updated.push_str(field.take().unwrap_or_default());
+                    replaced = true;
+                }
+            }
+            _ => updated.push_str(line),
+        }
+    }
+    updated.push_str(field.unwrap_or_default());
+    updated.push_str(&contents[end..]);
+    updated
+}
+
+/// Returns the project a note belongs to: the first directory of its path
+/// below the root, or `root_project` for notes at the root
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `note_path` - the full path of the note
+fn project_for(config: &Config, note_path: &Path) -> String {
+    let trunc_path = note_path
+        .strip_prefix(&config.root_dir)
+        .unwrap_or(note_path);
+    let mut components = trunc_path.components();
+    match (components.next(), components.next()) {
+        (Some(project), Some(_)) => project.as_os_str().to_string_lossy().into_owned(),
+        _ => config.root_project.clone(),
+    }
+}
+
+/// Sets every note's `project:` field to the project it's in
+///
+/// Binary files are skipped, as are notes `check_writable` refuses, which are
+/// reported. Returns how many notes changed.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn sync_project_fields(config: &Config, notes: &[Note]) -> usize {
+    let mut changed = 0;
+    for note in notes {
+        let Ok(Some(contents)) = read_text(&note.full_path) else {
+            continue;
+        };
+        let updated = set_project_field(&contents, &project_for(config, &note.full_path));
+        if updated == contents {
+            continue;
+        }
+        if let Err(e) = check_writable(config, note) {
+            println!("Skipping {}: {}", note.trunc_path.display(), e);
+            continue;
+        }
+        match write_text(&note.full_path, &updated) {
+            Ok(()) => changed += 1,
+            Err(e) => println!("Failed to update {}: {}", note.trunc_path.display(), e),
+        }
+    }
+    changed
+}
+
+/// Prompts for a pattern and replacement, then renames matching notes
+///
+/// Every planned rename is shown and confirmed once before any are applied.
+/// Notes that match but would collide are reported and skipped. With
+/// `sync_title_on_rename` set, renamed notes have their frontmatter title
+/// updated to the new file name.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+/// * `input` - where to read answers from, usually stdin
+fn bulk_rename(config: &Config, notes: &[Note], input: &mut impl BufRead) {
+    ask(config, "What text should be replaced in note names?");
+    let from = read_input(input);
+    let from = from.trim_end_matches(['\r', '\n']);
+    ask(
+        config,
+        "What should it be replaced with? (leave blank to remove it)",
+    );
+    let to = read_input(input);
+    let to = to.trim_end_matches(['\r', '\n']);
+    if to.contains('/') {
+        println!("'{}' isn't a valid file name", to);
+        return;
+    }
+
+    let mut plan = plan_bulk_rename(notes, from, to);
+    let relative = |path: &Path| {
+        path.strip_prefix(&config.root_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    for note in notes {
+        let name = note
+            .full_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        if !name.contains(from) || plan.iter().any(|(src, _)| *src == note.full_path) {
+            continue;
+        }
+        let new_name = name.replace(from, to);
+        if !is_valid_file_name(&new_name) {
+            println!(
+                "Skipping {}: '{}' isn't a valid file name",
+                note.trunc_path.display(),
+                new_name
+            );
+            continue;
+        }
+        let dest = note.full_path.with_file_name(new_name);
+        let claimed = plan.iter().any(|(_, other)| *other == dest);
+        if dest.exists() && !claimed && dest != note.full_path {
+            match resolve_overwrite(&dest, config.force_overwrite, Some(&mut *input)) {
+                Ok(Some(dest)) if !plan.iter().any(|(_, other)| *other == dest) => {
+                    plan.push((note.full_path.clone(), dest));
+                    continue;
+                }
+                Ok(_) => {}
+                Err(e) => println!("{}", e),
+            }
+        }
+        println!(
+            "Skipping {}: the new name is taken",
+            note.trunc_path.display()
+        );
+    }
+    plan.retain(|(src, dest)| {
+        let writable = match notes.iter().find(|note| note.full_path == *src) {
+            Some(note) => check_writable(config, note),
+            None => check_unlocked(config, src),
+        };
+        match writable.and_then(|_| check_unlocked(config, dest)) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Skipping {}: {}", relative(src), e);
+                false
+            }
+        }
+    });
+    if plan.is_empty() {
+        println!("Nothing to rename");
+        return;
+    }
+
+    println!("\nPlanned renames:");
+    for (src, dest) in &plan {
+        println!("- {} -> {}", relative(src), relative(dest));
+    }
+    if !confirm(input, &format!("Rename these {} notes?", plan.len())) {
+        println!("Cancelling ...");
+        return;
+    }
+    if rename_all(config, &plan) {
+        println!("Renamed {} notes", plan.len());
+    }
+}
+
+/// Renames files as planned, all or nothing
+///
+/// With `sync_title_on_rename` set each note's frontmatter title is updated
+/// too. A file a rename overwrites is staged in the trash first. If a rename
+/// fails, the ones already made are undone, titles and overwritten files
+/// included. Returns if every rename was made.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `plan` - the (source, destination) pairs to rename
+fn rename_all(config: &Config, plan: &[(PathBuf, PathBuf)]) -> bool {
+    let relative = |path: &Path| {
+        path.strip_prefix(&config.root_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let mut transaction = Transaction::new();
+    let mut staged = Vec::new();
+    for (src, dest) in plan {
+        let renamed = stage_overwritten(config, &mut transaction, dest).and_then(|path| {
+            staged.extend(path);
+            std::fs::rename(src, dest)
+        });
+        if let Err(e) = renamed {
+            println!("Failed to rename {}: {}", relative(src), e);
+            roll_back(transaction);
+            return false;
+        }
+        record_operation(Operation::Rename);
+        let mut original = None;
+        if config.sync_title_on_rename {
+            let title = dest.file_stem().unwrap_or_default().to_string_lossy();
+            if let Ok(contents) = std::fs::read_to_string(dest) {
+                let updated = update_title(&contents, &title);
+                if updated != contents {
+                    match write_text(dest, &updated) {
+                        Ok(()) => original = Some(contents),
+                        Err(e) => {
+                            println!("Failed to update the title of {}: {}", relative(dest), e)
+                        }
+                    }
+                }
+            }
+        }
+        transaction.push(
+            format!("rename {} back to {}", relative(dest), relative(src)),
+            move || {
+                if let Some(contents) = original {
+                    write_text(dest, &contents)?;
+                }
+                std::fs::rename(dest, src)
+            },
+        );
+    }
+    if !config.use_trash {
+        for path in staged {
+            let _ = remove_file(path);
+        }
+    }
+    true
+}
+
+/// Prompts the user for a valid project name
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn prompt_for_project_name(config: &Config) -> String {
+    let mut input = String::new();
+    let mut valid_input = false;
+    while !valid_input {
+        println!("\nWhat would you like to name this project?");
+        input = read_input(&mut prompt_input(config));
+        input.truncate(input.trim_end_matches(['\n', '\r']).len());
+
+        // Ensure the input is a valid directory name
+        valid_input = validate_project_name(&input, config.trim_names);
+        if !valid_input {
+            println!(
+                "Potential project name {} contains invalid characters",
+                input
+            );
+            println!("May only use alphanumerics, '_', and '.'");
+        }
+    }
+    String::from(input.trim())
+}
+
+/// Returns if Windows reserves the name for a device, e.g. `CON` or `nul.md`
+///
+/// # Arguments
+///
+/// * `name` - the file or directory name to check
+fn is_reserved_windows_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    let stem = stem.to_ascii_uppercase();
+    match stem.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => {
+            (stem.starts_with("COM") || stem.starts_with("LPT"))
+                && matches!(stem.as_bytes()[3..], [b'1'..=b'9'])
+        }
+    }
+}
+
+/// Ensures the passed project_name is a valid directory name
+///
+/// With `trim` set, whitespace around the name is ignored; otherwise it makes
+/// the name invalid. Names Windows can't store, reserved device names and
+/// names ending in a dot, are rejected everywhere so vaults stay portable.
+///
+/// # Arguments
+///
+/// * project_name - a reference to the project_name
+/// * trim - whether to ignore surrounding whitespace
+fn validate_project_name(project_name: &str, trim: bool) -> bool {
+    let name = if trim {
+        project_name.trim()
+    } else {
+        project_name
+    };
+    if name.is_empty() || name.ends_with('.') || is_reserved_windows_name(name) {
+        return false;
+    }
+
+    // Ensure the input is a valid directory name
+    name.chars()
+        .all(|c| char::is_alphanumeric(c) || ['_', '.'].contains(&c))
+}
+
+/// Creates a new project directory under the root
+///
+/// If `project_creates_note` is set, a starter note is also created inside the
+/// project, rendered by `apply_template` from `templates/project.md` when that
+/// template exists, else from whichever template `resolve_template` picks.
+/// Returns the path of the starter note if one was created.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `project_name` - the (already validated) name of the project
+fn create_project(config: &Config, project_name: &str) -> io::Result<Option<PathBuf>> {
+    if project_name.len() > config.max_name_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "project name is {} bytes long, more than max_name_len ({})",
+                project_name.len(),
+                config.max_name_len
+            ),
+        ));
+    }
+    let project_dir = config.root_dir.join(project_name);
+    create_dir_all(&project_dir)?;
+    println!("New project created: {}", project_name);
+
+    if !config.project_creates_note {
+        return Ok(None);
+    }
+
+    let note_path = create_named_note(config, &project_dir, config.naming_scheme, 1)?;
+    let template = Some(templates_dir(config).join("project.md"))
+        .filter(|path| path.is_file())
+        .or_else(|| resolve_template(config, &project_dir));
+    if let Some(template) = template {
+        apply_template(config, &note_path, &template)?;
+    }
+    finish_creating(config, &note_path);
+    Ok(Some(note_path))
+}
+
+/// Returns every `*.assets` directory whose note no longer exists
+///
+/// Hidden directories, like the trash, are not searched.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+fn orphaned_assets(config: &Config) -> Vec<PathBuf> {
+    let mut orphans = Vec::new();
+    let mut dirs = vec![config.root_dir.clone()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(contents) = read_dir(&dir) else {
+            continue;
+        };
+        for entry in contents.flatten() {
+            let path = entry.path();
+            if !path.is_dir() || entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.extension() != Some("assets".as_ref()) {
+                dirs.push(path);
+                continue;
+            }
+            let has_note = config
+                .note_extensions
+                .iter()
+                .any(|ext| path.with_extension(ext).is_file());
+            if !has_note {
+                orphans.push(path);
+            }
+        }
+    }
+    orphans.sort();
+    orphans
+}
+
+/// Runs every vault health check, returning each check's name and problems
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn diagnose(config: &Config, notes: &[Note]) -> Vec<(&'static str, Vec<String>)> {
+    let relative = |path: &Path| {
+        path.strip_prefix(&config.root_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let unreadable = notes
+        .iter()
+        .filter(|note| std::fs::read(&note.full_path).is_err())
+        .map(|note| relative(&note.full_path))
+        .collect();
+    let bad_names = notes
+        .iter()
+        .filter(|note| note.trunc_path.to_str().is_none())
+        .map(|note| relative(&note.full_path))
+        .collect();
+    let bad_extensions = notes
+        .iter()
+        .filter(|note| {
+            let extension = note.full_path.extension().unwrap_or_default();
+            !config
+                .note_extensions
+                .iter()
+                .any(|ext| extension == ext.as_str())
+        })
+        .map(|note| relative(&note.full_path))
+        .collect();
+    let links = broken_links(notes)
+        .into_iter()
+        .map(|(note, target)| format!("{} -> [[{}]]", relative(&note.full_path), target))
+        .collect();
+    let empty = empty_notes(notes)
+        .into_iter()
+        .map(|note| relative(&note.full_path))
+        .collect();
+    let orphans = orphaned_assets(config)
+        .iter()
+        .map(|path| relative(path))
+        .collect();
+
+    vec![
+        ("unreadable files", unreadable),
+        ("non-UTF-8 names", bad_names),
+        ("unexpected extensions", bad_extensions),
+        ("broken wikilinks", links),
+        ("empty notes", empty),
+        ("orphaned assets directories", orphans),
+    ]
+}
+
+/// Prints the health check results, returning whether every check passed
+///
+/// # Arguments
+///
+/// * `report` - each check's name and problems
+fn print_diagnosis(report: &[(&str, Vec<String>)]) -> bool {
+    let mut problems = 0;
+    for (check, found) in report {
+        if found.is_empty() {
+            println!("ok    {}", check);
+            continue;
+        }
+        println!("FAIL  {} ({})", check, found.len());
+        for problem in found {
+            println!("      - {}", problem);
+        }
+        problems += found.len();
+    }
+    if problems == 0 {
+        println!("\nNo problems found");
+    } else {
+        println!("\n{} problems found", problems);
+    }
+    problems == 0
+}
+
+/// The kinds of change to notes counted in a `Summary`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operation {
+    Create,
+    Delete,
+    Move,
+    Rename,
+}
+
+/// How many notes each kind of change touched this run
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Summary {
+    created: usize,
+    deleted: usize,
+    moved: usize,
+    renamed: usize,
+}
+
+impl Summary {
+    /// Counts one more note changed by the passed operation
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - what was done to the note
+    fn record(&mut self, operation: Operation) {
+        match operation {
+            Operation::Create => self.created += 1,
+            Operation::Delete => self.deleted += 1,
+            Operation::Move => self.moved += 1,
+            Operation::Rename => self.renamed += 1,
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    /// Lists the non-zero counts, naming notes on the first only, e.g.
+    /// "created 1 note, deleted 2, moved 1"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = [
+            ("created", self.created),
+            ("deleted", self.deleted),
+            ("moved", self.moved),
+            ("renamed", self.renamed),
+        ];
+        let mut first = true;
+        for (verb, count) in counts.into_iter().filter(|(_, count)| *count > 0) {
+            if first {
+                let noun = if count == 1 { "note" } else { "notes" };
+                write!(f, "{} {} {}", verb, count, noun)?;
+                first = false;
+            } else {
+                write!(f, ", {} {}", verb, count)?;
+            }
+        }
+        if first {
+            write!(f, "no notes changed")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every change made to notes this run, printed before clife exits
+static SUMMARY: Mutex<Summary> = Mutex::new(Summary {
+    created: 0,
+    deleted: 0,
+    moved: 0,
+    renamed: 0,
+});
+
+/// Counts a change to a note towards this run's summary
+///
+/// # Arguments
+///
+/// * `operation` - what was done to the note
+fn record_operation(operation: Operation) {
+    SUMMARY
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .record(operation);
+}
+
+/// Returns the changes made to notes this run so far
+fn run_summary() -> Summary {
+    *SUMMARY
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// The note most recently created this run, removed on Ctrl-C if still empty
+static JUST_CREATED: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// How many commands, like the editor, clife is currently waiting on in the
+/// foreground. Ctrl-C is theirs to handle while there are any.
+static FOREGROUND_CHILDREN: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// Runs a command in the foreground and waits for it to exit
+///
+/// Ctrl-C reaches the whole foreground process group, so while the command
+/// runs clife leaves it to the command instead of cleaning up and exiting
+/// under it.
+///
+/// # Arguments
+///
+/// * `command` - the command to run
+fn wait_in_foreground(command: &mut std::process::Command) -> io::Result<ExitStatus> {
+    FOREGROUND_CHILDREN.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    let status = command.status();
+    FOREGROUND_CHILDREN.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+    status
+}
+
+/// Write end of the pipe the SIGINT handler wakes the cleanup thread through
+#[cfg(unix)]
+static SIGINT_PIPE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+/// C's `struct sigaction`, with the handler as a plain function
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct SigAction {
+    sa_handler: extern "C" fn(i32),
+    sa_mask: [u64; 16],
+    sa_flags: i32,
+    sa_restorer: usize,
+}
+
+/// C's `struct sigaction`, with the handler as a plain function
+#[cfg(target_os = "macos")]
+#[repr(C)]
+struct SigAction {
+    sa_handler: extern "C" fn(i32),
+    sa_mask: u32,
+    sa_flags: i32,
+}
+
+/// Restarts reads the signal interrupts, like the one waiting on a prompt
+#[cfg(target_os = "linux")]
+const SA_RESTART: i32 = 0x1000_0000;
+#[cfg(target_os = "macos")]
+const SA_RESTART: i32 = 0x0002;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+extern "C" {
+    fn sigaction(signum: i32, act: *const SigAction, old: *mut SigAction) -> i32;
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn pipe(fds: *mut i32) -> i32;
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+}
+
+/// Tidies up after Ctrl-C, before clife exits
+///
+/// Removes the note created this run if nothing was written to it and any
+/// temporary files `atomic_write` left behind, and brings the metadata cache
+/// up to date. Runs holding `WRITE_LOCK`, so no other write is under way.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `just_created` - the note most recently created this run, if any
+fn interrupt_cleanup(config: &Config, just_created: Option<&Path>) {
+    if let Some(path) = just_created {
+        if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0) {
+            let _ = remove_file(path);
+        }
+    }
+    if config.root_dir.is_dir() {
+        remove_temp_files(&config.root_dir);
+        let notes = create_note_objects(config);
+        let _ = atomic_write_locked(&cache_path(config), render_cache(&notes).as_bytes());
+    }
+}
+
+/// Removes the temporary files this run's `atomic_write` calls left under a
+/// directory
+///
+/// # Arguments
+///
+/// * `root` - the directory to search
+fn remove_temp_files(root: &Path) {
+    let suffix = format!(".{}.tmp", std::process::id());
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(contents) = read_dir(&dir) else {
+            continue;
+        };
+        for entry in contents.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => dirs.push(entry.path()),
+                Ok(_) if name.starts_with('.') && name.ends_with(&suffix) => {
+                    let _ = remove_file(entry.path());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Runs from the SIGINT handler, so only wakes the cleanup thread, and only
+/// when no foreground command is running
+#[cfg(unix)]
+extern "C" fn on_sigint(_signum: i32) {
+    if FOREGROUND_CHILDREN.load(std::sync::atomic::Ordering::SeqCst) > 0 {
+        return;
+    }
+    let fd = SIGINT_PIPE.load(std::sync::atomic::Ordering::Relaxed);
+    let byte = 1u8;
+    unsafe {
+        write(fd, &byte, 1);
+    }
+}
+
+/// Makes Ctrl-C run `interrupt_cleanup` and exit with code 130
+///
+/// The signal handler itself only writes to a pipe; a thread waiting on the
+/// other end waits for any write under way to finish, then does the actual
+/// cleanup. Ctrl-C while the editor or another command started by
+/// `wait_in_foreground` runs is left to that command.
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn install_sigint_handler(config: &Config) {
+    use std::os::unix::io::FromRawFd;
+
+    const SIGINT: i32 = 2;
+    let mut fds = [0; 2];
+    if unsafe { pipe(fds.as_mut_ptr()) } != 0 {
+        return;
+    }
+    SIGINT_PIPE.store(fds[1], std::sync::atomic::Ordering::Relaxed);
+    let config = config.clone();
+    std::thread::spawn(move || {
+        let mut wake = unsafe { File::from_raw_fd(fds[0]) };
+        if io::Read::read_exact(&mut wake, &mut [0]).is_ok() {
+            // Held until exit, so no write is cut off or started after this
+            let _writing = WRITE_LOCK
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            let just_created = JUST_CREATED
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .clone();
+            println!();
+            interrupt_cleanup(&config, just_created.as_deref());
+            exit(130);
+        }
+    });
+    let action = SigAction {
+        sa_handler: on_sigint,
+        sa_mask: Default::default(),
+        sa_flags: SA_RESTART,
+        #[cfg(target_os = "linux")]
+        sa_restorer: 0,
+    };
+    unsafe {
+        sigaction(SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+/// Ctrl-C keeps its default behaviour where clife can't set a SIGINT handler
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn install_sigint_handler(_config: &Config) {}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cli = match parse_args(&args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            println!("{}", e);
+            exit(2);
+        }
+    };
+    let mut config = match load_config(cli.lenient_config).and_then(|config| {
+        validate_root_dir(&config)?;
+        Ok(config)
+    }) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
+
+    if let Some(sort) = cli.sort {
+        config.sort = sort;
+        config.set_source("sort", ConfigSource::Flag("--sort"));
+    }
+    if cli.menu {
+        config.default_action = None;
+        config.set_source("default_action", ConfigSource::Flag("--menu"));
+    }
+    if cli.force {
+        config.force_overwrite = true;
+        config.set_source("force_overwrite", ConfigSource::Flag("--force"));
+        config.override_locks = true;
+    }
+    if cli.include_nested {
+        config.include_nested = true;
+        config.set_source("include_nested", ConfigSource::Flag("--include-nested"));
+    }
+    if cli.verbose {
+        config.verbose = true;
+        config.set_source("verbose", ConfigSource::Flag("--verbose"));
+    }
+    if let Some(project) = &cli.focus {
+        if let Err(e) = apply_focus(&mut config, project) {
+            println!("{}", e);
+            exit(2);
+        }
+    }
+
+    install_sigint_handler(&config);
+    if cli.command == Command::Menu {
+        let _ = write_banner(&config, &mut io::stdout());
+    }
+
+    if cli.command == Command::ShowConfig {
+        print!("{}", describe_config(&config));
+        return;
+    }
+
+    if cli.command == Command::EditConfig {
+        edit_config(&config);
+        return;
+    }
+
+    if let Err(e) = ensure_root_folder(&config, &mut prompt_input(&config)) {
+        println!("{}", e);
+        exit(1);
+    }
+
+    purge_expired_trash(&config);
+    let mut notes = create_note_objects(&config);
+    sort_notes(&mut notes, config.sort);
+
+    let cached = load_cache(&config);
+    if let Err(e) = save_cache(&config, &notes) {
+        println!("Failed to update the metadata cache: {}", e);
+    }
+
+    match cli.command {
+        Command::Menu => {
+            if let Some(cached) = cached {
+                print_scan_diff(&diff_scans(&cached, &notes));
+            }
+            println!("inbox: {}", inbox_count(&config, &notes));
+            if config.report_empty_projects {
+                offer_empty_project_cleanup(&config, &mut prompt_input(&config));
+            }
+            run_menu(&config, &notes);
+            let _ = save_cache(&config, &create_note_objects(&config));
+        }
+        Command::Scratch => run_scratch(&config),
+        Command::Last => open_last(&config, &notes),
+        Command::Random => {
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64);
+            match pick_random(&notes, seed) {
+                Some(note) => {
+                    let _ = open_in_editor(&config, &note.full_path);
+                }
+                None => println!("There are no notes to pick from"),
+            }
+        }
+        Command::ShowConfig | Command::EditConfig => {
+            unreachable!("handled before the notes are loaded")
+        }
+        Command::Tags => print_tag_counts(&tag_counts(&notes)),
+        Command::Tagged { tags, all } => {
+            let matching = notes_matching_tags(&notes, &tags, all);
+            if matching.is_empty() {
+                println!("No notes found");
+            }
+            for note in matching {
+                println!("- {}", display_name(note, config.display));
+            }
+        }
+        Command::Projects { depth } => print_project_counts(&count_by_project(&notes, depth)),
+        Command::Index => {
+            let path = config.root_dir.join(INDEX_FILE);
+            match write_text(&path, &build_index(&notes)) {
+                Ok(()) => println!("Indexed {} notes in {}", notes.len(), path.display()),
+                Err(e) => {
+                    println!("Failed to write {}: {}", path.display(), e);
+                    exit(1);
+                }
+            }
+        }
+        Command::Lint { fix } => {
+            if lint_notes(&config, &notes, fix) > 0 && !fix {
+                exit(1);
+            }
+        }
+        Command::Reindex => match reindex(&config) {
+            Ok(count) => println!("Indexed {} notes", count),
+            Err(e) => {
+                println!("Failed to rebuild the metadata cache: {}", e);
+                exit(1);
+            }
+        },
+        Command::Doctor => {
+            if !print_diagnosis(&diagnose(&config, &notes)) {
+                exit(1);
+            }
+        }
+        Command::New {
+            template,
+            name,
+            fence,
+        } => {
+            let note_suffix = next_note_suffix(&note_file_names(&notes));
+            let piped = !stdin().is_terminal();
+            let result = if piped {
+                create_note_from_input(
+                    &config,
+                    template.as_deref(),
+                    name.as_deref(),
+                    note_suffix,
+                    fence.as_ref().map(Option::as_deref),
+                    &mut stdin().lock(),
+                )
+            } else {
+                create_note_from_template(
+                    &config,
+                    template.as_deref(),
+                    name.as_deref(),
+                    note_suffix,
+                )
+            };
+            match result {
+                Ok(note_path) => {
+                    if !piped {
+                        let _ = open_in_editor(&config, &note_path);
+                    }
+                }
+                Err(e) => {
+                    println!("Failed to create note: {}", e);
+                    exit(1);
+                }
+            }
+        }
+        Command::Graph { output } => {
+            let dot = build_dot_graph(&notes);
+            match output {
+                Some(path) => {
+                    if let Err(e) = write_text(&path, &dot) {
+                        println!("Failed to write {}: {}", path.display(), e);
+                        exit(1);
+                    }
+                }
+                None => print!("{}", dot),
+            }
+        }
+        Command::Search {
+            query,
+            context,
+            case_sensitive,
+        } => {
+            let case_sensitive = case_sensitive || config.search_case_sensitive;
+            search_notes(
+                &notes,
+                &query,
+                context,
+                case_sensitive,
+                io::stdout().is_terminal(),
+            )
+        }
+        Command::Cat {
+            note,
+            strip_frontmatter,
+        } => {
+            let found = find_note_or_exit(&notes, &note);
+            if let Err(e) = cat_note(found, strip_frontmatter, &mut io::stdout()) {
+                println!("Failed to read {}: {}", note, e);
+                exit(1);
+            }
+        }
+        Command::EmptyTrash => {
+            if let Err(e) = empty_trash(&config, &mut prompt_input(&config)) {
+                println!("Failed to empty the trash: {}", e);
+                exit(1);
+            }
+        }
+        Command::ExportProject { name, dest } => {
+            match export_project(&config, &notes, &name, &dest) {
+                Ok(count) => println!("Exported {} notes to {}", count, dest.display()),
+                Err(e) => {
+                    println!("Failed to export {}: {}", name, e);
+                    exit(1);
+                }
+            }
+        }
+        Command::ExportHtml { note, dest } => {
+            let found = find_note_or_exit(&notes, &note);
+            match export_note_html(&config, found, &dest) {
+                Ok(()) => println!("Exported {} to {}", note, dest.display()),
+                Err(e) => {
+                    println!("Failed to export {}: {}", note, e);
+                    exit(1);
+                }
+            }
+        }
+        Command::Move { note, dir } => {
+            let found = find_note_or_exit(&notes, &note);
+            let Some(dir) = parse_project_path(&dir) else {
+                println!("'{}' isn't a valid directory", dir);
+                exit(2);
+            };
+            let result = move_note(&config, found, &dir, None);
+            let failed = result.is_err();
+            report_move(found, result);
+            if failed {
+                exit(1);
+            }
+        }
+        Command::Tag { note, tags } => {
+            let found = find_note_or_exit(&notes, &note);
+            for tag in tags {
+                match tag_note(&config, found, &tag) {
+                    Ok(true) => println!("Tagged {} with {}", note, tag),
+                    Ok(false) => println!("{} is already tagged {}", note, tag),
+                    Err(e) => {
+                        println!("Failed to tag {}: {}", note, e);
+                        exit(1);
+                    }
+                }
+            }
+        }
+        Command::Rename { note, name } => {
+            let found = find_note_or_exit(&notes, &note);
+            match rename_note(&config, found, &name) {
+                Ok(dest) => println!("Renamed {} to {}", note, dest.display()),
+                Err(e) => {
+                    println!("Failed to rename {}: {}", note, e);
+                    exit(1);
+                }
+            }
+        }
+        Command::Delete { note, yes } => {
+            let found = find_note_or_exit(&notes, &note);
+            if !yes && !confirm_delete(found, &mut prompt_input(&config)) {
+                println!("Cancelling ...");
+                exit(1);
+            }
+            if !remove_note(&config, found) {
+                exit(1);
+            }
+        }
+    }
+
+    let summary = run_summary();
+    if !cli.quiet && summary != Summary::default() {
+        println!("\nThis run {}", summary);
+    }
+}
+
+/// Finds a note named on the command line, exiting if there isn't one
+///
+/// # Arguments
+///
+/// * `notes` - a reference to the notes vector
+/// * `query` - the note's relative path
+fn find_note_or_exit<'a>(notes: &'a [Note], query: &str) -> &'a Note {
+    find_note(notes, query).unwrap_or_else(|| {
+        println!("No note named {}", query);
+        exit(1);
+    })
+}
+
+/// Runs the interactive menu, prompting for an action and carrying it out
+///
+/// # Arguments
+///
+/// * `config` - a reference to a config object
+/// * `notes` - a reference to the notes vector
+fn run_menu(config: &Config, notes: &[Note]) {
+    println!("Found {} notes", notes.len());
+
+    let action = match config.default_action {
+        Some(action) => action,
+        None if config.remember_last_action => {
+            let action =
+                prompt_for_action(config, &mut prompt_input(config), load_last_action(config));
+            if let Err(e) = save_last_action(config, action) {
+                println!("Failed to remember the chosen action: {}", e);
+            }
+            action
+        }
+        None => prompt_for_action(config, &mut prompt_input(config), None),
+    };
+
+    match action {
+        Action::CreateNote => {
+            match prompt_and_create_note(
+                config,
+                &mut prompt_input(config),
+                next_note_suffix(&note_file_names(notes)),
+            ) {
+                Ok(note_path) => {
+                    let status = open_in_editor(config, &note_path);
+                    cleanup_after_editor_abort(
+                        config,
+                        &note_path,
+                        &status,
+                        &mut prompt_input(config),
+                    );
+                }
+                Err(e) => {
+                    println!("Giving up on creating a note: {}", e);
+                    exit(1);
+                }
+            }
+        }
+        Action::Delete => delete_chosen_note(config, notes, &mut prompt_input(config)),
+        Action::Edit => {
+            let selected = prompt_for_notes(config, notes, "edit", &mut prompt_input(config));
+            let paths: Vec<&Path> = selected
+                .iter()
+                .map(|note| note.full_path.as_path())
+                .collect();
+            if let Err(e) = open_all_in_editor(config, &paths) {
+                println!("Failed to open the editor: {}", e);
+                exit(1);
+            }
+        }
+        Action::File => {
+            file_notes(config, notes, &mut prompt_input(config));
+        }
+        Action::Link => {
+            if let Err(e) = link_notes(config, notes, &mut prompt_input(config)) {
+                println!("Failed to link notes: {}", e);
+                exit(1);
+            }
+        }
+        Action::PruneEmpty => {
+            prune_empty_notes(config, notes, &mut prompt_input(config));
+        }
+        Action::Attach => {
+            let note =
+                prompt_for_note(config, notes, "attach a file to", &mut prompt_input(config));
+            ask(config, "What file would you like to attach?");
+            let src = read_input(&mut prompt_input(config));
+            match attach_file(config, note, Path::new(src.trim())) {
+                Ok(dest) => println!("Attached {}", dest.display()),
+                Err(e) => {
+                    println!("Failed to attach file: {}", e);
+                    exit(1);
+                }
+            }
+        }
+        Action::List => list_notes(config, notes),
+        Action::Duplicates => {
+            remove_duplicates(config, notes, &mut prompt_input(config));
+        }
+        Action::BulkRename => {
+            bulk_rename(config, notes, &mut prompt_input(config));
+        }
+        Action::Toc => {
+            let note = prompt_for_note(config, notes, "outline", &mut prompt_input(config));
+            match std::fs::read_to_string(&note.full_path) {
+                Ok(contents) => print_toc(&extract_headings(&contents)),
+                Err(e) => {
+                    println!("Failed to read {}: {}", note.trunc_path.display(), e);
+                    exit(1);
+                }
+            }
+        }
+        Action::Scratch => run_scratch(config),
+        Action::Cat => {
+            let note = prompt_for_note(config, notes, "print", &mut prompt_input(config));
+            if let Err(e) = cat_note(note, false, &mut io::stdout()) {
+                println!("Failed to read {}: {}", note.trunc_path.display(), e);
+                exit(1);
+            }
+        }
+        Action::Search => {
+            ask(config, "What would you like to search for?");
+            let query = read_input(&mut prompt_input(config));
+            search_notes(
+                notes,
+                query.trim(),
+                0,
+                config.search_case_sensitive,
+                io::stdout().is_terminal(),
+            );
+        }
+        Action::Process => process_inbox(config, notes, &mut prompt_input(config)),
+        Action::UndoCreate => undo_create(config, notes, &mut prompt_input(config)),
+        Action::Last => open_last(config, notes),
+        Action::Retag => retag_notes(config, notes, &mut prompt_input(config)),
+        Action::Tree => print!("{}", render_tree(&build_tree(notes))),
+        Action::Lint => {
+            lint_notes(config, notes, false);
+        }
+        Action::Promote => promote_note(config, notes, &mut prompt_input(config)),
+        Action::Demote => demote_note(config, notes, &mut prompt_input(config)),
+        Action::Projects => match list_projects(&config.root_dir) {
+            Ok(projects) if projects.is_empty() => println!("There are no projects yet"),
+            Ok(projects) => {
+                for (project, count) in projects {
+                    println!("{:>5}  {}", count, project);
+                }
+            }
+            Err(e) => println!("Failed to list projects: {}", e),
+        },
+        Action::Tag => tag_notes(config, notes, &mut prompt_input(config)),
+        Action::Agenda => {
+            let today = Date::from(DateTime::from_system_time(SystemTime::now()));
+            print!("{}", render_agenda(&agenda(notes), today));
+        }
+        Action::SyncProject => {
+            println!("Updated {} notes", sync_project_fields(config, notes));
+        }
+        Action::CreateProject => {
+            let project_name = prompt_for_project_name(config);
+            match create_project(config, &project_name) {
+                Ok(Some(note_path)) => {
+                    let _ = open_in_editor(config, &note_path);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    println!("Failed to create project: {}", e);
+                    exit(1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A test's temporary directory, removed again when the test ends
+    struct TempRoot(PathBuf);
+
+    impl std::ops::Deref for TempRoot {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempRoot {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempRoot {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Returns a fresh, empty directory under the system temp dir for a test
+    fn temp_root(name: &str) -> TempRoot {
+        let dir = std::env::temp_dir().join(format!("clife_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        TempRoot(dir)
+    }
+
+    #[test]
+    fn test_detect_root_folder_exists() {
+        let config = Config {
+            root_dir: PathBuf::from("/home"),
+            ..Default::default()
+        };
+        let result: bool = detect_root_folder(&config);
+        assert!(result)
+    }
+
+    #[test]
+    fn test_detect_root_folder_not_exists() {
+        let config = Config {
+            root_dir: PathBuf::from("~/nonsense_folder_ntuyfwntw/"),
+            ..Default::default()
+        };
+        let result: bool = detect_root_folder(&config);
+        assert!(!result)
+    }
+
+    #[test]
+    fn test_create_note_objects() {
+        let config = Config {
+            root_dir: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/test_data/.clife/")),
+            ..Default::default()
+        };
+        let result: Vec<Note> = create_note_objects(&config);
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn test_valid_project_name() {
+        let valid_names = [
+            "test",
+            "test_1",
+            "my.project",
+            ".HELLO.P_Arker_",
+            "   hello   ",
+        ];
+
+        for name in valid_names {
+            assert!(validate_project_name(&String::from(name), true));
+        }
+    }
+
+    #[test]
+    fn test_invalid_project_name() {
+        let invalid_names = ["hello parker", "&parker", "_hello_("];
+
+        for name in invalid_names {
+            assert!(!validate_project_name(&String::from(name), true));
+        }
+    }
+
+    #[test]
+    fn test_reserved_windows_name() {
+        assert!(is_reserved_windows_name("CON"));
+        assert!(is_reserved_windows_name("con.md"));
+        assert!(is_reserved_windows_name("Lpt3.txt"));
+        assert!(!is_reserved_windows_name("console.md"));
+        assert!(!is_reserved_windows_name("COM0"));
+        assert!(!is_reserved_windows_name("plan.md"));
+
+        assert!(!validate_project_name("nul", true));
+        assert!(!validate_project_name("trailing.", true));
+        assert!(validate_project_name("safe_name", true));
+    }
+
+    #[test]
+    fn test_untrimmed_project_name() {
+        assert!(validate_project_name("hello", false));
+        for name in ["   hello   ", " hello", "hello\t", "   "] {
+            assert!(!validate_project_name(name, false));
+        }
+        assert!(!validate_project_name("   ", true));
+    }
+
+    #[test]
+    fn test_create_project_with_starter_note() {
+        let root = temp_root("create_project_with_starter_note");
+        let config = Config {
+            root_dir: root.clone(),
+            project_creates_note: true,
+            ..Default::default()
+        };
+        let note_path = create_project(&config, "work").unwrap();
+
+        assert!(root.join("work").is_dir());
+        assert_eq!(note_path, Some(root.join("work").join("new_note_1.md")));
+        assert_eq!(read_dir(root.join("work")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_create_project_renders_its_template() {
+        let root = temp_root("create_project_renders_its_template");
+        let config = Config {
+            root_dir: root.clone(),
+            project_creates_note: true,
+            sync_project_on_create: true,
+            ..Default::default()
+        };
+        write_note(&root, "templates/project.md", "Started {{title}}\n");
+        let note_path = create_project(&config, "work").unwrap().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&note_path).unwrap(),
+            "---\nproject: work\n---\nStarted new_note_1\n"
+        );
+    }
+
+    #[test]
+    fn test_prompt_and_create_note_retries() {
+        let root = temp_root("prompt_and_create_note_retries");
+        create_dir_all(root.join("work")).unwrap();
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let mut input = io::Cursor::new("missing\nalso_missing\nwork\n");
+        let note_path = prompt_and_create_note(&config, &mut input, 1).unwrap();
+
+        assert_eq!(note_path, root.join("work").join("new_note_1.md"));
+        assert!(note_path.is_file());
+    }
+
+    #[test]
+    fn test_prompt_and_create_note_gives_up() {
+        let root = temp_root("prompt_and_create_note_gives_up");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let mut input = io::Cursor::new("a\nb\nc\nd\n");
+        let result = prompt_and_create_note(&config, &mut input, 1);
+
+        assert!(result.is_err());
+        assert_eq!(read_dir(&root).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_create_new_note_invalid_dir() {
+        let root = temp_root("create_new_note_invalid_dir");
+        let config = Config::default();
+        assert!(create_new_note(&config, &root.join("nope"), 1).is_err());
+    }
+
+    #[test]
+    fn test_prompt_for_action_only_enabled() {
+        let config = Config {
+            enabled_actions: vec![Action::CreateNote],
+            ..Default::default()
+        };
+        let mut input = io::Cursor::new("d\nc\n");
+        let action = prompt_for_action(&config, &mut input, None);
+
+        assert_eq!(action, Action::CreateNote);
+        // The rejected "d" must have been read and skipped
+        assert_eq!(input.position(), 4);
+    }
+
+    #[test]
+    fn test_config_enabled_actions() {
+        let mut config = Config::default();
+        config
+            .apply_file("# only the basics\nenabled_actions = [\"create\", \"edit\"]\n")
+            .unwrap();
+
+        assert_eq!(
+            config.enabled_actions,
+            vec![Action::CreateNote, Action::Edit]
+        );
+        assert!(!config.action_enabled(Action::Delete));
+    }
+
+    #[test]
+    fn test_config_unknown_action() {
+        let mut config = Config::default();
+        let result = config.apply_file("enabled_actions = [\"create\", \"frobnicate\"]");
+
+        match result {
+            Err(AppError::Config(message)) => assert!(message.contains("frobnicate")),
+            _ => panic!("expected a config error"),
+        }
+    }
+
+    /// Writes a note under the root and returns it
+    fn write_note(root: &Path, trunc_path: &str, contents: &str) -> Note {
+        let full_path = root.join(trunc_path);
+        create_dir_all(full_path.parent().unwrap()).unwrap();
+        std::fs::write(&full_path, contents).unwrap();
+        Note {
+            full_path,
+            trunc_path: PathBuf::from(trunc_path),
+            root: root.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn test_split_frontmatter() {
+        let (frontmatter, body) = split_frontmatter("---\ntitle: Hi\n---\nbody\n");
+        assert_eq!(frontmatter, Some("title: Hi\n"));
+        assert_eq!(body, "body\n");
+
+        assert_eq!(
+            split_frontmatter("no frontmatter"),
+            (None, "no frontmatter")
+        );
+        assert_eq!(
+            split_frontmatter("---\nunterminated"),
+            (None, "---\nunterminated")
+        );
+    }
+
+    #[test]
+    fn test_desired_dir() {
+        let root = temp_root("desired_dir");
+        let filed = write_note(&root, "a.md", "---\ntitle: A\nproject: work\n---\nhello\n");
+        let quoted = write_note(&root, "b.md", "---\nproject: \"work/clients\"\n---\n");
+        let unfiled = write_note(&root, "c.md", "---\ntitle: C\n---\nproject: work\n");
+        let invalid = write_note(&root, "d.md", "---\nproject: ../elsewhere\n---\n");
+
+        assert_eq!(desired_dir(&filed), Some(PathBuf::from("work")));
+        assert_eq!(desired_dir(&quoted), Some(PathBuf::from("work/clients")));
+        assert_eq!(desired_dir(&unfiled), None);
+        assert_eq!(desired_dir(&invalid), None);
+    }
+
+    #[test]
+    fn test_file_notes_moves_after_confirming() {
+        let root = temp_root("file_notes_moves_after_confirming");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "a.md", "---\nproject: work\n---\n"),
+            write_note(&root, "work/b.md", "---\nproject: work\n---\n"),
+        ];
+        file_notes(&config, &notes, &mut io::Cursor::new("y\n"));
+
+        assert!(!root.join("a.md").exists());
+        assert!(root.join("work").join("a.md").is_file());
+        assert!(root.join("work").join("b.md").is_file());
+    }
+
+    #[test]
+    fn test_file_notes_rollback_restores_overwritten_files() {
+        let root = temp_root("file_notes_rollback_restores_overwritten_files");
+        let config = Config {
+            root_dir: root.clone(),
+            force_overwrite: true,
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "a.md", "---\nproject: new/deep\n---\n"),
+            write_note(&root, "b.md", "---\nproject: work\n---\n"),
+            write_note(&root, "work/b.md", "already here\n"),
+            // Its project is a file, so its move fails after the others are made
+            write_note(&root, "c.md", "---\nproject: blocked\n---\n"),
+        ];
+        std::fs::write(root.join("blocked"), "").unwrap();
+        file_notes(&config, &notes, &mut io::Cursor::new("y\n"));
+
+        assert!(root.join("a.md").is_file());
+        assert!(!root.join("new").exists());
+        assert!(root.join("b.md").is_file());
+        assert_eq!(
+            std::fs::read_to_string(root.join("work/b.md")).unwrap(),
+            "already here\n"
+        );
+        assert!(trash_entries(&config).is_empty());
+
+        // Without the failing note the overwritten file is left in the trash
+        file_notes(&config, &notes[..3], &mut io::Cursor::new("y\n"));
+        assert!(root.join("new/deep/a.md").is_file());
+        assert_eq!(
+            std::fs::read_to_string(root.join(".trash/work/b.md")).unwrap(),
+            "already here\n"
+        );
+    }
+
+    #[test]
+    fn test_file_notes_skips_unwritable_notes() {
+        let root = temp_root("file_notes_skips_unwritable_notes");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let shared = temp_root("file_notes_skips_unwritable_notes_shared");
+        write_note(&root, "a.md", "---\nproject: work\n---\n");
+        write_note(&root, "b.md", "---\nproject: work\nlocked: true\n---\n");
+        write_note(&shared, "c.md", "---\nproject: work\n---\n");
+        std::os::unix::fs::symlink(shared.join("c.md"), root.join("c.md")).unwrap();
+        let notes = create_note_objects(&config);
+        file_notes(&config, &notes, &mut io::Cursor::new("y\n"));
+
+        // The locked note and the link are left alone rather than failing the batch
+        assert!(root.join("work/a.md").is_file());
+        assert!(root.join("b.md").is_file());
+        assert!(root.join("c.md").is_symlink());
+        assert!(!root.join("work/b.md").exists());
+        assert!(!root.join("work/c.md").exists());
+    }
+
+    #[test]
+    fn test_file_notes_plan_leaves_out_locked_destinations() {
+        let root = temp_root("file_notes_plan_leaves_out_locked_destinations");
+        let config = Config {
+            root_dir: root.clone(),
+            force_overwrite: true,
+            ..Default::default()
+        };
+        let locked = "---\nlocked: true\n---\n";
+        let notes = vec![
+            write_note(&root, "a.md", "---\nproject: work\n---\n"),
+            write_note(&root, "b.md", "---\nproject: work\n---\n"),
+            write_note(&root, "work/b.md", locked),
+        ];
+        file_notes(&config, &notes, &mut io::Cursor::new("y\n"));
+
+        // Only the move that was shown and confirmed is made
+        assert!(root.join("work/a.md").is_file());
+        assert!(root.join("b.md").is_file());
+        assert_eq!(
+            std::fs::read_to_string(root.join("work/b.md")).unwrap(),
+            locked
+        );
+    }
+
+    #[test]
+    fn test_file_notes_collision_aborts_every_move() {
+        let root = temp_root("file_notes_collision_aborts_every_move");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "a.md", "---\nproject: work\n---\n"),
+            write_note(&root, "b.md", "---\nproject: work\n---\n"),
+            write_note(&root, "work/b.md", "already here\n"),
+        ];
+        let moves: Vec<(&Note, PathBuf)> = notes[..2]
+            .iter()
+            .map(|note| (note, PathBuf::from("work")))
+            .collect();
+        let plan = plan_moves(&config, &moves);
+        assert_eq!(
+            render_move_plan(&config, &plan),
+            "- a.md -> work/a.md\n- b.md -> work/b.md\n"
+        );
+        assert_eq!(plan_collisions(&plan, false), [root.join("work/b.md")]);
+        assert!(plan_collisions(&plan, true).is_empty());
+
+        // No answer is given, as nothing should be asked
+        file_notes(&config, &notes, &mut io::Cursor::new(""));
+        assert!(root.join("a.md").is_file());
+        assert!(root.join("b.md").is_file());
+        assert!(!root.join("work/a.md").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.join("work/b.md")).unwrap(),
+            "already here\n"
+        );
+    }
+
+    #[test]
+    fn test_validate_root_dir_rejects_file() {
+        let root = temp_root("validate_root_dir_rejects_file");
+        let file = root.join("not_a_dir");
+        std::fs::write(&file, "").unwrap();
+        let config = Config {
+            root_dir: file,
+            ..Default::default()
+        };
+
+        match validate_root_dir(&config) {
+            Err(AppError::Config(message)) => {
+                assert!(message.starts_with("root_dir points to a file"))
+            }
+            _ => panic!("expected a config error"),
+        }
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        assert!(validate_root_dir(&config).is_ok());
+    }
+
+    #[test]
+    fn test_generate_note_id() {
+        // 2024-06-01 15:30:42 UTC
+        let now = UNIX_EPOCH + Duration::from_secs(1_717_255_842);
+
+        assert_eq!(
+            generate_note_id(NamingScheme::Incremental, now, 4),
+            "new_note_4"
+        );
+        assert_eq!(
+            generate_note_id(NamingScheme::Timestamp, now, 0),
+            "202406011530"
+        );
+        assert_eq!(
+            generate_note_id(NamingScheme::Timestamp, now, 1),
+            "20240601153043"
+        );
+
+        let short = generate_note_id(NamingScheme::UuidShort, now, 0);
+        assert_eq!(short.len(), 8);
+        assert!(short.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(short, generate_note_id(NamingScheme::UuidShort, now, 1));
+    }
+
+    #[test]
+    fn test_create_new_note_timestamp_collision() {
+        let root = temp_root("create_new_note_timestamp_collision");
+        let config = Config {
+            root_dir: root.clone(),
+            naming_scheme: NamingScheme::Timestamp,
+            ..Default::default()
+        };
+        let first = create_new_note(&config, &root, 1).unwrap();
+        let second = create_new_note(&config, &root, 1).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(read_dir(&root).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_note_title_fallback() {
+        let root = temp_root("note_title_fallback");
+        let titled = write_note(&root, "202406011530.md", "---\ntitle: Groceries\n---\n");
+        let untitled = write_note(&root, "work/plan.md", "just a body\n");
+
+        assert_eq!(note_title(&titled), "Groceries");
+        assert_eq!(note_title(&untitled), "plan");
+        assert_eq!(display_name(&untitled, DisplayMode::Path), "work/plan.md");
+        assert_eq!(
+            display_name(&titled, DisplayMode::Both),
+            "Groceries (202406011530.md)"
+        );
+    }
+
+    #[test]
+    fn test_prompt_for_note_by_number() {
+        let root = temp_root("prompt_for_note_by_number");
+        let config = Config {
+            root_dir: root.clone(),
+            display: DisplayMode::Title,
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "a.md", "---\ntitle: Zebra\n---\n"),
+            write_note(&root, "b.md", "---\ntitle: Apple\n---\n"),
+        ];
+        let note = prompt_for_note(&config, &notes, "edit", &mut io::Cursor::new("9\n2\n"));
+
+        assert_eq!(note.trunc_path, PathBuf::from("b.md"));
+    }
+
+    #[test]
+    fn test_insert_link_idempotent() {
+        let once = insert_link("Some thoughts", "plan");
+        assert_eq!(once, "Some thoughts\n[[plan]]\n");
+        assert_eq!(insert_link(&once, "plan"), once);
+        assert_eq!(
+            insert_link("see [[plan|the plan]]\n", "plan"),
+            "see [[plan|the plan]]\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_link_in_section() {
+        let added = insert_link_in_section("# Notes\nbody\n", "plan", "Related");
+        assert_eq!(added, "# Notes\nbody\n\n## Related\n- [[plan]]\n");
+
+        let existing = "## Related\n- [[a]]\n\n## Later\ntext\n";
+        assert_eq!(
+            insert_link_in_section(existing, "b", "Related"),
+            "## Related\n- [[a]]\n- [[b]]\n\n## Later\ntext\n"
+        );
+        assert_eq!(insert_link_in_section(&added, "plan", "Related"), added);
+    }
+
+    #[test]
+    fn test_find_links() {
+        let links = find_links("see [[plan]] and [[ideas|my ideas]], not [[]] or [[open");
+        assert_eq!(links, vec!["plan", "ideas"]);
+    }
+
+    #[test]
+    fn test_build_dot_graph() {
+        let root = temp_root("build_dot_graph");
+        let notes = vec![
+            write_note(&root, "a.md", "links to [[b]]\n"),
+            write_note(&root, "work/b.md", "back to [[a]]\n"),
+            write_note(&root, "lonely.md", "no links here\n"),
+        ];
+        let dot = build_dot_graph(&notes);
+
+        assert!(dot.starts_with("digraph notes {\n"));
+        assert!(dot.contains("    \"a\";\n"));
+        assert!(dot.contains("    \"lonely\";\n"));
+        assert!(dot.contains("    \"a\" -> \"b\";\n"));
+        assert!(dot.contains("    \"b\" -> \"a\";\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |a: &[&str]| a.iter().map(|s| String::from(*s)).collect::<Vec<_>>();
+
+        assert_eq!(parse_args(&args(&[])).unwrap().command, Command::Menu);
+        assert_eq!(
+            parse_args(&args(&["graph", "out.dot"])).unwrap().command,
+            Command::Graph {
+                output: Some(PathBuf::from("out.dot"))
+            }
+        );
+        assert!(parse_args(&args(&["frobnicate"])).is_err());
+        assert!(parse_args(&args(&["graph", "a", "b"])).is_err());
+
+        let cli = parse_args(&args(&["--sort", "size_desc"])).unwrap();
+        assert_eq!(cli.sort, Some(Sort::SizeDesc));
+        assert!(parse_args(&args(&["--sort", "sideways"])).is_err());
+        assert!(parse_args(&args(&["--sort"])).is_err());
+        assert!(parse_args(&args(&["--frobnicate"])).is_err());
+
+        assert_eq!(
+            parse_args(&args(&["new", "--template", "daily"]))
+                .unwrap()
+                .command,
+            Command::New {
+                template: Some(String::from("daily")),
+                name: None,
+                fence: None,
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["new"])).unwrap().command,
+            Command::New {
+                template: None,
+                name: None,
+                fence: None,
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["new", "--name", "foo"]))
+                .unwrap()
+                .command,
+            Command::New {
+                template: None,
+                name: Some(String::from("foo")),
+                fence: None,
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["new", "--fence", "rust", "--name", "foo"]))
+                .unwrap()
+                .command,
+            Command::New {
+                template: None,
+                name: Some(String::from("foo")),
+                fence: Some(Some(String::from("rust"))),
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["new", "--fence", "--name", "foo"]))
+                .unwrap()
+                .command,
+            Command::New {
+                template: None,
+                name: Some(String::from("foo")),
+                fence: Some(None),
+            }
+        );
+        assert!(parse_args(&args(&["new", "--template"])).is_err());
+        assert!(parse_args(&args(&["graph", "--template", "x"])).is_err());
+    }
+
+    #[test]
+    fn test_is_empty_note() {
+        let root = temp_root("is_empty_note");
+        let empty = write_note(&root, "empty.md", "");
+        let written = write_note(&root, "written.md", "hello\n");
+
+        assert!(is_empty_note(&empty.full_path));
+        assert!(!is_empty_note(&written.full_path));
+        assert!(!is_empty_note(&root.join("missing.md")));
+    }
+
+    #[test]
+    fn test_empty_notes() {
+        let root = temp_root("empty_notes");
+        let notes = vec![
+            write_note(&root, "new_note_1.md", ""),
+            write_note(&root, "written.md", "hello\n"),
+        ];
+        let empty = empty_notes(&notes);
+
+        assert_eq!(empty.len(), 1);
+        assert_eq!(empty[0].trunc_path, PathBuf::from("new_note_1.md"));
+    }
+
+    #[test]
+    fn test_expired_trash() {
+        let now = UNIX_EPOCH + Duration::from_secs(100 * 86_400);
+        let entries = vec![
+            (
+                PathBuf::from("old.md"),
+                now - Duration::from_secs(31 * 86_400),
+            ),
+            (
+                PathBuf::from("recent.md"),
+                now - Duration::from_secs(29 * 86_400),
+            ),
+            (PathBuf::from("future.md"), now + Duration::from_secs(60)),
+        ];
+
+        assert_eq!(
+            expired_trash(&entries, now, 30),
+            vec![PathBuf::from("old.md")]
+        );
+        assert!(expired_trash(&entries, now, 60).is_empty());
+        assert!(expired_trash(&entries, now, u64::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_trash_note_hides_it_from_scans() {
+        let root = temp_root("trash_note_hides_it_from_scans");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let first = write_note(&root, "work/plan.md", "one");
+        let second = write_note(&root, "other.md", "two");
+        trash_note(&config, &first).unwrap();
+        let again = write_note(&root, "work/plan.md", "three");
+        let dest = trash_note(&config, &again).unwrap();
+
+        assert_eq!(dest, root.join(".trash/work/plan_1.md"));
+        assert_eq!(trash_entries(&config).len(), 2);
+        let notes = create_note_objects(&config);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].full_path, second.full_path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_trashing_a_read_only_note_sets_its_trash_time() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = temp_root("trashing_a_read_only_note_sets_its_trash_time");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let note = write_note(&root, "reference.md", "keep");
+        let long_ago = UNIX_EPOCH + Duration::from_secs(86_400);
+        File::open(&note.full_path)
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+        std::fs::set_permissions(&note.full_path, std::fs::Permissions::from_mode(0o444)).unwrap();
+        let dest = trash_note(&config, &note).unwrap();
+
+        let modified = dest.metadata().unwrap().modified().unwrap();
+        assert!(modified > long_ago);
+        assert!(expired_trash(&trash_entries(&config), SystemTime::now(), 30).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_trashing_a_symlink_leaves_its_target_alone() {
+        let root = temp_root("trashing_a_symlink_leaves_its_target_alone");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let target = write_note(&root, "shared.md", "shared");
+        let long_ago = UNIX_EPOCH + Duration::from_secs(86_400);
+        File::options()
+            .write(true)
+            .open(&target.full_path)
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+        std::os::unix::fs::symlink(&target.full_path, root.join("link.md")).unwrap();
+        let link = Note {
+            full_path: root.join("link.md"),
+            trunc_path: PathBuf::from("link.md"),
+            root: root.clone(),
+        };
+        let dest = trash_note(&config, &link).unwrap();
+
+        assert!(dest.symlink_metadata().unwrap().file_type().is_symlink());
+        let modified = target.full_path.metadata().unwrap().modified().unwrap();
+        assert_eq!(modified, long_ago);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"drafts/*", b"drafts/a.md"));
+        assert!(!glob_match(b"drafts/*", b"drafts"));
+        assert!(!glob_match(b"*.md", b"work/a.md"));
+        assert!(glob_match(b"**/*.md", b"a.md"));
+        assert!(glob_match(b"**/*.md", b"work/clients/a.md"));
+        assert!(glob_match(b"note_?.md", b"note_1.md"));
+        assert!(!glob_match(b"note_?.md", b"note_10.md"));
+    }
+
+    #[test]
+    fn test_load_ignore_excludes_drafts() {
+        let root = temp_root("load_ignore_excludes_drafts");
+        std::fs::write(
+            root.join(".clifeignore"),
+            "# unfinished work\ndrafts/*\n\n*.tmp\n",
+        )
+        .unwrap();
+        write_note(&root, "drafts/idea.md", "");
+        write_note(&root, "drafts/deeper/idea.md", "");
+        write_note(&root, "scratch.tmp", "");
+        write_note(&root, "keep.md", "");
+        write_note(&root, "work/drafts.md", "");
+
+        let ignore = load_ignore(&root);
+        assert_eq!(ignore.patterns, vec!["drafts/*", "*.tmp"]);
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let mut found: Vec<PathBuf> = create_note_objects(&config)
+            .into_iter()
+            .map(|note| note.trunc_path)
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![PathBuf::from("keep.md"), PathBuf::from("work/drafts.md")]
+        );
+    }
+
+    #[test]
+    fn test_attach_file() {
+        let root = temp_root("attach_file");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let note = write_note(&root, "work/plan.md", "The plan");
+        let outside = temp_root("attach_file_outside");
+        std::fs::write(outside.join("diagram.png"), "png bytes").unwrap();
+        std::fs::write(outside.join("spec sheet.pdf"), "pdf bytes").unwrap();
+
+        let dest = attach_file(&config, &note, &outside.join("diagram.png")).unwrap();
+        assert_eq!(dest, root.join("work/plan.assets/diagram.png"));
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "png bytes");
+        attach_file(&config, &note, &outside.join("spec sheet.pdf")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&note.full_path).unwrap(),
+            "The plan\n![diagram.png](plan.assets/diagram.png)\n[spec sheet.pdf](<plan.assets/spec sheet.pdf>)\n"
+        );
+
+        assert!(attach_file(&config, &note, &outside.join("missing.pdf")).is_err());
+        assert!(attach_file(&config, &note, &outside.join("diagram.png")).is_err());
+        assert_eq!(create_note_objects(&config).len(), 1);
+    }
+
+    #[test]
+    fn test_export_project() {
+        let root = temp_root("export_project");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        write_note(&root, "work/b.md", "---\ntitle: B\n---\nSecond body\n");
+        write_note(&root, "work/a.md", "First body\n");
+        write_note(&root, "home/c.md", "Not exported\n");
+        write_note(&root, ".trash/work/old.md", "Trashed\n");
+        let notes = create_note_objects(&config);
+        let dest = root.join("export.md");
+
+        assert_eq!(export_project(&config, &notes, "work", &dest).unwrap(), 2);
+        assert_eq!(
+            std::fs::read_to_string(&dest).unwrap(),
+            "## work/a.md\n\nFirst body\n\n---\n\n## work/b.md\n\nSecond body\n"
+        );
+        assert!(export_project(&config, &notes, "missing", &dest).is_err());
+    }
+
+    #[test]
+    fn test_sort_notes() {
+        let root = temp_root("sort_notes");
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let fixtures = [("b.md", "bb", 3), ("a.md", "a", 1), ("c.md", "ccc", 2)];
+        let mut notes: Vec<Note> = fixtures
+            .iter()
+            .map(|(name, contents, age)| {
+                let note = write_note(&root, name, contents);
+                File::options()
+                    .write(true)
+                    .open(&note.full_path)
+                    .unwrap()
+                    .set_modified(base - Duration::from_secs(*age * 60))
+                    .unwrap();
+                note
+            })
+            .collect();
+        let order = |notes: &Vec<Note>| {
+            notes
+                .iter()
+                .map(|n| n.trunc_path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        sort_notes(&mut notes, Sort::NameAsc);
+        assert_eq!(order(&notes), vec!["a.md", "b.md", "c.md"]);
+        sort_notes(&mut notes, Sort::NameDesc);
+        assert_eq!(order(&notes), vec!["c.md", "b.md", "a.md"]);
+        sort_notes(&mut notes, Sort::MtimeDesc);
+        assert_eq!(order(&notes), vec!["a.md", "c.md", "b.md"]);
+        sort_notes(&mut notes, Sort::MtimeAsc);
+        assert_eq!(order(&notes), vec!["b.md", "c.md", "a.md"]);
+        sort_notes(&mut notes, Sort::SizeDesc);
+        assert_eq!(order(&notes), vec!["c.md", "b.md", "a.md"]);
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("2", 5), Ok(vec![1]));
+        assert_eq!(parse_selection("1-3,5", 5), Ok(vec![0, 1, 2, 4]));
+        assert_eq!(parse_selection(" 3 , 1-2, 2 ", 5), Ok(vec![2, 0, 1]));
+        assert!(parse_selection("0", 5).is_err());
+        assert!(parse_selection("4-6", 5).is_err());
+        assert!(parse_selection("3-1", 5).is_err());
+        assert!(parse_selection("one", 5).is_err());
+        assert!(parse_selection("", 5).is_err());
+    }
+
+    #[test]
+    fn test_build_editor_command() {
+        let a = PathBuf::from("/vault/a.md");
+        let b = PathBuf::from("/vault/work/b.md");
+        let command = build_editor_command("nvim", &[&a, &b]);
+
+        assert_eq!(command.get_program(), "nvim");
+        let args: Vec<&std::ffi::OsStr> = command.get_args().collect();
+        assert_eq!(args, vec![a.as_os_str(), b.as_os_str()]);
+    }
+
+    #[test]
+    fn test_create_note_from_template() {
+        let root = temp_root("create_note_from_template");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        write_note(&root, "templates/daily.md", "# {{title}}\n\n## Todo\n");
+
+        let note_path = create_note_from_template(&config, Some("daily"), None, 1).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&note_path).unwrap(),
+            "# new_note_1\n\n## Todo\n"
+        );
+
+        let blank = create_note_from_template(&config, None, None, 1).unwrap();
+        assert!(is_empty_note(&blank));
+
+        let result = create_note_from_template(&config, Some("weekly"), None, 1);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("no template named 'weekly'"));
+        assert_eq!(create_note_objects(&config).len(), 2);
+    }
+
+    #[test]
+    fn test_render_template() {
+        // 2024-06-01 15:30:42 UTC
+        let now = UNIX_EPOCH + Duration::from_secs(1_717_255_842);
+        assert_eq!(
+            render_template("{{title}} on {{date}} at {{time}}", "Standup", now),
+            "Standup on 2024-06-01 at 15:30"
+        );
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let root = temp_root("group_duplicates");
+        let notes = vec![
+            write_note(&root, "a.md", "same words\n"),
+            write_note(&root, "unique.md", "different words\n"),
+            write_note(&root, "work/b.md", "same words\n"),
+        ];
+        let groups = group_duplicates(&notes);
+
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<&Path> = groups[0].iter().map(|n| n.trunc_path.as_path()).collect();
+        assert_eq!(paths, vec![Path::new("a.md"), Path::new("work/b.md")]);
+    }
+
+    #[test]
+    fn test_plan_bulk_rename() {
+        let root = temp_root("plan_bulk_rename");
+        let notes = vec![
+            write_note(&root, "new_note_1.md", ""),
+            write_note(&root, "work/new_note_2.md", ""),
+            write_note(&root, "new_note_3.md", ""),
+            write_note(&root, "3.md", ""),
+            write_note(&root, "plan.md", ""),
+        ];
+        let plan = plan_bulk_rename(&notes, "new_note_", "");
+
+        assert_eq!(
+            plan,
+            vec![
+                (root.join("new_note_1.md"), root.join("1.md")),
+                (root.join("work/new_note_2.md"), root.join("work/2.md")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_bulk_rename_collisions_between_renames() {
+        let root = temp_root("plan_bulk_rename_collisions_between_renames");
+        let notes = vec![
+            write_note(&root, "a_draft.md", ""),
+            write_note(&root, "a_final.md", ""),
+            write_note(&root, "b_draft.md", ""),
+        ];
+        let plan = plan_bulk_rename(&notes, "_draft", "_final");
+        assert_eq!(
+            plan,
+            vec![(root.join("b_draft.md"), root.join("b_final.md"))]
+        );
+
+        let both = plan_bulk_rename(&notes, "_draft", "");
+        assert_eq!(both.len(), 2);
+        assert!(plan_bulk_rename(&notes, "", "x").is_empty());
+        assert!(plan_bulk_rename(&notes, "a_", "../").is_empty());
+        assert!(plan_bulk_rename(&notes, "a_", "sub/").is_empty());
+        assert!(plan_bulk_rename(&notes, "a_", ".").is_empty());
+    }
+
+    #[test]
+    fn test_bulk_rename_rejects_path_separators() {
+        let root = temp_root("bulk_rename_rejects_path_separators");
+        let config = Config {
+            root_dir: root.clone(),
+            force_overwrite: true,
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "a_draft.md", ""),
+            write_note(&root, "b_draft.md", ""),
+        ];
+        bulk_rename(&config, &notes, &mut io::Cursor::new("a_\n../\ny\n"));
+        bulk_rename(&config, &notes, &mut io::Cursor::new("b_\n.\ny\n"));
+
+        assert!(root.join("a_draft.md").is_file());
+        assert!(root.join("b_draft.md").is_file());
+        assert_eq!(read_dir(&root).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_extract_headings() {
+        let contents = "---\n# not a heading\n---\n# Plan\nintro\n## Steps ##\n```sh\n# a comment\n```\n#hashtag\n### Done\n";
+        assert_eq!(
+            extract_headings(contents),
+            vec![
+                (1, 4, String::from("Plan")),
+                (2, 6, String::from("Steps")),
+                (3, 11, String::from("Done")),
+            ]
+        );
+        assert_eq!(
+            extract_headings("~~~\n# inside\n```\n# still inside\n~~~\n#######\n"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_create_scratch_note() {
+        let root = temp_root("create_scratch_note");
+        let config = Config {
+            root_dir: root.clone(),
+            scratch_dir: PathBuf::from("inbox/scratch"),
+            ..Default::default()
+        };
+        let note_path = create_scratch_note(&config).unwrap();
+
+        assert!(root.join("inbox/scratch").is_dir());
+        assert_eq!(
+            note_path.parent(),
+            Some(root.join("inbox/scratch").as_path())
+        );
+        let name = note_path.file_name().unwrap().to_string_lossy();
+        assert_eq!(name.len(), "202406011530.md".len());
+        assert!(name.ends_with(".md"));
+        assert!(name[..12].chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn test_build_note_name_truncates_long_names() {
+        let long = "a".repeat(300);
+        let name = build_note_name(&long, 200);
+        assert_eq!(name.len(), 200);
+        assert!(name.ends_with(".md"));
+
+        assert_eq!(build_note_name("short", 200), "short.md");
+        // never splits a multi-byte character
+        let name = build_note_name(&"é".repeat(150), 200);
+        assert!(name.len() <= 200);
+        assert!(name.ends_with(".md"));
+    }
+
+    #[test]
+    fn test_long_names_keep_their_suffix() {
+        let base = "a".repeat(17);
+        let existing = vec![format!("{}.md", base)];
+        assert_eq!(
+            resolve_collision(&base, CollisionStrategy::Increment, &existing, 20).unwrap(),
+            format!("{}_2", "a".repeat(15))
+        );
+        let stamped =
+            resolve_collision(&base, CollisionStrategy::Timestamp, &existing, 20).unwrap();
+        assert_eq!(stamped.len(), 17);
+        assert!(stamped.starts_with("aa_"));
+
+        assert_eq!(fit_file_name("plan", "md", 20), "plan.md");
+        assert_eq!(
+            fit_file_name(&"b".repeat(30), "txt", 20),
+            format!("{}.txt", "b".repeat(16))
+        );
+
+        let root = temp_root("long_names_keep_their_suffix");
+        let config = Config {
+            root_dir: root.clone(),
+            max_name_len: 20,
+            ..Default::default()
+        };
+        let note = write_note(&root, "plan.md", "");
+        let renamed = rename_note(&config, &note, &format!("{}.txt", "c".repeat(30))).unwrap();
+        assert_eq!(renamed, root.join(format!("{}.txt", "c".repeat(16))));
+
+        for len in ["0", "3", "19"] {
+            let mut config = Config::default();
+            assert!(config
+                .apply_file(&format!("max_name_len = {}\n", len))
+                .is_err());
+        }
+        let mut config = Config::default();
+        config.apply_file("max_name_len = 20\n").unwrap();
+        assert_eq!(config.max_name_len, 20);
+    }
+
+    #[test]
+    fn test_next_note_suffix() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(next_note_suffix(&[]), 1);
+        assert_eq!(next_note_suffix(&names(&["new_note_1", "new_note_3"])), 2);
+        assert_eq!(
+            next_note_suffix(&names(&["new_note_2.md", "new_note_1.md", "ideas.md"])),
+            3
+        );
+        assert_eq!(
+            next_note_suffix(&names(&["new_note_x.md", "new_note_2.md"])),
+            1
+        );
+    }
+
+    #[test]
+    fn test_next_note_suffix_ignores_other_names() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            next_note_suffix(&names(&[
+                "new_note_1.md",
+                "new_note_plan.md",
+                "new_note_2_draft.md",
+                "new_note_+2.md",
+                "new_note_02.md",
+                "new_note_2.md.bak",
+                "new_note_.md",
+                "my_new_note_2.md",
+            ])),
+            2
+        );
+        assert_eq!(
+            next_note_suffix(&names(&["new_note_1.md", "new_note_2.md", "new_note_4.md"])),
+            3
+        );
+        assert_eq!(
+            next_note_suffix(&names(&[
+                "new_note_99999999999999999999999.md",
+                "new_note_1.md"
+            ])),
+            2
+        );
+    }
+
+    #[test]
+    fn test_describe_config_labels_sources() {
+        let mut config = Config::default();
+        assert!(describe_config(&config).contains("editor = nvim (default)"));
+
+        config.apply_file("editor = \"vim\"\n").unwrap();
+        assert!(describe_config(&config).contains("editor = vim (file)"));
+
+        config.apply_env(|name| (name == "CLIFE_EDITOR").then(|| String::from("hx")));
+        let description = describe_config(&config);
+        assert!(description.contains("editor = hx (env CLIFE_EDITOR)"));
+        assert!(description.contains("use_trash = true (default)"));
+    }
+
+    #[test]
+    fn test_create_note_from_input() {
+        let root = temp_root("create_note_from_input");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let mut input = io::Cursor::new("idea from a pipe\n");
+        let note_path =
+            create_note_from_input(&config, None, Some("foo"), 1, None, &mut input).unwrap();
+
+        assert_eq!(note_path, root.join("foo.md"));
+        assert_eq!(
+            std::fs::read_to_string(&note_path).unwrap(),
+            "idea from a pipe\n"
+        );
+        // a named note never replaces an existing one
+        let mut input = io::Cursor::new("again");
+        assert!(create_note_from_input(&config, None, Some("foo"), 1, None, &mut input).is_err());
+    }
+
+    #[test]
+    fn test_fence_content() {
+        assert_eq!(
+            fence_content("fn main() {}\n", Some("rust")),
+            "```rust\nfn main() {}\n```\n"
+        );
+        assert_eq!(fence_content("no newline", None), "```\nno newline\n```\n");
+        assert_eq!(
+            fence_content("```\ninner\n```\n", Some("md")),
+            "````md\n```\ninner\n```\n````\n"
+        );
+
+        let root = temp_root("fence_content");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let mut input = io::Cursor::new("$ ls\n");
+        let fenced = create_note_from_input(
+            &config,
+            None,
+            Some("fenced"),
+            1,
+            Some(Some("sh")),
+            &mut input,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(fenced).unwrap(),
+            "```sh\n$ ls\n```\n"
+        );
+        let mut input = io::Cursor::new("$ ls\n");
+        let plain =
+            create_note_from_input(&config, None, Some("plain"), 1, None, &mut input).unwrap();
+        assert_eq!(std::fs::read_to_string(plain).unwrap(), "$ ls\n");
+    }
+
+    #[test]
+    fn test_read_note_body() {
+        let root = temp_root("read_note_body");
+        let note = write_note(&root, "ideas.md", "---\ntitle: Ideas\n---\n# Ideas\n");
+
+        assert_eq!(
+            read_note_body(&note, false).unwrap(),
+            "---\ntitle: Ideas\n---\n# Ideas\n"
+        );
+        assert_eq!(read_note_body(&note, true).unwrap(), "# Ideas\n");
+
+        let raw = write_note(&root, "raw.md", "");
+        std::fs::write(&raw.full_path, [0xff, 0xfe, b'x']).unwrap();
+        assert!(read_note_body(&raw, true).is_err());
+        let mut out = Vec::new();
+        cat_note(&raw, true, &mut out).unwrap();
+        assert_eq!(out, vec![0xff, 0xfe, b'x']);
+    }
+
+    #[test]
+    fn test_resolve_template_precedence() {
+        let root = temp_root("resolve_template");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        create_dir_all(root.join("work")).unwrap();
+        create_dir_all(root.join("home")).unwrap();
+        assert_eq!(resolve_template(&config, &root.join("work")), None);
+
+        create_dir_all(templates_dir(&config)).unwrap();
+        std::fs::write(templates_dir(&config).join("default.md"), "global\n").unwrap();
+        std::fs::write(templates_dir(&config).join("daily.md"), "daily\n").unwrap();
+        std::fs::write(root.join("work/.template.md"), "# {{title}} (work)\n").unwrap();
+
+        assert_eq!(
+            resolve_template(&config, &root.join("work")),
+            Some(root.join("work/.template.md"))
+        );
+        assert_eq!(
+            resolve_template(&config, &root.join("home")),
+            Some(templates_dir(&config).join("default.md"))
+        );
+
+        let mut input = io::Cursor::new("work\n");
+        let note_path = prompt_and_create_note(&config, &mut input, 1).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(note_path).unwrap(),
+            "# new_note_1 (work)\n"
+        );
+
+        // a named template beats the global default, a root-level one beats both
+        let named = create_note_from_template(&config, Some("daily"), None, 1).unwrap();
+        assert_eq!(std::fs::read_to_string(named).unwrap(), "daily\n");
+        std::fs::write(root.join(".template.md"), "root\n").unwrap();
+        let overridden = create_note_from_template(&config, Some("daily"), None, 1).unwrap();
+        assert_eq!(std::fs::read_to_string(overridden).unwrap(), "root\n");
+    }
+
+    #[test]
+    fn test_search_with_context() {
+        let contents = "todo: one\ntwo\nthree\nfour\nfive\nsix\ntodo: seven";
+
+        let hits = search_with_context(contents, "todo", 2, true);
+        assert_eq!(
+            hits,
+            vec![
+                Hit {
+                    first: 1,
+                    lines: vec!["todo: one".into(), "two".into(), "three".into()],
+                    matches: vec![1],
+                },
+                Hit {
+                    first: 5,
+                    lines: vec!["five".into(), "six".into(), "todo: seven".into()],
+                    matches: vec![7],
+                },
+            ]
+        );
+
+        // wider context makes the two groups touch, so they merge
+        let hits = search_with_context(contents, "todo", 3, true);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].first, 1);
+        assert_eq!(hits[0].lines.len(), 7);
+        assert_eq!(hits[0].matches, vec![1, 7]);
+
+        assert!(search_with_context(contents, "nothing", 1, true).is_empty());
+    }
+
+    #[test]
+    fn test_process_inbox() {
+        let root = temp_root("process_inbox");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "inbox/a.md", "first idea\n"),
+            write_note(&root, "inbox/b.md", "second idea\n"),
+            write_note(&root, "kept.md", "not in the inbox\n"),
+        ];
+
+        // an invalid project is asked for again before the move goes through
+        let mut input = io::Cursor::new("a\nx\nm\n../up\nm\nwork\n");
+        process_inbox(&config, &notes, &mut input);
+
+        assert!(root.join(".archive/inbox/a.md").is_file());
+        assert!(root.join("work/b.md").is_file());
+        assert!(!root.join("inbox/a.md").exists());
+        assert!(!root.join("inbox/b.md").exists());
+        assert!(root.join("kept.md").is_file());
+    }
+
+    #[test]
+    fn test_diff_scans() {
+        let root = temp_root("diff_scans");
+        let notes = vec![
+            write_note(&root, "same.md", "unchanged\n"),
+            write_note(&root, "edited.md", "edited elsewhere\n"),
+            write_note(&root, "fresh.md", "brand new\n"),
+        ];
+        let cached = vec![
+            CachedNote {
+                trunc_path: PathBuf::from("same.md"),
+                modified: modified_secs(&notes[0].full_path),
+            },
+            CachedNote {
+                trunc_path: PathBuf::from("edited.md"),
+                modified: modified_secs(&notes[1].full_path) - 60,
+            },
+            CachedNote {
+                trunc_path: PathBuf::from("gone.md"),
+                modified: 0,
+            },
+        ];
+
+        assert_eq!(
+            diff_scans(&cached, &notes),
+            ScanDiff {
+                added: vec![PathBuf::from("fresh.md")],
+                removed: vec![PathBuf::from("gone.md")],
+                changed: vec![PathBuf::from("edited.md")],
+            }
+        );
+
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        assert_eq!(load_cache(&config), None);
+        save_cache(&config, &notes).unwrap();
+        assert_eq!(
+            diff_scans(&load_cache(&config).unwrap(), &notes),
+            ScanDiff::default()
+        );
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let home = std::env::var("HOME").unwrap_or_default();
+
+        assert_eq!(expand_path("~/bin/x"), format!("{}/bin/x", home));
+        assert_eq!(expand_path("$HOME/bin/x"), format!("{}/bin/x", home));
+        assert_eq!(expand_path("${HOME}/bin/x"), format!("{}/bin/x", home));
+        assert_eq!(expand_path("/usr/bin/~x"), "/usr/bin/~x");
+        assert_eq!(expand_path("cost$"), "cost$");
+
+        let command = build_editor_command("~/bin/myeditor --wait", &[]);
+        assert_eq!(
+            command.get_program(),
+            format!("{}/bin/myeditor", home).as_str()
+        );
+        assert_eq!(command.get_args().collect::<Vec<_>>(), vec!["--wait"]);
+    }
+
+    #[test]
+    fn test_last_created_note() {
+        let root = temp_root("last_created_note");
+        assert!(last_created_note(&[]).is_none());
+
+        let notes = vec![
+            write_note(&root, "new_note_1.md", ""),
+            write_note(&root, "new_note_2.md", ""),
+            write_note(&root, "edited_later.md", "not a new note\n"),
+        ];
+        let set_modified = |note: &Note, secs: u64| {
+            File::options()
+                .write(true)
+                .open(&note.full_path)
+                .unwrap()
+                .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        };
+        set_modified(&notes[0], 2_000);
+        set_modified(&notes[1], 1_000);
+        set_modified(&notes[2], 3_000);
+
+        let last = last_created_note(&notes).unwrap();
+        assert_eq!(last.trunc_path, PathBuf::from("new_note_1.md"));
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        let text = "# A heading that is far too long to fit but stays put\n\
+                    short line\n\
+                    The quick brown fox jumps over the lazy dog and keeps on running.\n\
+                    - a list item that also runs on for quite a while\n\
+                    ```\n\
+                    code that is long enough to wrap but lives in a fence\n\
+                    ```\n";
+
+        assert_eq!(
+            wrap_text(text, 20),
+            "# A heading that is far too long to fit but stays put\n\
+             short line\n\
+             The quick brown fox\n\
+             jumps over the lazy\n\
+             dog and keeps on\n\
+             running.\n\
+             - a list item that\n  \
+               also runs on for\n  \
+               quite a while\n\
+             ```\n\
+             code that is long enough to wrap but lives in a fence\n\
+             ```\n"
+        );
+        assert_eq!(
+            wrap_text("---\ntitle: a long frontmatter title\n---\nok\n", 10),
+            "---\ntitle: a long frontmatter title\n---\nok\n"
+        );
+    }
+
+    #[test]
+    fn test_most_recent_note() {
+        let root = temp_root("most_recent_note");
+        assert!(most_recent_note(&[]).is_none());
+
+        let notes = vec![
+            write_note(&root, "a.md", "a\n"),
+            write_note(&root, "work/b.md", "b\n"),
+            write_note(&root, "c.md", "c\n"),
+        ];
+        for (note, secs) in notes.iter().zip([1_000, 3_000, 2_000]) {
+            File::options()
+                .write(true)
+                .open(&note.full_path)
+                .unwrap()
+                .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        }
+
+        let last = most_recent_note(&notes).unwrap();
+        assert_eq!(last.trunc_path, PathBuf::from("work/b.md"));
+    }
+
+    #[test]
+    fn test_merge_notes_custom_format() {
+        let root = temp_root("merge_notes_custom_format");
+        let mut config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        config
+            .apply_file("merge_separator = \"\\n\\n* * *\\n\\n\"\nmerge_header_template = \"# {{title}} ({{path}})\"\n")
+            .unwrap();
+        let a = write_note(&root, "a.md", "---\ntitle: Alpha\n---\nFirst\n");
+        let b = write_note(&root, "work/b.md", "Second\n");
+
+        assert_eq!(
+            merge_notes(&config, &[&a, &b]).unwrap(),
+            "# Alpha (a.md)\n\nFirst\n\n* * *\n\n# b (work/b.md)\n\nSecond\n"
+        );
+    }
+
+    #[test]
+    fn test_update_title() {
+        assert_eq!(
+            update_title(
+                "---\ntags: [a]\ntitle: Old name\nproject: work\n---\n# Body\ntitle: not frontmatter\n",
+                "new-name"
+            ),
+            "---\ntags: [a]\ntitle: new-name\nproject: work\n---\n# Body\ntitle: not frontmatter\n"
+        );
+        assert_eq!(
+            update_title("no frontmatter\ntitle: x\n", "y"),
+            "no frontmatter\ntitle: x\n"
+        );
+        assert_eq!(
+            update_title("---\ntags: [a]\n---\nbody\n", "y"),
+            "---\ntags: [a]\n---\nbody\n"
+        );
+    }
+
+    #[test]
+    fn test_action_from_str() {
+        let names = [
+            ("create", Action::CreateNote),
+            ("delete", Action::Delete),
+            ("project", Action::CreateProject),
+            ("edit", Action::Edit),
+            ("file", Action::File),
+            ("link", Action::Link),
+            ("prune-empty", Action::PruneEmpty),
+            ("attach", Action::Attach),
+            ("list", Action::List),
+            ("duplicates", Action::Duplicates),
+            ("bulk-rename", Action::BulkRename),
+            ("toc", Action::Toc),
+            ("scratch", Action::Scratch),
+            ("cat", Action::Cat),
+            ("search", Action::Search),
+            ("process", Action::Process),
+            ("undo-create", Action::UndoCreate),
+            ("last", Action::Last),
+        ];
+        for (name, action) in names {
+            assert_eq!(name.parse::<Action>(), Ok(action));
+        }
+        assert_eq!(
+            "frobnicate".parse::<Action>(),
+            Err(String::from("unknown action 'frobnicate'"))
+        );
+
+        let mut config = Config::default();
+        config.apply_file("default_action = \"create\"\n").unwrap();
+        assert_eq!(config.default_action, Some(Action::CreateNote));
+        assert!(config.apply_file("default_action = \"nope\"\n").is_err());
+        assert!(parse_args(&[String::from("--menu")]).unwrap().menu);
+    }
+
+    #[test]
+    fn test_action_from_str_short_names() {
+        let short_names = [
+            ("c", Action::CreateNote),
+            ("d", Action::Delete),
+            ("p", Action::CreateProject),
+            ("e", Action::Edit),
+            ("f", Action::File),
+            ("l", Action::Link),
+            ("a", Action::Attach),
+            ("n", Action::List),
+            ("dup", Action::Duplicates),
+            ("s", Action::Scratch),
+        ];
+        for (name, action) in short_names {
+            assert_eq!(name.parse::<Action>(), Ok(action));
+        }
+        assert!("x".parse::<Action>().is_err());
+        assert!("".parse::<Action>().is_err());
+
+        // the name in brackets in every menu label picks that action
+        for (label, action) in ACTION_MENU {
+            let start = label.find('(').unwrap() + 1;
+            let end = label.find(')').unwrap();
+            assert_eq!(label[start..end].parse::<Action>(), Ok(action), "{}", label);
+        }
+    }
+
+    #[test]
+    fn test_diagnose() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = temp_root("diagnose");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        write_note(&root, "fine.md", "Links to [[work/plan]] and [[missing]]\n");
+        write_note(&root, "work/plan.md", "The plan\n");
+        write_note(&root, "work/plan.assets/diagram.png", "png");
+        write_note(&root, "gone.assets/photo.png", "png");
+        write_note(&root, "blank.md", "");
+        write_note(&root, "notes.txt", "wrong extension\n");
+        std::fs::write(
+            root.join(std::ffi::OsStr::from_bytes(b"bad\xffname.md")),
+            "odd name\n",
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(root.join("nowhere.md"), root.join("dangling.md")).unwrap();
+        let notes = create_note_objects(&config);
+
+        let report = diagnose(&config, &notes);
+        let found = |check: &str| {
+            report
+                .iter()
+                .find(|(name, _)| *name == check)
+                .map(|(_, found)| found.clone())
+                .unwrap()
+        };
+        assert_eq!(found("unreadable files"), vec!["dangling.md"]);
+        assert_eq!(found("non-UTF-8 names"), vec!["bad\u{FFFD}name.md"]);
+        assert_eq!(found("unexpected extensions"), vec!["notes.txt"]);
+        assert_eq!(found("broken wikilinks"), vec!["fine.md -> [[missing]]"]);
+        assert_eq!(found("empty notes"), vec!["blank.md"]);
+        assert_eq!(found("orphaned assets directories"), vec!["gone.assets"]);
+        assert!(!print_diagnosis(&report));
+    }
+
+    #[test]
+    fn test_move_note_overwrite() {
+        let root = temp_root("move_note_overwrite");
+        let mut config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let note = write_note(&root, "plan.md", "new plan\n");
+        write_note(&root, "work/plan.md", "old plan\n");
+
+        // without an input to ask on, an existing file is never replaced
+        assert!(move_note(&config, &note, Path::new("work"), None).is_err());
+        assert_eq!(
+            std::fs::read_to_string(root.join("work/plan.md")).unwrap(),
+            "old plan\n"
+        );
+
+        // saying no and picking another name keeps both
+        let mut input = io::Cursor::new("n\nplan_2.md\n");
+        let dest = move_note(&config, &note, Path::new("work"), Some(&mut input)).unwrap();
+        assert_eq!(dest, Some(root.join("work/plan_2.md")));
+
+        let note = write_note(&root, "plan.md", "newer plan\n");
+        config.force_overwrite = true;
+        let dest = move_note(&config, &note, Path::new("work"), None).unwrap();
+        assert_eq!(dest, Some(root.join("work/plan.md")));
+        assert_eq!(
+            std::fs::read_to_string(root.join("work/plan.md")).unwrap(),
+            "newer plan\n"
+        );
+        assert!(!root.join("plan.md").exists());
+    }
+
+    #[test]
+    fn test_rename_tag_in_frontmatter() {
+        assert_eq!(
+            rename_tag_in_frontmatter(
+                "---\ntitle: Plan\ntags: [todo, work]\n---\ntags: [todo]\n",
+                "todo",
+                "to-do"
+            ),
+            "---\ntitle: Plan\ntags: [to-do, work]\n---\ntags: [todo]\n"
+        );
+        // the new tag is already there, so the two merge
+        assert_eq!(
+            rename_tag_in_frontmatter(
+                "---\ntags:\n  - to-do\n  - todo\nproject: work\n---\nbody\n",
+                "#todo",
+                "to-do"
+            ),
+            "---\ntags: [to-do]\nproject: work\n---\nbody\n"
+        );
+        let untouched = "---\ntags: work\n---\nbody\n";
+        assert_eq!(
+            rename_tag_in_frontmatter(untouched, "todo", "to-do"),
+            untouched
+        );
+        assert_eq!(
+            rename_tag_in_frontmatter("no frontmatter\n", "todo", "x"),
+            "no frontmatter\n"
+        );
+    }
+
+    #[test]
+    fn test_tag_counts() {
+        let root = temp_root("tag_counts");
+        let notes = vec![
+            write_note(&root, "a.md", "---\ntags: [todo, work]\n---\n"),
+            write_note(&root, "b.md", "---\ntags:\n  - \"#todo\"\n  - todo\n---\n"),
+            write_note(&root, "c.md", "---\ntags: work, home\n---\n"),
+            write_note(&root, "d.md", "no tags here\n"),
+        ];
+        let counts = tag_counts(&notes);
+
+        assert_eq!(counts.get("todo"), Some(&2));
+        assert_eq!(counts.get("work"), Some(&2));
+        assert_eq!(counts.get("home"), Some(&1));
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[test]
+    fn test_cleanup_skipped_for_non_blocking_editor() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let root = temp_root("cleanup_non_blocking");
+        let note = write_note(&root, "new_note_1.md", "");
+        let failed: io::Result<ExitStatus> = Ok(ExitStatus::from_raw(1 << 8));
+
+        let config = Config {
+            root_dir: root.clone(),
+            editor_blocks: false,
+            ..Default::default()
+        };
+        let mut input = io::Cursor::new("y\n");
+        cleanup_after_editor_abort(&config, &note.full_path, &failed, &mut input);
+        assert!(note.full_path.exists());
+        assert_eq!(input.position(), 0);
+
+        let config = Config {
+            editor_blocks: true,
+            ..config
+        };
+        cleanup_after_editor_abort(&config, &note.full_path, &failed, &mut input);
+        assert!(!note.full_path.exists());
+    }
+
+    #[test]
+    fn test_is_probably_text() {
+        assert!(is_probably_text(
+            b"# Notes\nplain text, even with unicode: \xc3\xa9\n"
+        ));
+        assert!(is_probably_text(b""));
+        assert!(!is_probably_text(b"PNG\x00\x01\x02 header"));
+        assert!(!is_probably_text(b"\xff\xfe not utf-8"));
+
+        // a character cut off by the sniffing limit is still text
+        let mut long = "a".repeat(TEXT_SNIFF_LEN - 1).into_bytes();
+        long.extend_from_slice("é and more".as_bytes());
+        assert!(is_probably_text(&long));
+    }
+
+    #[test]
+    fn test_write_banner() {
+        let mut out = Vec::new();
+        write_banner(&Config::default(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Welcome to clife!\n");
+
+        let config = Config {
+            banner: Some(String::from("Team notes, powered by clife")),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_banner(&config, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Team notes, powered by clife\n"
+        );
+    }
+
+    #[test]
+    fn test_build_editor_command_keeps_paths_whole() {
+        let root = temp_root("editor_paths_whole");
+        let note = write_note(&root, "my notes/a $HOME & (draft).md", "");
+        let command = build_editor_command("nvim -p", &[&note.full_path]);
+
+        assert_eq!(command.get_program(), "nvim");
+        let args: Vec<&std::ffi::OsStr> = command.get_args().collect();
+        assert_eq!(args, vec!["-p".as_ref(), note.full_path.as_os_str()]);
+
+        let command = build_editor_command("'/opt/My Editor/edit' --wait", &[]);
+        assert_eq!(command.get_program(), "/opt/My Editor/edit");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), vec!["--wait"]);
+    }
+
+    #[test]
+    fn test_editor_for_uses_extension_mapping() {
+        let mut config = Config::default();
+        config
+            .apply_file("editor = \"nvim\"\neditor_by_extension.drawio = \"drawio\"\n")
+            .unwrap();
+        assert_eq!(editor_for(&config, Path::new("diagram.drawio")), ["drawio"]);
+        assert_eq!(editor_for(&config, Path::new("note.md")), ["nvim"]);
+        assert_eq!(editor_for(&config, Path::new("README")), ["nvim"]);
+    }
+
+    #[test]
+    fn test_reindex_matches_fresh_scan() {
+        let root = temp_root("reindex");
+        write_note(&root, "a.md", "a");
+        write_note(&root, "work/b.md", "b");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        std::fs::write(cache_path(&config), "0\tgone.md\n0\ta.md\n").unwrap();
+
+        assert_eq!(reindex(&config).unwrap(), 2);
+        let notes = create_note_objects(&config);
+        let fresh: Vec<CachedNote> = notes
+            .iter()
+            .map(|note| CachedNote {
+                trunc_path: note.trunc_path.clone(),
+                modified: modified_secs(&note.full_path),
+            })
+            .collect();
+        assert_eq!(load_cache(&config).unwrap(), fresh);
+    }
+
+    #[test]
+    fn test_count_by_project_groups_at_depth() {
+        let root = temp_root("count_by_project");
+        let notes = vec![
+            write_note(&root, "top.md", ""),
+            write_note(&root, "work/clients/acme/plan.md", ""),
+            write_note(&root, "work/clients/globex.md", ""),
+            write_note(&root, "work/reports/q1.md", ""),
+            write_note(&root, "work/todo.md", ""),
+        ];
+
+        let by_top = count_by_project(&notes, 1);
+        assert_eq!(by_top.get(Path::new("work")), Some(&4));
+        assert_eq!(by_top.get(Path::new("")), Some(&1));
+
+        let by_two = count_by_project(&notes, 2);
+        assert_eq!(by_two.get(Path::new("work/clients")), Some(&2));
+        assert_eq!(by_two.get(Path::new("work/reports")), Some(&1));
+        assert_eq!(by_two.get(Path::new("work")), Some(&1));
+        assert_eq!(by_two.len(), 4);
+
+        assert_eq!(
+            parse_command(&[
+                "projects".to_string(),
+                "--depth".to_string(),
+                "2".to_string()
+            ])
+            .unwrap(),
+            Command::Projects { depth: 2 }
+        );
+    }
+
+    #[test]
+    fn test_delete_question_includes_size() {
+        let root = temp_root("delete_question");
+        let note = write_note(&root, "work/plan.md", &"line\n".repeat(860));
+        assert_eq!(
+            delete_question(&note),
+            "Are you sure you want to delete work/plan.md (4.2 KiB, 860 lines)?"
+        );
+
+        remove_file(&note.full_path).unwrap();
+        assert_eq!(
+            delete_question(&note),
+            "Are you sure you want to delete work/plan.md?"
+        );
+
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+        assert_eq!(count_lines(b"a\nb"), 2);
+        assert_eq!(count_lines(b""), 0);
+    }
+
+    #[test]
+    fn test_log_delete_keeps_content() {
+        let root = temp_root("log_delete");
+        let config = Config {
+            root_dir: root.clone(),
+            use_trash: false,
+            log_deleted_content: true,
+            log_deleted_content_max: 12,
+            ..Default::default()
+        };
+        let note = write_note(&root, "work/plan.md", "keep this safe\nplease\n");
+        remove_note(&config, &note);
+
+        assert!(!note.full_path.exists());
+        let log = std::fs::read_to_string(audit_log_path(&config)).unwrap();
+        assert!(log.contains(" delete work/plan.md\n"));
+        assert!(log.contains("```\nkeep this sa\n[cut off, 22 bytes in total]\n```\n"));
+
+        // A code block in the note gets a longer fence around it
+        let config = Config {
+            log_deleted_content_max: 100,
+            ..config
+        };
+        let note = write_note(&root, "code.md", "```sh\nls\n```\n");
+        remove_note(&config, &note);
+        let log = std::fs::read_to_string(audit_log_path(&config)).unwrap();
+        assert!(log.contains(" delete code.md\n````\n```sh\nls\n```\n````\n"));
+    }
+
+    #[test]
+    fn test_interrupt_cleanup_removes_empty_note() {
+        let root = temp_root("interrupt_cleanup");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let kept = write_note(&root, "kept.md", "words");
+        let empty = write_note(&root, "new_note_1.md", "");
+        let temp = temp_path_for(&root.join("work/plan.md"));
+        write_note(&root, "work/.plan.md.1.tmp", "another run's");
+        write_note(
+            &root,
+            temp.strip_prefix(&root).unwrap().to_str().unwrap(),
+            "half",
+        );
+
+        interrupt_cleanup(&config, Some(&empty.full_path));
+        assert!(!empty.full_path.exists());
+        assert!(!temp.exists());
+        assert!(root.join("work/.plan.md.1.tmp").exists());
+        assert_eq!(
+            load_cache(&config).unwrap(),
+            vec![CachedNote {
+                trunc_path: PathBuf::from("kept.md"),
+                modified: modified_secs(&kept.full_path),
+            }]
+        );
+
+        interrupt_cleanup(&config, Some(&kept.full_path));
+        assert!(kept.full_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sigint_ignored_while_editor_runs() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { pipe(fds.as_mut_ptr()) }, 0);
+        SIGINT_PIPE.store(fds[1], std::sync::atomic::Ordering::Relaxed);
+
+        FOREGROUND_CHILDREN.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        on_sigint(2);
+        FOREGROUND_CHILDREN.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+        on_sigint(2);
+
+        // Only the second Ctrl-C may wake the cleanup thread
+        let mut wake = unsafe { <File as std::os::unix::io::FromRawFd>::from_raw_fd(fds[0]) };
+        let mut byte = [0];
+        io::Read::read_exact(&mut wake, &mut byte).unwrap();
+        unsafe { write(fds[1], &7, 1) };
+        io::Read::read_exact(&mut wake, &mut byte).unwrap();
+        assert_eq!(byte, [7]);
+
+        let status = wait_in_foreground(&mut std::process::Command::new("true")).unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_merge_configs_local_overrides_global() {
+        let mut global = Config::default();
+        global
+            .apply_file("editor = \"nvim\"\nuse_trash = false\nmax_name_len = 80\n")
+            .unwrap();
+        let local = PartialConfig::parse(
+            "editor = \"hx\"\nmax_name_len = 40\n",
+            ConfigSource::LocalFile,
+        )
+        .unwrap();
+
+        let merged = merge_configs(global, local).unwrap();
+        assert_eq!(merged.editor, ["hx"]);
+        assert_eq!(merged.max_name_len, 40);
+        assert!(!merged.use_trash);
+        assert_eq!(merged.source("editor"), ConfigSource::LocalFile);
+        assert_eq!(merged.source("use_trash"), ConfigSource::File);
+    }
+
+    #[test]
+    fn test_build_tree_nests_notes_by_directory() {
+        let root = temp_root("build_tree");
+        let notes = vec![
+            write_note(&root, "top.md", ""),
+            write_note(&root, "work/clients/acme.md", ""),
+            write_note(&root, "work/plan.md", ""),
+            write_note(&root, "work/clients/globex.md", ""),
+        ];
+        let leaf = |name: &str| TreeNode {
+            name: String::from(name),
+            children: Vec::new(),
+        };
+
+        let tree = build_tree(&notes);
+        assert_eq!(
+            tree,
+            TreeNode {
+                name: String::from("."),
+                children: vec![
+                    leaf("top.md"),
+                    TreeNode {
+                        name: String::from("work"),
+                        children: vec![
+                            TreeNode {
+                                name: String::from("clients"),
+                                children: vec![leaf("acme.md"), leaf("globex.md")],
+                            },
+                            leaf("plan.md"),
+                        ],
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            render_tree(&tree),
+            ".\n├── top.md\n└── work\n    ├── clients\n    │   ├── acme.md\n    │   └── globex.md\n    └── plan.md\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        assert_eq!(
+            normalize_newlines("one\r\ntwo\rthree\nfour\r\n\r\n"),
+            "one\ntwo\nthree\nfour\n\n"
+        );
+        assert_eq!(normalize_newlines("plain\n"), "plain\n");
+
+        let root = temp_root("normalize_newlines");
+        let config = Config {
+            root_dir: root.clone(),
+            normalize_newlines: true,
+            ..Default::default()
+        };
+        let note = write_note(&root, "windows.md", "a\r\nb\r\n");
+        let binary = root.join("image.png");
+        std::fs::write(&binary, b"\x89PNG\r\n\0\r\n").unwrap();
+        normalize_note_newlines(&config, &note.full_path).unwrap();
+        normalize_note_newlines(&config, &binary).unwrap();
+        assert_eq!(std::fs::read_to_string(&note.full_path).unwrap(), "a\nb\n");
+        assert_eq!(std::fs::read(&binary).unwrap(), b"\x89PNG\r\n\0\r\n");
+    }
+
+    #[test]
+    fn test_lint_markdown_reports_each_rule() {
+        let issue = |line, rule| LintIssue { line, rule };
+        assert_eq!(lint_markdown("# Title\n\nclean\n"), vec![]);
+        assert_eq!(
+            lint_markdown("# Title  \n\n\n\n\tindented\nend"),
+            vec![
+                issue(1, LintRule::TrailingWhitespace),
+                issue(3, LintRule::MultipleBlankLines),
+                issue(5, LintRule::TabIndentation),
+                issue(6, LintRule::MissingFinalNewline),
+            ]
+        );
+        assert_eq!(
+            fix_markdown("# Title  \n\n\n\n\tindented\nend"),
+            "# Title\n\n\tindented\nend\n"
+        );
+        assert_eq!(lint_markdown(&fix_markdown("a \n\n \nb")), vec![]);
+    }
+
+    #[test]
+    fn test_ensure_root_folder_follows_create_root() {
+        let parent = temp_root("ensure_root_folder");
+        let config = |name: &str, create_root| Config {
+            root_dir: parent.join(name),
+            create_root,
+            ..Default::default()
+        };
+
+        let always = config("always", CreateRoot::Always);
+        assert!(ensure_root_folder(&always, &mut "".as_bytes()).is_ok());
+        assert!(always.root_dir.is_dir());
+
+        let never = config("never", CreateRoot::Never);
+        assert!(ensure_root_folder(&never, &mut "y\n".as_bytes()).is_err());
+        assert!(!never.root_dir.exists());
+
+        let declined = config("declined", CreateRoot::Prompt);
+        assert!(ensure_root_folder(&declined, &mut "maybe\nn\n".as_bytes()).is_err());
+        assert!(!declined.root_dir.exists());
+        assert!(ensure_root_folder(&declined, &mut "".as_bytes()).is_err());
+
+        let accepted = config("accepted", CreateRoot::Prompt);
+        assert!(ensure_root_folder(&accepted, &mut "y\n".as_bytes()).is_ok());
+        assert!(accepted.root_dir.is_dir());
+    }
+
+    #[test]
+    fn test_notes_matching_tags_all_or_any() {
+        let root = temp_root("notes_matching_tags");
+        let notes = vec![
+            write_note(&root, "both.md", "---\ntags: [project, active]\n---\n"),
+            write_note(&root, "project.md", "---\ntags: [project]\n---\n"),
+            write_note(&root, "active.md", "---\ntags: [active]\n---\n"),
+            write_note(&root, "none.md", "no tags"),
+        ];
+        let tags = vec![String::from("project"), String::from("active")];
+        let names = |found: Vec<&Note>| -> Vec<String> {
+            found
+                .iter()
+                .map(|note| note.trunc_path.display().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            names(notes_matching_tags(&notes, &tags, true)),
+            vec!["both.md"]
+        );
+        assert_eq!(
+            names(notes_matching_tags(&notes, &tags, false)),
+            vec!["both.md", "project.md", "active.md"]
+        );
+        assert_eq!(
+            parse_command(&[
+                "tagged".to_string(),
+                "--any".to_string(),
+                "#project".to_string()
+            ])
+            .unwrap(),
+            Command::Tagged {
+                tags: vec![String::from("project")],
+                all: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_inbox_count_counts_direct_notes() {
+        let root = temp_root("inbox_count");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        assert_eq!(inbox_count(&config, &[]), 0);
+
+        let notes = vec![
+            write_note(&root, "inbox/one.md", ""),
+            write_note(&root, "inbox/two.md", ""),
+            write_note(&root, "inbox/later/three.md", ""),
+            write_note(&root, "work/four.md", ""),
+        ];
+        assert_eq!(inbox_count(&config, &notes), 2);
+    }
+
+    #[test]
+    fn test_default_root_dir_follows_xdg() {
+        let home = temp_root("default_root_dir");
+        let xdg = |name: &str| (name == "XDG_DATA_HOME").then(|| String::from("/data"));
+        let unset = |_: &str| None;
+        let relative = |_: &str| Some(String::from("data"));
+
+        assert_eq!(default_root_dir(xdg, &home), PathBuf::from("/data/clife"));
+        assert_eq!(
+            default_root_dir(unset, &home),
+            home.join(".local/share/clife")
+        );
+        assert_eq!(
+            default_root_dir(relative, &home),
+            home.join(".local/share/clife")
+        );
+
+        create_dir_all(home.join(".clife")).unwrap();
+        assert_eq!(default_root_dir(xdg, &home), home.join(".clife"));
+        assert_eq!(default_root_dir(unset, &home), home.join(".clife"));
+    }
+
+    #[test]
+    fn test_pick_random_is_deterministic() {
+        let root = temp_root("pick_random");
+        assert!(pick_random(&[], 7).is_none());
+
+        let notes = vec![
+            write_note(&root, "a.md", ""),
+            write_note(&root, "b.md", ""),
+            write_note(&root, "c.md", ""),
+        ];
+        let picked = pick_random(&notes, 42).unwrap();
+        assert_eq!(
+            picked.trunc_path,
+            pick_random(&notes, 42).unwrap().trunc_path
+        );
+
+        let mut seen: Vec<&PathBuf> = (0..50)
+            .map(|seed| &pick_random(&notes, seed).unwrap().trunc_path)
+            .collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 3);
+    }
+
+    #[test]
+    fn test_build_index_groups_by_project() {
+        let root = temp_root("build_index");
+        write_note(&root, INDEX_FILE, "old index");
+        write_note(&root, "top.md", "");
+        write_note(&root, "work/plan.md", "---\ntitle: The Plan\n---\n");
+        write_note(&root, "work/my notes.md", "");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let mut notes = create_note_objects(&config);
+        sort_notes(&mut notes, Sort::NameAsc);
+
+        assert_eq!(
+            build_index(&notes),
+            "# Index\n\n- [top](top.md)\n\n## work\n\n- [my notes](work/my%20notes.md)\n- [The Plan](work/plan.md)\n"
+        );
+    }
+
+    #[test]
+    fn test_search_case_sensitivity() {
+        let contents = "The API is down\nCall the api again\nnothing here";
+
+        let insensitive = search_with_context(contents, "API", 0, false);
+        assert_eq!(insensitive.len(), 1);
+        assert_eq!(insensitive[0].matches, vec![1, 2]);
+        let sensitive = search_with_context(contents, "API", 0, true);
+        assert_eq!(sensitive.len(), 1);
+        assert_eq!(sensitive[0].matches, vec![1]);
+
+        assert_eq!(
+            find_matches("Api api APİ", "api", false),
+            vec![(0, 3), (4, 7)]
+        );
+        assert_eq!(find_matches("Api api", "api", true), vec![(4, 7)]);
+        assert_eq!(
+            parse_command(&["search".to_string(), "-s".to_string(), "API".to_string()]).unwrap(),
+            Command::Search {
+                query: String::from("API"),
+                context: 0,
+                case_sensitive: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_parent_dir_within_root() {
+        let root = temp_root("parent_dir_within_root");
+        let nested = write_note(&root, "work/clients/acme.md", "");
+        let shallow = write_note(&root, "work/plan.md", "");
+        let top = write_note(&root, "top.md", "");
+
+        assert_eq!(
+            parent_dir_within_root(&nested, &root),
+            Some(PathBuf::from("work"))
+        );
+        assert_eq!(
+            parent_dir_within_root(&shallow, &root),
+            Some(PathBuf::new())
+        );
+        assert_eq!(parent_dir_within_root(&top, &root), None);
+
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        write_note(&root, "work/acme.md", "already here");
+        promote_note(&config, &[nested], &mut "1\nn\nacme_old.md\n".as_bytes());
+        assert!(root.join("work/acme_old.md").is_file());
+        assert!(!root.join("work/clients/acme.md").exists());
+
+        assert_eq!(child_dirs(&config, &root), vec!["work"]);
+        demote_note(&config, &[top], &mut "1\n1\n".as_bytes());
+        assert!(root.join("work/top.md").is_file());
+    }
+
+    #[test]
+    fn test_prompt_for_note_filters_and_resets() {
+        let root = temp_root("prompt_for_note_filter");
+        let notes = vec![
+            write_note(&root, "alpha.md", ""),
+            write_note(&root, "work/meetings.md", ""),
+            write_note(&root, "work/plan.md", ""),
+        ];
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+
+        let picked = prompt_for_note(&config, &notes, "edit", &mut "mtg\n1\n".as_bytes());
+        assert_eq!(picked.trunc_path, PathBuf::from("work/meetings.md"));
+
+        // nothing matches, so the full list is back and the next answer picks
+        // from it rather than being thrown away
+        let picked = prompt_for_note(&config, &notes, "edit", &mut "zzz\n3\n".as_bytes());
+        assert_eq!(picked.trunc_path, PathBuf::from("work/plan.md"));
+
+        assert!(fuzzy_match("WP", "work/plan.md"));
+        assert!(!fuzzy_match("pw", "work/plan.md"));
+    }
+
+    #[test]
+    fn test_title_from_stem() {
+        assert_eq!(title_from_stem("my_great_note"), "My Great Note");
+        assert_eq!(title_from_stem("new_note_3"), "New Note 3");
+        assert_eq!(title_from_stem("__élan__vital"), "Élan Vital");
+        assert_eq!(title_from_stem("Already"), "Already");
+
+        assert_eq!(
+            with_h1_title("---\ntags: [a]\n---\nbody\n", "my_note"),
+            "---\ntags: [a]\n---\n# My Note\n\nbody\n"
+        );
+
+        let root = temp_root("seed_h1_title");
+        let config = Config {
+            root_dir: root.clone(),
+            seed_h1_title: true,
+            ..Default::default()
+        };
+        let note_path = create_new_note(&config, &root, 1).unwrap();
+        // write_text drops the blank line after the title
+        assert_eq!(with_h1_title("", "new_note_1"), "# New Note 1\n\n");
+        assert_eq!(
+            std::fs::read_to_string(note_path).unwrap(),
+            "# New Note 1\n"
+        );
+    }
+
+    #[test]
+    fn test_trash_without_confirmation() {
+        let root = temp_root("confirm_trash");
+        let config = Config {
+            root_dir: root.clone(),
+            confirm_trash: false,
+            ..Default::default()
+        };
+        let notes = vec![write_note(&root, "plan.md", "plan")];
+
+        // the "n" would cancel if the trash were confirmed
+        delete_chosen_note(&config, &notes, &mut "1\nn\n".as_bytes());
+        assert!(!root.join("plan.md").exists());
+        assert!(trash_dir(&config).join("plan.md").is_file());
+
+        let config = Config {
+            use_trash: false,
+            ..config
+        };
+        let notes = vec![write_note(&root, "keep.md", "keep")];
+        delete_chosen_note(&config, &notes, &mut "1\nn\n".as_bytes());
+        assert!(root.join("keep.md").is_file());
+    }
+
+    #[test]
+    fn test_nested_vaults_are_skipped() {
+        let root = temp_root("nested_vaults");
+        write_note(&root, "outer.md", "");
+        write_note(&root, "projects/inner/.clife.toml", "");
+        write_note(&root, "projects/inner/inner.md", "");
+        write_note(&root, "projects/legacy/.clife/settings", "");
+        write_note(&root, "projects/legacy/old.md", "");
+        write_note(&root, "projects/plan.md", "");
+        let mut config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let scanned = |config: &Config| -> Vec<PathBuf> {
+            let mut notes = create_note_objects(config);
+            sort_notes(&mut notes, Sort::NameAsc);
+            notes.into_iter().map(|note| note.trunc_path).collect()
+        };
+
+        assert_eq!(
+            scanned(&config),
+            vec![PathBuf::from("outer.md"), PathBuf::from("projects/plan.md")]
+        );
+
+        config.include_nested = true;
+        assert_eq!(scanned(&config).len(), 4);
+        assert!(
+            parse_args(&["--include-nested".to_string()])
+                .unwrap()
+                .include_nested
+        );
+    }
+
+    #[test]
+    fn test_list_projects() {
+        let root = temp_root("list_projects");
+        write_note(&root, "root_note.md", "");
+        write_note(&root, "work/plan.md", "");
+        write_note(&root, "work/clients/acme.md", "");
+        write_note(&root, "home/list.md", "");
+        write_note(&root, ".trash/gone.md", "");
+        write_note(&root, ".archive/old/done.md", "");
+        write_note(&root, "templates/default.md", "");
+
+        assert_eq!(
+            list_projects(&root).unwrap(),
+            vec![(String::from("home"), 1), (String::from("work"), 2)]
+        );
+    }
+
+    #[test]
+    fn test_last_action_round_trip() {
+        let root = temp_root("last_action");
+        let config = Config {
+            root_dir: root.clone(),
+            remember_last_action: true,
+            ..Default::default()
+        };
+        assert_eq!(load_last_action(&config), None);
+
+        save_last_action(&config, Action::BulkRename).unwrap();
+        assert_eq!(load_last_action(&config), Some(Action::BulkRename));
+        save_last_action(&config, Action::Edit).unwrap();
+        assert_eq!(load_last_action(&config), Some(Action::Edit));
+
+        let picked = prompt_for_action(&config, &mut "\n".as_bytes(), Some(Action::Edit));
+        assert_eq!(picked, Action::Edit);
+        let picked = prompt_for_action(&config, &mut "c\n".as_bytes(), Some(Action::Edit));
+        assert_eq!(picked, Action::CreateNote);
+    }
+
+    #[test]
+    fn test_add_tag_to_frontmatter() {
+        assert_eq!(
+            add_tag_to_frontmatter("just text\n", "#inbox"),
+            "---\ntags: [inbox]\n---\njust text\n"
+        );
+        assert_eq!(
+            add_tag_to_frontmatter("---\ntitle: Plan\n---\nbody\n", "inbox"),
+            "---\ntitle: Plan\ntags: [inbox]\n---\nbody\n"
+        );
+        assert_eq!(
+            add_tag_to_frontmatter("---\ntags:\n  - work\ntitle: x\n---\n", "inbox"),
+            "---\ntags: [work, inbox]\ntitle: x\n---\n"
+        );
+        let tagged = "---\ntags: [inbox, work]\n---\nbody\n";
+        assert_eq!(add_tag_to_frontmatter(tagged, "inbox"), tagged);
+
+        let root = temp_root("tag_notes");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "a.md", "a\n"),
+            write_note(&root, "b.md", "---\ntags: [new]\n---\nb\n"),
+            write_note(&root, "c.md", "c\n"),
+        ];
+        tag_notes(&config, &notes, &mut "1-2\nnew\ny\n".as_bytes());
+        assert_eq!(
+            read_tags(&std::fs::read_to_string(root.join("a.md")).unwrap()),
+            vec!["new"]
+        );
+        assert_eq!(
+            read_tags(&std::fs::read_to_string(root.join("c.md")).unwrap()),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_ensure_trailing_newline() {
+        assert_eq!(ensure_trailing_newline("text"), "text\n");
+        assert_eq!(ensure_trailing_newline("text\n"), "text\n");
+        assert_eq!(ensure_trailing_newline("text\n\n\n"), "text\n");
+        assert_eq!(ensure_trailing_newline("a\n\nb\r\n\r\n"), "a\n\nb\n");
+        assert_eq!(ensure_trailing_newline(""), "");
+        assert_eq!(ensure_trailing_newline("\n\n"), "");
+    }
+
+    #[test]
+    fn test_parse_due() {
+        let date = |year, month, day| Date { year, month, day };
+        assert_eq!(parse_due("due: 2024-05-01"), Some(date(2024, 5, 1)));
+        assert_eq!(
+            parse_due("title: x\ndue: \"2024-05-01T09:30:00+02:00\""),
+            Some(date(2024, 5, 1))
+        );
+        assert_eq!(parse_due("due: 2024-02-29"), Some(date(2024, 2, 29)));
+        assert_eq!(parse_due("due: 2023-02-29"), None);
+        assert_eq!(parse_due("due: 2024-13-01"), None);
+        assert_eq!(parse_due("due: tomorrow"), None);
+        assert_eq!(parse_due("tags: [a]"), None);
+    }
+
+    #[test]
+    fn test_classify_due() {
+        let today = Date {
+            year: 2024,
+            month: 5,
+            day: 10,
+        };
+        let on = |month, day| Date {
+            year: 2024,
+            month,
+            day,
+        };
+        assert_eq!(classify_due(on(5, 9), today), DueBucket::Overdue);
+        assert_eq!(classify_due(on(4, 30), today), DueBucket::Overdue);
+        assert_eq!(classify_due(on(5, 10), today), DueBucket::Today);
+        assert_eq!(classify_due(on(5, 11), today), DueBucket::Upcoming);
+        assert_eq!(classify_due(on(6, 1), today), DueBucket::Upcoming);
+
+        let root = temp_root("agenda");
+        let notes = vec![
+            write_note(&root, "later.md", "---\ndue: 2024-06-01\n---\n"),
+            write_note(&root, "now.md", "---\ndue: 2024-05-10\n---\n"),
+            write_note(&root, "late.md", "---\ndue: 2024-05-01\n---\n"),
+            write_note(&root, "undated.md", "no frontmatter\n"),
+        ];
+        assert_eq!(
+            render_agenda(&agenda(&notes), today),
+            "Overdue:\n  2024-05-01  late.md\n\nToday:\n  2024-05-10  now.md\n\nUpcoming:\n  2024-06-01  later.md\n"
+        );
+    }
+
+    #[test]
+    fn test_scaffold_config() {
+        let root = temp_root("scaffold_config");
+        let path = root.join("clife").join("config.toml");
+
+        assert!(scaffold_config(&path).unwrap());
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, DEFAULT_CONFIG);
+        assert!(parse_config_file(&contents).unwrap().is_empty());
+
+        std::fs::write(&path, "editor = \"nano\"\n").unwrap();
+        assert!(!scaffold_config(&path).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "editor = \"nano\"\n"
+        );
+        assert_eq!(
+            parse_command(&["config".to_string(), "edit".to_string()]).unwrap(),
+            Command::EditConfig
+        );
+    }
+
+    #[test]
+    fn test_link_resolution_with_aliases() {
+        let root = temp_root("link_aliases");
+        let notes = vec![
+            write_note(
+                &root,
+                "work/plan.md",
+                "---\naliases: [roadmap, \"The Plan\"]\n---\nbody\n",
+            ),
+            write_note(
+                &root,
+                "ideas.md",
+                "---\naliases:\n  - brainstorm\n---\nSee [[roadmap]], [[The Plan]], [[brainstorm]] and [[gone]]\n",
+            ),
+        ];
+        assert_eq!(note_names(&notes[0]), vec!["plan", "roadmap", "The Plan"]);
+        assert_eq!(note_names(&notes[1]), vec!["ideas", "brainstorm"]);
+
+        let names: Vec<Vec<String>> = notes.iter().map(note_names).collect();
+        let resolved =
+            |target: &str| resolve_link(&notes, &names, target).map(|note| note.trunc_path.clone());
+        assert_eq!(resolved("roadmap"), Some(PathBuf::from("work/plan.md")));
+        assert_eq!(resolved("work/plan"), Some(PathBuf::from("work/plan.md")));
+        assert_eq!(resolved("brainstorm"), Some(PathBuf::from("ideas.md")));
+        assert_eq!(resolved("gone"), None);
+
+        let broken: Vec<String> = broken_links(&notes)
+            .into_iter()
+            .map(|(_, target)| target)
+            .collect();
+        assert_eq!(broken, vec!["gone"]);
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        let root = temp_root("atomic_write");
+        let path = root.join("note.md");
+        std::fs::write(&path, "old contents\n").unwrap();
+
+        atomic_write(&path, b"new contents\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new contents\n");
+        let entries: Vec<_> = read_dir(&root).unwrap().flatten().collect();
+        assert_eq!(entries.len(), 1);
+
+        // A temp file that can't be written leaves the old contents in place
+        create_dir_all(temp_path_for(&path)).unwrap();
+        assert!(atomic_write(&path, b"lost\n").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new contents\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_write_keeps_links_and_modes() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = temp_root("atomic_write_keeps_links_and_modes");
+        let target = root.join("shared.md");
+        std::fs::write(&target, "old\n").unwrap();
+        let link = root.join("link.md");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        atomic_write(&link, b"through the link\n").unwrap();
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(&target).unwrap(),
+            "through the link\n"
+        );
+
+        let private = root.join("private.md");
+        std::fs::write(&private, "secret\n").unwrap();
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o600)).unwrap();
+        atomic_write(&private, b"still secret\n").unwrap();
+        let mode = private.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_locked_notes_are_not_deleted() {
+        let root = temp_root("locked_notes");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "reference.md", "---\nlocked: true\n---\nkeep me\n"),
+            write_note(&root, "draft.md", "---\nlocked: false\n---\n"),
+        ];
+        assert!(is_locked(&notes[0]));
+        assert!(!is_locked(&notes[1]));
+
+        delete_chosen_note(&config, &notes, &mut "1\ny\n".as_bytes());
+        assert!(root.join("reference.md").is_file());
+        assert!(move_note(&config, &notes[0], Path::new("archive"), None).is_err());
+        assert!(root.join("reference.md").is_file());
+
+        let forced = Config {
+            override_locks: true,
+            ..config
+        };
+        delete_chosen_note(&forced, &notes, &mut "1\ny\n".as_bytes());
+        assert!(!root.join("reference.md").exists());
+    }
+
+    #[test]
+    fn test_preview_pane() {
+        let root = temp_root("preview_pane");
+        let notes = [
+            write_note(
+                &root,
+                "plan.md",
+                "---\ntitle: Plan\n---\nfirst\nsecond\nthird\n",
+            ),
+            write_note(&root, "ref.md", "---\nlocked: true\n---\nfacts\n"),
+        ];
+        std::fs::write(root.join("blob.md"), [0u8, 159, 146, 150]).unwrap();
+        let blob = Note {
+            full_path: root.join("blob.md"),
+            trunc_path: PathBuf::from("blob.md"),
+            root: root.clone(),
+        };
+        assert_eq!(preview_note(&notes[0], 2), vec!["first", "second"]);
+        assert_eq!(preview_note(&notes[1], 5), vec!["(locked)", "facts"]);
+        assert_eq!(preview_note(&blob, 5), vec!["(binary)"]);
+
+        let items = vec![String::from("1. plan.md"), String::from("2. a.md")];
+        let preview = vec![
+            String::from("first"),
+            String::from("a rather long second line"),
+            String::from("third"),
+        ];
+        assert_eq!(
+            render_two_pane(&items, 1, &preview, 30),
+            "  1. plan.md │ first\n> 2. a.md    │ a rather long s\n             │ third\n"
+        );
+    }
+
+    #[test]
+    fn test_long_note_lists_need_a_filter() {
+        let root = temp_root("list_cap");
+        let config = Config {
+            root_dir: root.clone(),
+            list_cap: 2,
+            ..Default::default()
+        };
+        let notes: Vec<Note> = ["alpha", "beta", "gamma", "delta", "omega"]
+            .iter()
+            .map(|name| write_note(&root, &format!("{}.md", name), ""))
+            .collect();
+
+        assert_eq!(
+            over_list_cap(&config, notes.len()),
+            Some(String::from("too many notes (5); type a filter"))
+        );
+        assert_eq!(over_list_cap(&config, 2), None);
+
+        // Numbers from the withheld list are refused, then "ga" narrows it to
+        // gamma and omega, which fits under the cap
+        let answers = "?1\n3\nga\n2\n";
+        let note = prompt_for_note(&config, &notes, "edit", &mut answers.as_bytes());
+        assert_eq!(note.trunc_path, PathBuf::from("omega.md"));
+
+        let uncapped = Config {
+            list_cap: 0,
+            ..config
+        };
+        assert_eq!(over_list_cap(&uncapped, 5), None);
+    }
+
+    #[test]
+    fn test_batch_commands_run_in_sequence() {
+        let root = temp_root("batch_commands");
+        let config = Config {
+            root_dir: root.clone(),
+            use_trash: false,
+            ..Default::default()
+        };
+        write_note(&root, "a.md", "draft\n");
+        write_note(&root, "taken.md", "");
+
+        assert_eq!(
+            parse_command(&["move", "a.md", "work/"].map(String::from)).unwrap(),
+            Command::Move {
+                note: String::from("a.md"),
+                dir: String::from("work/"),
+            }
+        );
+        assert_eq!(
+            parse_command(&["delete", "--yes", "work/b.md"].map(String::from)).unwrap(),
+            Command::Delete {
+                note: String::from("work/b.md"),
+                yes: true,
+            }
+        );
+        assert!(parse_command(&["tag", "a.md"].map(String::from)).is_err());
+
+        let notes = create_note_objects(&config);
+        let note = find_note(&notes, "a").unwrap();
+        let dir = parse_project_path("work/").unwrap();
+        move_note(&config, note, &dir, None).unwrap();
+
+        let notes = create_note_objects(&config);
+        let note = find_note(&notes, "work/a.md").unwrap();
+        assert!(tag_note(&config, note, "imported").unwrap());
+        assert!(!tag_note(&config, note, "imported").unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&note.full_path).unwrap(),
+            "---\ntags: [imported]\n---\ndraft\n"
+        );
+        let renamed = rename_note(&config, note, "b").unwrap();
+        assert_eq!(renamed, root.join("work").join("b.md"));
+
+        let notes = create_note_objects(&config);
+        let note = find_note(&notes, "work/b.md").unwrap();
+        assert!(rename_note(&config, note, "../taken").is_err());
+        assert!(remove_note(&config, note));
+        assert!(!renamed.exists());
+
+        let other = write_note(&root, "other.md", "");
+        assert!(rename_note(&config, &other, "taken.md").is_err());
+        assert!(other.full_path.exists());
+    }
+
+    #[test]
+    fn test_notes_over_the_word_budget_are_flagged() {
+        let config = Config {
+            word_budget: Some(5),
+            ..Default::default()
+        };
+        let short = "---\ntags: [a, b, c, d, e, f]\n---\none two three four five\n";
+        let long = "one two three\nfour five six\n";
+        assert_eq!(count_words(short), 5);
+        assert_eq!(over_word_budget(&config, short), None);
+        assert_eq!(over_word_budget(&config, long), Some((6, 5)));
+        assert_eq!(over_word_budget(&Config::default(), long), None);
+
+        let root = temp_root("word_budget");
+        let notes = vec![
+            write_note(&root, "short.md", short),
+            write_note(&root, "long.md", long),
+        ];
+        assert_eq!(lint_notes(&config, &notes, false), 1);
+    }
+
+    #[test]
+    fn test_set_project_field() {
+        assert_eq!(
+            set_project_field("body\n", "work"),
+            "---\nproject: work\n---\nbody\n"
+        );
+        assert_eq!(
+            set_project_field("---\ntitle: A\n---\nbody\n", "work"),
+            "---\ntitle: A\nproject: work\n---\nbody\n"
+        );
+        assert_eq!(
+            set_project_field("---\nproject: old\ntitle: A\n---\n", "work"),
+            "---\nproject: work\ntitle: A\n---\n"
+        );
+        assert_eq!(
+            set_project_field("---\nproject: old\ntitle: A\n---\n", ""),
+            "---\ntitle: A\n---\n"
+        );
+        assert_eq!(set_project_field("body\n", ""), "body\n");
+
+        let root = temp_root("sync_project");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let notes = vec![
+            write_note(&root, "work/meetings/standup.md", "notes\n"),
+            write_note(&root, "loose.md", "---\nproject: work\n---\n"),
+        ];
+        assert_eq!(sync_project_fields(&config, &notes), 2);
+        assert_eq!(
+            std::fs::read_to_string(&notes[0].full_path).unwrap(),
+            "---\nproject: work\n---\nnotes\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&notes[1].full_path).unwrap(),
+            "---\nproject: (root)\n---\n"
+        );
+        assert_eq!(sync_project_fields(&config, &notes), 0);
+    }
+
+    #[test]
+    fn test_find_empty_projects() {
+        let root = temp_root("empty_projects");
+        write_note(&root, "work/plan.md", "plan");
+        create_dir_all(root.join("unused").join("drafts")).unwrap();
+        create_dir_all(root.join("templates")).unwrap();
+        create_dir_all(root.join(".trash")).unwrap();
+        create_dir_all(root.join("inbox")).unwrap();
+        create_dir_all(root.join("scratch")).unwrap();
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        assert_eq!(find_empty_projects(&config), vec![root.join("unused")]);
+
+        offer_empty_project_cleanup(&config, &mut "y\n".as_bytes());
+        assert!(!root.join("unused").exists());
+        assert!(root.join("work").join("plan.md").is_file());
+        assert!(root.join("inbox").is_dir());
+        assert!(find_empty_projects(&config).is_empty());
+    }
+
+    #[test]
+    fn test_render_markdown_to_html() {
+        assert_eq!(render_markdown_to_html("# Title\n"), "<h1>Title</h1>\n");
+        assert_eq!(
+            render_markdown_to_html("### Deep *one*\n"),
+            "<h3>Deep <em>one</em></h3>\n"
+        );
+        assert_eq!(
+            render_markdown_to_html("Some *em*, _em_, **strong** and `co*de`\n"),
+            "<p>Some <em>em</em>, <em>em</em>, <strong>strong</strong> and <code>co*de</code></p>\n"
+        );
+        assert_eq!(
+            render_markdown_to_html("snake_case_name & <b>\n"),
+            "<p>snake_case_name &amp; &lt;b&gt;</p>\n"
+        );
+        assert_eq!(
+            render_markdown_to_html("See [[plan|the plan]] and [site](https://a.b?x=1&y=2)\n"),
+            "<p>See <span class=\"wikilink\">the plan</span> and <a href=\"https://a.b?x=1&amp;y=2\">site</a></p>\n"
+        );
+        assert_eq!(
+            render_markdown_to_html("[a](JavaScript:void) [b](./b.html#top) [c](#top)\n"),
+            "<p>a <a href=\"./b.html#top\">b</a> <a href=\"#top\">c</a></p>\n"
+        );
+        assert!(is_safe_href("mailto:me@example.com"));
+        assert!(!is_safe_href("data:text/html,hi"));
+        assert!(!is_safe_href("java\tscript:alert(1)"));
+        assert_eq!(
+            render_markdown_to_html("2 * 3 * 4\n\n## Done ##\n"),
+            "<p>2 * 3 * 4</p>\n<h2>Done</h2>\n"
+        );
+        assert_eq!(
+            render_markdown_to_html("- one\n  - nested\n"),
+            "<ul>\n<li>one\n<ul>\n<li>nested</li>\n</ul>\n</li>\n</ul>\n"
+        );
+        assert_eq!(
+            render_markdown_to_html("<script>alert(1)</script>\n\n[[plan]]\n"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;\n<p><span class=\"wikilink\">plan</span></p>\n"
+        );
+        assert_eq!(
+            render_markdown_to_html("- one\n- two\n\n1. first\n\n> quoted\n\n```\n# not a heading\n```\n"),
+            "<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n<ol>\n<li>first</li>\n</ol>\n<blockquote>\n<p>quoted</p>\n</blockquote>\n<pre><code># not a heading\n</code></pre>\n"
+        );
+
+        let root = temp_root("export_html");
+        let note = write_note(&root, "plan.md", "---\ntitle: The Plan\n---\n# Plan\n");
+        let dest = root.join("plan.html");
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        export_note_html(&config, &note, &dest).unwrap();
+        let page = std::fs::read_to_string(&dest).unwrap();
+        assert!(page.contains("<title>The Plan</title>"));
+        assert!(page.contains("<body>\n<h1>Plan</h1>\n</body>"));
+        assert!(!page.contains("title: The Plan"));
+
+        // Exporting over an existing file, like another note, needs --force
+        let other = write_note(&root, "other.md", "keep me\n");
+        assert!(export_note_html(&config, &note, &other.full_path).is_err());
+        assert_eq!(
+            std::fs::read_to_string(&other.full_path).unwrap(),
+            "keep me\n"
+        );
+    }
+
+    #[test]
+    fn test_unanswered_prompts_time_out() {
+        let never = |_: &mut String| -> io::Result<usize> {
+            loop {
+                std::thread::park();
+            }
+        };
+        let mut reader = TimedReader::new(never, Duration::from_millis(20));
+        let mut line = String::new();
+        let err = reader.read_line(&mut line).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(line.is_empty());
+
+        let mut answers = vec!["y\n", "2\n"].into_iter();
+        let mut reader = TimedReader::new(
+            move |line: &mut String| {
+                let answer = answers.next().unwrap_or_default();
+                line.push_str(answer);
+                Ok(answer.len())
+            },
+            Duration::from_secs(5),
+        );
+        assert_eq!(read_input(&mut reader), "y\n");
+        assert_eq!(read_input(&mut reader), "2\n");
+        assert_eq!(read_input(&mut reader), "");
+    }
+
+    #[test]
+    fn test_extra_roots_are_merged_read_only() {
+        let root = temp_root("extra_roots_primary");
+        let shared = temp_root("extra_roots_shared");
+        write_note(&root, "mine.md", "mine");
+        write_note(&shared, "team/handbook.md", "shared");
+        write_note(&shared, "faq.md", "shared");
+        let config = Config {
+            root_dir: root.clone(),
+            extra_roots: vec![shared.clone(), root.join("missing")],
+            use_trash: false,
+            ..Default::default()
+        };
+
+        let mut notes = create_note_objects(&config);
+        notes.sort_by(|a, b| a.trunc_path.cmp(&b.trunc_path));
+        assert_eq!(notes.len(), 3);
+        let handbook = notes
+            .iter()
+            .find(|note| note.trunc_path == Path::new("team/handbook.md"))
+            .unwrap();
+        assert_eq!(handbook.root, *shared);
+        assert!(is_read_only(&config, handbook));
+        assert!(!remove_note(&config, handbook));
+        assert!(move_note(&config, handbook, Path::new("archive"), None).is_err());
+        assert!(shared.join("team").join("handbook.md").is_file());
+
+        let mine = notes.iter().find(|note| note.root == *root).unwrap();
+        assert!(!is_read_only(&config, mine));
+        assert!(remove_note(&config, mine));
+    }
+
+    #[test]
+    fn test_extra_root_notes_are_never_written() {
+        let root = temp_root("extra_root_notes_are_never_written");
+        let shared = temp_root("extra_root_notes_are_never_written_shared");
+        let contents = "---\ntags: [team]\n---\n# Handbook  \n\n\n\nend";
+        write_note(&shared, "team/handbook.md", contents);
+        let config = Config {
+            root_dir: root.clone(),
+            extra_roots: vec![shared.clone()],
+            ..Default::default()
+        };
+        let notes = create_note_objects(&config);
+        let handbook = &notes[0];
+
+        assert!(tag_note(&config, handbook, "imported").is_err());
+        tag_notes(&config, &notes, &mut io::Cursor::new("1\nimported\ny\n"));
+        retag_notes(&config, &notes, &mut io::Cursor::new("team\nstaff\ny\n"));
+        assert!(lint_notes(&config, &notes, true) > 0);
+        assert_eq!(sync_project_fields(&config, &notes), 0);
+        assert_eq!(
+            std::fs::read_to_string(&handbook.full_path).unwrap(),
+            contents
+        );
+    }
+
+    #[test]
+    fn test_resolve_collision() {
+        let existing = vec![
+            String::from("plan.md"),
+            String::from("plan_2.md"),
+            String::from("ideas"),
+        ];
+        for strategy in [
+            CollisionStrategy::Increment,
+            CollisionStrategy::Timestamp,
+            CollisionStrategy::Error,
+        ] {
+            assert_eq!(
+                resolve_collision("fresh", strategy, &existing, 200).unwrap(),
+                "fresh"
+            );
+        }
+
+        assert_eq!(
+            resolve_collision("plan", CollisionStrategy::Increment, &existing, 200).unwrap(),
+            "plan_3"
+        );
+        assert_eq!(
+            resolve_collision("ideas", CollisionStrategy::Increment, &existing, 200).unwrap(),
+            "ideas_2"
+        );
+
+        let stamped =
+            resolve_collision("plan", CollisionStrategy::Timestamp, &existing, 200).unwrap();
+        let stamp = stamped.strip_prefix("plan_").unwrap();
+        assert_eq!(stamp.len(), 14);
+        assert!(stamp.chars().all(|c| c.is_ascii_digit()));
+
+        let err = resolve_collision("plan", CollisionStrategy::Error, &existing, 200).unwrap_err();
+        assert_eq!(err.to_string(), "name collision: plan already exists");
+
+        let root = temp_root("collision_strategy");
+        let config = Config {
+            root_dir: root.clone(),
+            collision_strategy: CollisionStrategy::Error,
+            ..Default::default()
+        };
+        write_note(&root, "new_note_1.md", "");
+        assert!(create_new_note(&config, &root, 1).is_err());
+    }
+
+    #[test]
+    fn test_focus_limits_notes_to_the_project() {
+        let root = temp_root("focus");
+        write_note(&root, "top.md", "");
+        write_note(&root, "work/plan.md", "");
+        write_note(&root, "work/acme/call.md", "");
+        write_note(&root, "home/list.md", "");
+        let mut config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+
+        let cli = parse_args(&["--focus".to_string(), "work".to_string()]).unwrap();
+        assert_eq!(cli.focus.as_deref(), Some("work"));
+        assert!(apply_focus(&mut config, "nowhere").is_err());
+        assert!(apply_focus(&mut config, "../work").is_err());
+
+        apply_focus(&mut config, "work").unwrap();
+        assert_eq!(config.root_dir, root.join("work"));
+        let mut names: Vec<String> = create_note_objects(&config)
+            .iter()
+            .map(|note| note.trunc_path.display().to_string().replace('\\', "/"))
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["acme/call.md", "plan.md"]);
+    }
+
+    #[test]
+    fn test_first_available_editor() {
+        let candidates = vec![
+            String::from("clife-no-such-editor-here --wait"),
+            String::from("sh -c true"),
+        ];
+        assert_eq!(
+            first_available_editor(&candidates),
+            Some(String::from("sh -c true"))
+        );
+        assert_eq!(first_available_editor(&candidates[..1]), None);
+        assert_eq!(
+            first_available_editor(&[String::from("/bin/sh")]),
+            Some(String::from("/bin/sh"))
+        );
+
+        let mut config = Config::default();
+        config
+            .apply_file("editor = [\"clife-no-such-editor-here\", \"sh\"]\n")
+            .unwrap();
+        assert_eq!(config.editor, ["clife-no-such-editor-here", "sh"]);
+        assert!(describe_config(&config).contains("editor = clife-no-such-editor-here, sh (file)"));
+    }
+
+    #[test]
+    fn test_rename_all_rolls_back_on_failure() {
+        let root = temp_root("rename_all_rolls_back_on_failure");
+        let config = Config {
+            root_dir: root.clone(),
+            sync_title_on_rename: true,
+            ..Default::default()
+        };
+        write_note(&root, "a.md", "---\ntitle: a\n---\n");
+        write_note(&root, "a2.md", "overwritten");
+        write_note(&root, "c.md", "");
+        let plan = vec![
+            (root.join("a.md"), root.join("a2.md")),
+            // Gone before the rename, so this one fails
+            (root.join("b.md"), root.join("b2.md")),
+            (root.join("c.md"), root.join("c2.md")),
+        ];
+
+        assert!(!rename_all(&config, &plan));
+        assert_eq!(
+            std::fs::read_to_string(root.join("a.md")).unwrap(),
+            "---\ntitle: a\n---\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("a2.md")).unwrap(),
+            "overwritten"
+        );
+        assert!(trash_entries(&config).is_empty());
+        assert!(root.join("c.md").is_file());
+        assert!(!root.join("c2.md").exists());
+
+        let mut transaction = Transaction::new();
+        transaction.push(String::from("undo a"), || Ok(()));
+        transaction.push(String::from("undo b"), || Err(io::Error::other("stuck")));
+        let failures = transaction.rollback();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "undo b");
+    }
+
+    #[test]
+    fn test_hooks_receive_the_note_path() {
+        let root = temp_root("hooks_receive_the_note_path");
+        let log = root.join("hook.log");
+        let hook = |event: &str| {
+            format!(
+                "f() {{ echo \"{} $1|$CLIFE_NOTE_PATH\" >> '{}'; }}; f",
+                event,
+                log.display()
+            )
+        };
+        let config = Config {
+            root_dir: root.clone(),
+            use_trash: false,
+            post_create_hook: Some(hook("create")),
+            post_delete_hook: Some(hook("delete")),
+            ..Default::default()
+        };
+
+        let note_path = create_note_from_template(&config, None, Some("my_note"), 1).unwrap();
+        let note = Note {
+            trunc_path: PathBuf::from("my_note.md"),
+            full_path: note_path.clone(),
+            root: root.clone(),
+        };
+        assert!(remove_note(&config, &note));
+
+        let path = note_path.display();
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            format!("create {}|{}\ndelete {}|{}\n", path, path, path, path)
+        );
+
+        let failing = Config {
+            root_dir: root.clone(),
+            post_create_hook: Some(String::from("false")),
+            ..Default::default()
+        };
+        assert!(create_new_note(&failing, &root, 1).is_ok());
+    }
+
+    #[test]
+    fn test_create_hook_sees_the_finished_note() {
+        let root = temp_root("create_hook_sees_the_finished_note");
+        let log = root.join("hook.log");
+        let config = Config {
+            root_dir: root.clone(),
+            post_create_hook: Some(format!("cat >> '{}'", log.display())),
+            ..Default::default()
+        };
+        write_note(&root, "templates/daily.md", "# {{title}}\n\n## Todo\n");
+        write_note(&root, "work/.template.md", "## Work\n");
+
+        create_note_from_template(&config, Some("daily"), None, 1).unwrap();
+        let mut input = io::Cursor::new("work\n");
+        prompt_and_create_note(&config, &mut input, 2).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "# new_note_1\n\n## Todo\n## Work\n"
+        );
+    }
+
+    #[test]
+    fn test_unknown_config_keys() {
+        let mut config = Config::default();
+        match config.apply_file("rootdir = \"~/notes\"\n") {
+            Err(AppError::Config(message)) => assert_eq!(
+                message,
+                "unknown setting 'rootdir', did you mean 'root_dir'?"
+            ),
+            _ => panic!("expected a config error"),
+        }
+        assert_eq!(
+            unknown_key_message("editor_by_extention.drawio"),
+            "unknown setting 'editor_by_extention.drawio', did you mean 'editor_by_extension.drawio'?"
+        );
+        assert_eq!(
+            unknown_key_message("favourite_colour"),
+            "unknown setting 'favourite_colour'"
+        );
+
+        let mut lenient = Config {
+            lenient_config: true,
+            ..Default::default()
+        };
+        lenient
+            .apply_file("rootdir = \"/elsewhere\"\nuse_trash = false\nseed_h1_title = true\n")
+            .unwrap();
+        assert!(!lenient.use_trash);
+        assert!(lenient.seed_h1_title);
+        assert_eq!(lenient.source("rootdir"), ConfigSource::Default);
+
+        // Every setting shown by `clife config` can be set in the file, bar the
+        // one that only `--force` sets
+        for line in describe_config(&Config::default()).lines().skip(1) {
+            let key = line.split(" = ").next().unwrap();
+            assert!(
+                key == "force_overwrite" || CONFIG_SETTINGS.iter().any(|(known, _)| *known == key),
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_symlinked_notes_are_listed_but_protected() {
+        let root = temp_root("symlinked_notes");
+        let shared = temp_root("symlinked_notes_shared");
+        write_note(&shared, "shared.md", "Shared\n");
+        write_note(&shared, "inner.md", "Inner\n");
+        write_note(&root, "own.md", "");
+        std::os::unix::fs::symlink(shared.join("shared.md"), root.join("shared.md")).unwrap();
+        std::os::unix::fs::symlink(&shared, root.join("linked_dir")).unwrap();
+        let config = Config {
+            root_dir: root.clone(),
+            use_trash: false,
+            ..Default::default()
+        };
+
+        let notes = create_note_objects(&config);
+        let mut listed: Vec<_> = notes.iter().map(|n| n.trunc_path.clone()).collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            [PathBuf::from("own.md"), PathBuf::from("shared.md")]
+        );
+
+        let link = notes
+            .iter()
+            .find(|n| n.trunc_path == Path::new("shared.md"))
+            .unwrap();
+        assert!(is_symlink(link));
+        assert!(!is_symlink(
+            notes
+                .iter()
+                .find(|n| n.trunc_path == Path::new("own.md"))
+                .unwrap()
+        ));
+        assert!(!remove_note(&config, link));
+        assert!(move_note(&config, link, Path::new("work"), None).is_err());
+        assert!(root.join("shared.md").exists());
+
+        let forced = Config {
+            root_dir: root.clone(),
+            override_locks: true,
+            ..Default::default()
+        };
+        assert!(check_writable(&forced, link).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_notes_are_only_edited_with_force() {
+        let root = temp_root("symlinked_notes_are_only_edited_with_force");
+        let shared = temp_root("symlinked_notes_are_only_edited_with_force_shared");
+        let contents = "# Shared  \n\n\n\nend";
+        write_note(&shared, "shared.md", contents);
+        write_note(&shared, "diagram.png", "png bytes");
+        std::os::unix::fs::symlink(shared.join("shared.md"), root.join("shared.md")).unwrap();
+        let config = Config {
+            root_dir: root.clone(),
+            ..Default::default()
+        };
+        let notes = create_note_objects(&config);
+        let link = &notes[0];
+
+        assert!(tag_note(&config, link, "imported").is_err());
+        assert!(attach_file(&config, link, &shared.join("diagram.png")).is_err());
+        lint_notes(&config, &notes, true);
+        assert_eq!(sync_project_fields(&config, &notes), 0);
+        assert_eq!(
+            std::fs::read_to_string(shared.join("shared.md")).unwrap(),
+            contents
+        );
+
+        // With --force the edit goes through the link to the shared note
+        let forced = Config {
+            override_locks: true,
+            ..config
+        };
+        assert!(tag_note(&forced, link, "imported").unwrap());
+        assert!(root
+            .join("shared.md")
+            .symlink_metadata()
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(std::fs::read_to_string(shared.join("shared.md"))
+            .unwrap()
+            .starts_with("---\ntags: [imported]\n---\n"));
+    }
+
+    #[test]
+    fn test_summary_counts_operations() {
+        let mut summary = Summary::default();
+        assert_eq!(summary.to_string(), "no notes changed");
+        for operation in [
+            Operation::Delete,
+            Operation::Create,
+            Operation::Move,
+            Operation::Delete,
+        ] {
+            summary.record(operation);
+        }
+        assert_eq!(summary.to_string(), "created 1 note, deleted 2, moved 1");
+
+        let mut summary = Summary::default();
+        summary.record(Operation::Rename);
+        summary.record(Operation::Rename);
+        assert_eq!(summary.to_string(), "renamed 2 notes");
     }
 }